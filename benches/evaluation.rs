@@ -1,12 +1,11 @@
 use aaltofunktionromautus::{
     backtracking::{gradual_reset::BacktrackerByGradualReset, reset::BacktrackerByReset},
-    grid::{constant_2d::ConstantSizeGrid2D, dynamic_2d::DynamicSizeGrid2D},
-    utils::space::s2d::Location2D,
+    grid::dynamic_2d::DynamicSizeGrid2D,
     wave_function_collapse::interface::WaveFunctionCollapse,
 };
 use criterion::{Criterion, black_box};
 use criterion::{criterion_group, criterion_main};
-use rand::{Rng, thread_rng};
+use rand::{Rng, rng};
 
 fn eval_terrain_simple(size: usize) {
     let rules = aaltofunktionromautus::rules::samples::terrain_simple::rules();
@@ -25,7 +24,7 @@ fn eval_terrain(size: usize) {
 fn eval_flowers(size: usize) {
     let rules = aaltofunktionromautus::rules::samples::flowers_singlepixel::rules();
 
-    let mut rng = thread_rng();
+    let mut rng = rng();
     let mut grid = DynamicSizeGrid2D::new(size, size, rules, black_box(rng.random()));
     let _ = grid.run::<BacktrackerByReset>(size * size, None);
 }
@@ -33,18 +32,18 @@ fn eval_flowers(size: usize) {
 fn eval_flowers_reset(size: usize) {
     let rules = aaltofunktionromautus::rules::samples::flowers_singlepixel::rules();
 
-    let mut rng = thread_rng();
+    let mut rng = rng();
     let mut grid = DynamicSizeGrid2D::new(size, size, rules, black_box(rng.random()));
-    let mut b = BacktrackerByGradualReset::new(1);
+    let b = BacktrackerByGradualReset::new(1);
     let _ = grid.run(size * size, Some(b));
 }
 
 fn eval_flowers_reset_gradual(size: usize) {
     let rules = aaltofunktionromautus::rules::samples::flowers_singlepixel::rules();
 
-    let mut rng = thread_rng();
+    let mut rng = rng();
     let mut grid = DynamicSizeGrid2D::new(size, size, rules, black_box(rng.random()));
-    let mut b = BacktrackerByReset {};
+    let b = BacktrackerByReset {};
     let _ = grid.run(size * size, Some(b));
 }
 
@@ -1,9 +1,14 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
 use aaltofunktionromautus::{
-    grid::{constant_2d::ConstantSizeGrid2D, dynamic_2d::DynamicSizeGrid2D},
-    utils::space::s2d::Location2D,
-    wave_function_collapse::interface::WaveFunctionCollapse,
+    backtracking::reset::BacktrackerByReset,
+    grid::dynamic_2d::DynamicSizeGrid2D,
+    rules::RuleSet2D,
+    tile::{Tile, TileState, bitset::BitsetTile, interface::TileInterface},
+    utils::space::s2d::{Direction2D, Location2D},
+    wave_function_collapse::{interface::WaveFunctionCollapse, propagation::PropagationStrategy},
 };
-use criterion::{Criterion, black_box};
+use criterion::{BatchSize, Criterion, black_box};
 use criterion::{criterion_group, criterion_main};
 
 fn propagate_checkers(size: usize) {
@@ -17,6 +22,86 @@ fn propagate_checkers(size: usize) {
     );
 }
 
+fn eval_village<T: TileInterface<TileState> + Clone + PartialEq>(
+    size: usize,
+    propagation: PropagationStrategy,
+) {
+    let rules: RuleSet2D = serde_json::from_str(include_str!("../samples/rules/village.json"))
+        .expect("failed to parse prebuilt rules.json");
+
+    let mut grid =
+        DynamicSizeGrid2D::<T>::new_with_propagation(size, size, rules, black_box(0), propagation);
+    let _ = grid.run(size * size, Some(BacktrackerByReset {}));
+}
+
+/// `count` states, each of them may only be next to states that are at most `spread` away
+fn gradient_rules(count: TileState, spread: TileState) -> RuleSet2D {
+    let mut allowed = HashSet::new();
+    for a in 0..count {
+        for b in a.saturating_sub(spread)..(a + spread + 1).min(count) {
+            for direction in [
+                Direction2D::UP,
+                Direction2D::RIGHT,
+                Direction2D::DOWN,
+                Direction2D::LEFT,
+            ] {
+                allowed.insert((a, direction, b));
+            }
+        }
+    }
+    RuleSet2D::new(
+        BTreeSet::from_iter(0..count),
+        allowed,
+        HashMap::new(),
+        HashMap::new(),
+        BTreeMap::new(),
+    )
+}
+
+/// With thousands of states, pairwise checks have to combine a long bitmask for every state of
+/// the source, while support counts only touch the counters of the states that changed
+fn eval_gradient(mut grid: DynamicSizeGrid2D<BitsetTile>) {
+    let size = grid.width;
+    let _ = grid.run(size * size, None::<BacktrackerByReset>);
+}
+
+pub fn benchmark_propagation_strategies(c: &mut Criterion) {
+    for propagation in [
+        PropagationStrategy::Pairwise,
+        PropagationStrategy::SupportCount,
+    ] {
+        for size in [16, 32] {
+            c.bench_function(&format!("village {propagation:?} {size}"), |b| {
+                b.iter(|| {
+                    eval_village::<Tile>(black_box(size), propagation);
+                })
+            });
+            c.bench_function(&format!("village {propagation:?} bitset {size}"), |b| {
+                b.iter(|| {
+                    eval_village::<BitsetTile>(black_box(size), propagation);
+                })
+            });
+        }
+        let rules = gradient_rules(2048, 8);
+        c.bench_function(&format!("gradient {propagation:?} 2048 states"), |b| {
+            // only the run is measured, compiling the rules takes a while
+            b.iter_batched(
+                || {
+                    DynamicSizeGrid2D::<BitsetTile>::new_with_propagation(
+                        16,
+                        16,
+                        rules.clone(),
+                        0,
+                        propagation,
+                    )
+                },
+                eval_gradient,
+                BatchSize::LargeInput,
+            )
+        });
+    }
+}
+
 pub fn benchmark_propagation(c: &mut Criterion) {
     c.bench_function("propagate 10", |b| {
         b.iter(|| {
@@ -50,5 +135,9 @@ pub fn benchmark_propagation(c: &mut Criterion) {
     });
 }
 
-criterion_group!(prop, benchmark_propagation);
+criterion_group!(
+    prop,
+    benchmark_propagation,
+    benchmark_propagation_strategies
+);
 criterion_main!(prop);
@@ -140,6 +140,8 @@ const App: Component = () => {
               <option value="">no backtracking</option>
               <option value="0">reset</option>
               <option value="1">gradual reset</option>
+              <option value="2">undo</option>
+              <option value="3">backjumping</option>
             </select>
           </label>
           <details style={{ background: "#1D2021", padding: "0.2rem 0.1rem" }}>
@@ -5,9 +5,7 @@ import initSync, {
   TileState,
   Direction2D,
   TileVisual,
-  Backtracker2D,
   BacktrackerVariant,
-  new_backtracker,
   OverlappingBitmapExtractorOptions,
 } from "aaltofunktionromautus";
 import { CustomRule, pickRandomSeed, Seed } from "./utils";
@@ -152,14 +150,6 @@ function getRules(ruleset: InbuiltRuleSet | CustomRule) {
   return rules;
 }
 
-function getBacktracker(variant: BacktrackerVariant | null) {
-  let backtracker = null;
-  if (variant !== null) {
-    backtracker = new_backtracker(variant);
-  }
-  return backtracker;
-}
-
 let initPromise: Promise<void>;
 async function initWasm() {
   await initSync();
@@ -187,12 +177,12 @@ async function reset(
     dimensions.height,
   );
   console.timeEnd("reset");
-  const backtracker = getBacktracker(backtrackerVariant);
+  // kept by the grid, so undoing can go back past earlier ticks
+  grid.set_backtracker(backtrackerVariant ?? undefined);
   persistent_state = {
     grid,
     seed,
     history_cache: new Map(),
-    tickBacktracker: backtracker,
     customRules,
   };
   console.info({ persistent_state });
@@ -202,7 +192,6 @@ async function reset(
 type PersistentState = {
   grid: Grid;
   // tileset: TileVisual[];
-  tickBacktracker: Backtracker2D | null;
   seed: number;
   history_cache: Map<number, State>;
   customRules: CustomRule[];
@@ -366,7 +355,7 @@ async function tick(data: WorkerRequest, s: PersistentStateUpdate) {
     throw new Error("Unexpected message type");
   }
   console.time("tick");
-  s.grid.tick(s.tickBacktracker);
+  s.grid.tick();
   console.timeEnd("tick");
   const resp: WorkerResponse = {
     type: "state_update",
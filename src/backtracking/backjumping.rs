@@ -0,0 +1,492 @@
+//! Conflict-directed backjumping
+//!
+//! Like `BacktrackingByUndo`, every decision is recorded along with the tiles it changed. Each
+//! change also remembers why it happened: a decision, a ban made while backtracking, or
+//! propagation from the neighbouring tiles. When a contradiction happens, the changes are traced
+//! back from the contradicting tile to find the decisions that actually led to it (the conflict
+//! set). The backtracker then jumps straight back to the most recent of them, undoing every
+//! unrelated decision made after it in one go.
+//!
+//! The chosen state is banned from the decision's tile. The ban is only valid as long as the rest
+//! of the conflict set stands, so it is attributed to those decisions when tracing later
+//! conflicts.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+    wave_function_collapse::{
+        interface::{TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+        propagate_from_tile,
+    },
+};
+
+use super::{Backtracker, ChangeTracker, reapply_masks};
+
+/// Why a tile lost some of its states
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Cause {
+    /// The tile was collapsed by the decision
+    Decision,
+    /// A state was banned while backtracking, valid as long as the listed decisions stand
+    Ban(Vec<usize>),
+    /// The neighbouring tiles lost states
+    Propagation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Change<TPosition> {
+    position: TPosition,
+    /// Possible states of the tile before the change
+    previous: Vec<TileState>,
+    cause: Cause,
+}
+
+/// A decision and every change made to the grid while it was the latest one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Frame<TPosition> {
+    position: TPosition,
+    state: TileState,
+    changes: Vec<Change<TPosition>>,
+}
+
+/// States are stored as plain lists, so the same backtracker works with any tile implementation
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BacktrackerByBackjumping<TPosition: Location> {
+    /// Possible states of every tile, as of the last change recorded
+    snapshot: HashMap<TPosition, Vec<TileState>>,
+    /// Tiles that might differ from the snapshot
+    changes: ChangeTracker<TPosition>,
+    /// Decisions in the order they were made
+    frames: Vec<Frame<TPosition>>,
+    /// tile -> (frame, index into its changes) of every recorded change, in the order they were
+    /// made
+    history: HashMap<TPosition, Vec<(usize, usize)>>,
+}
+
+impl<TPosition: Location> BacktrackerByBackjumping<TPosition> {
+    pub fn new() -> Self {
+        Self {
+            snapshot: HashMap::new(),
+            changes: ChangeTracker::new(),
+            frames: Vec::new(),
+            history: HashMap::new(),
+        }
+    }
+
+    /// Amount of decisions that can still be undone
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Brings the snapshot up to date with the grid. The changes are attributed to the latest
+    /// decision, if there is one.
+    fn record<
+        const N: usize,
+        TDirection: Direction<N>,
+        T: TileInterface<TileState>,
+        TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+    >(
+        &mut self,
+        grid: &TGrid,
+        cause: impl Fn(TPosition) -> Cause,
+    ) {
+        let mut changes = Vec::new();
+        for position in self.changes.take_dirty(grid) {
+            let Some(tile) = grid.get_tile(position) else {
+                continue;
+            };
+            let current: Vec<_> = tile.possible_states().collect();
+            match self.snapshot.get_mut(&position) {
+                Some(previous) if *previous != current => {
+                    changes.push(Change {
+                        position,
+                        previous: std::mem::replace(previous, current),
+                        cause: cause(position),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    self.snapshot.insert(position, current);
+                }
+            }
+        }
+        self.changes.compared(grid);
+        let depth = self.frames.len().wrapping_sub(1);
+        if let Some(frame) = self.frames.last_mut() {
+            for change in changes {
+                self.history
+                    .entry(change.position)
+                    .or_default()
+                    .push((depth, frame.changes.len()));
+                frame.changes.push(change);
+            }
+        }
+    }
+
+    /// Undoes every decision from `depth` onwards, returns the earliest one undone
+    fn rewind<
+        const N: usize,
+        TDirection: Direction<N>,
+        T: TileInterface<TileState>,
+        TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+    >(
+        &mut self,
+        grid: &mut TGrid,
+        depth: usize,
+    ) -> Option<Frame<TPosition>> {
+        let mut earliest = None;
+        while self.frames.len() > depth {
+            let frame = self.frames.pop()?;
+            for change in frame.changes.iter().rev() {
+                self.snapshot
+                    .insert(change.position, change.previous.clone());
+                self.changes.push_pending(change.position);
+                if let Some(history) = self.history.get_mut(&change.position) {
+                    history.pop();
+                }
+            }
+            earliest = Some(frame);
+        }
+
+        for position in self.changes.take_dirty(grid) {
+            let Some(states) = self.snapshot.get(&position) else {
+                continue;
+            };
+            let differs = grid
+                .get_tile(position)
+                .is_some_and(|tile| !tile.possible_states().eq(states.iter().cloned()));
+            if differs {
+                grid.with_tile(position, |tile, _| {
+                    tile.set_possible_states(states.iter().cloned());
+                });
+            }
+        }
+        self.changes.compared(grid);
+        earliest
+    }
+
+    /// Decisions that led to the contradiction at the given tile.
+    ///
+    /// A tile loses states during propagation because its neighbours lost states earlier, so
+    /// the changes are followed backwards in time through the neighbours until they end up at
+    /// decisions or bans.
+    fn conflict_set<
+        const N: usize,
+        TDirection: Direction<N>,
+        T: TileInterface<TileState>,
+        TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+    >(
+        &self,
+        grid: &TGrid,
+        contradiction_location: TPosition,
+    ) -> BTreeSet<usize> {
+        if grid.get_state_counts().is_some()
+            || grid.get_connectivity().is_some()
+            || grid.get_symmetry().is_some()
+        {
+            // global constraints ban and force states anywhere in the grid, which can't be traced
+            // through the neighbours, so every decision has to be considered
+            return (0..self.frames.len()).collect();
+        }
+
+        let mut conflict = BTreeSet::new();
+        // latest point in time each tile has been looked at
+        let mut visited: HashMap<TPosition, usize> = HashMap::new();
+        // propagation stops before emptying the contradicting tile, so its neighbours are
+        // looked at as they are now
+        let now = self.frames.len();
+        let mut stack = vec![(contradiction_location, now)];
+        for (_, neighbour) in grid.get_neighbours(contradiction_location) {
+            if let Some(neighbour) = neighbour {
+                stack.push((neighbour, now));
+            }
+        }
+        while let Some((position, until)) = stack.pop() {
+            if visited.get(&position).is_some_and(|seen| *seen >= until) {
+                continue;
+            }
+            visited.insert(position, until);
+
+            let Some(changes) = self.history.get(&position) else {
+                continue;
+            };
+            for (depth, index) in changes.iter().filter(|(depth, _)| *depth <= until) {
+                let through_neighbours = match &self.frames[*depth].changes[*index].cause {
+                    Cause::Decision => {
+                        conflict.insert(*depth);
+                        false
+                    }
+                    Cause::Ban(decisions) => {
+                        conflict.extend(decisions.iter().cloned());
+                        false
+                    }
+                    Cause::Propagation => true,
+                };
+                if through_neighbours {
+                    for (_, neighbour) in grid.get_neighbours(position) {
+                        if let Some(neighbour) = neighbour {
+                            stack.push((neighbour, *depth));
+                        }
+                    }
+                }
+            }
+        }
+        conflict
+    }
+}
+
+impl<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState> + Clone,
+    TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+> Backtracker<N, TileState, TPosition, TDirection, T, TGrid>
+    for BacktrackerByBackjumping<TPosition>
+{
+    /// Collapses the tile like `WaveFunctionCollapse::collapse`, but
+    /// remembers the decision and the changes it causes
+    fn tick(
+        &mut self,
+        grid: &mut TGrid,
+        position: TPosition,
+        state: TileState,
+    ) -> TickResult<TPosition> {
+        // anything that happened outside of the backtracker is treated like propagation
+        self.record(grid, |_| Cause::Propagation);
+        self.frames.push(Frame {
+            position,
+            state,
+            changes: Vec::new(),
+        });
+
+        let result = grid.collapse(position, Some(state));
+        self.record(grid, |changed| {
+            if changed == position {
+                Cause::Decision
+            } else {
+                Cause::Propagation
+            }
+        });
+        result
+    }
+
+    fn contradiction_handler(
+        &mut self,
+        grid: &mut TGrid,
+        contradiction_location: TPosition,
+    ) -> TickResult<TPosition> {
+        let mut location = contradiction_location;
+        loop {
+            self.record(grid, |_| Cause::Propagation);
+            let mut conflict = self.conflict_set(grid, location);
+            // without decisions to blame, the grid can't be collapsed
+            let depth = conflict
+                .pop_last()
+                .ok_or(WaveFunctionCollapseInterruption::Contradiction(location))?;
+            let decision = self
+                .rewind(grid, depth)
+                .ok_or(WaveFunctionCollapseInterruption::Contradiction(location))?;
+            // masks hold regardless of the decisions
+            let mut queue = reapply_masks(grid);
+            self.record(grid, |_| Cause::Ban(Vec::new()));
+
+            let remaining = grid
+                .with_tile(decision.position, |tile, _| {
+                    tile.retain(|state| *state != decision.state);
+                    tile.possible_states_ref().count()
+                })
+                .unwrap_or_default();
+            // the ban is recorded on its own, anything the tile loses after it is propagation
+            let reasons: Vec<_> = conflict.into_iter().collect();
+            self.record(grid, |_| Cause::Ban(reasons.clone()));
+
+            let result = if remaining == 0 {
+                Err(WaveFunctionCollapseInterruption::Contradiction(
+                    decision.position,
+                ))
+            } else {
+                queue.extend(propagate_from_tile(grid, decision.position));
+                grid.propagate(queue)
+            };
+
+            self.record(grid, |_| Cause::Propagation);
+
+            match result {
+                Err(WaveFunctionCollapseInterruption::Contradiction(next)) => location = next,
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D, tests::any_combination_grid},
+        selection::entropy::MinimumEntropy,
+        tile::Tile,
+        utils::space::s2d::{Location2D, Vector2D},
+        value_selection::{ValueSelector, weighted::WeightedRandom},
+    };
+
+    use super::*;
+
+    /// Decides the tile with the lowest entropy, like `run` does by default
+    fn tick(
+        b: &mut BacktrackerByBackjumping<Location2D>,
+        grid: &mut DynamicSizeGrid2D<Tile>,
+    ) -> TickResult<Location2D> {
+        let position = grid.get_lowest_entropy_position().unwrap();
+        let value = WeightedRandom::default()
+            .select_value(grid, position)
+            .unwrap();
+        b.tick(grid, position, value)
+    }
+
+    fn states_at(grid: &DynamicSizeGrid2D<Tile>, location: Location2D) -> Vec<TileState> {
+        grid.get_tile(location).unwrap().possible_states().collect()
+    }
+
+    #[test]
+    fn jumps_over_unrelated_decisions() {
+        let mut grid = any_combination_grid(5, 1, 0..2);
+        let mut b = BacktrackerByBackjumping::new();
+        b.record(&grid, |_| Cause::Propagation);
+        // decide the tiles by hand, so the later decisions are far away from the first one
+        for x in [0, 4, 3] {
+            let position = Location2D { x, y: 0 };
+            b.frames.push(Frame {
+                position,
+                state: 0,
+                changes: Vec::new(),
+            });
+            grid.with_tile(position, |t, _| t.set_possible_states([0]));
+            b.record(&grid, |_| Cause::Decision);
+        }
+
+        let first = Location2D { x: 0, y: 0 };
+        assert_eq!(b.conflict_set(&grid, first), BTreeSet::from([0]));
+        b.contradiction_handler(&mut grid, first)
+            .expect("contradiction should've resolved");
+        assert_eq!(b.depth(), 0);
+        for location in grid.positions() {
+            let expected = if location == first {
+                vec![1]
+            } else {
+                vec![0, 1]
+            };
+            assert_eq!(states_at(&grid, location), expected, "{location:?}");
+        }
+    }
+
+    #[test]
+    fn conflicts_are_traced_through_propagation() {
+        // neighbours have to differ, so every decision is felt across the whole row
+        let rules = crate::rules::samples::checkers::rules();
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(4, 1, rules, 0);
+        let mut b = BacktrackerByBackjumping::new();
+        tick(&mut b, &mut grid).unwrap();
+        assert_eq!(b.depth(), 1);
+
+        let position = grid
+            .positions()
+            .find(|location| *location != b.frames[0].position)
+            .unwrap();
+        assert_eq!(
+            b.conflict_set(&grid, position),
+            BTreeSet::from([0]),
+            "the first decision decided every tile"
+        );
+    }
+
+    #[test]
+    fn bans_remember_their_reasons() {
+        let mut grid = any_combination_grid(5, 1, 0..3);
+        let mut b = BacktrackerByBackjumping::new();
+        tick(&mut b, &mut grid).unwrap();
+        tick(&mut b, &mut grid).unwrap();
+        let decided: Vec<_> = b.frames.iter().map(|frame| frame.position).collect();
+        // next to the second decision, so the first one is left after jumping back
+        let undecided = grid
+            .get_neighbours(decided[1])
+            .into_iter()
+            .filter_map(|(_, neighbour)| neighbour)
+            .find(|location| !decided.contains(location))
+            .unwrap();
+
+        grid.with_tile(undecided, |t, _| t.set_possible_states([]));
+        b.record(&grid, |_| Cause::Propagation);
+        let mut conflict = b.conflict_set(&grid, undecided);
+        let latest = conflict.pop_last().unwrap();
+        assert_eq!(latest, 1);
+        let banned = b.frames[latest].clone();
+
+        b.contradiction_handler(&mut grid, undecided).unwrap();
+        assert_eq!(b.depth(), latest);
+        // the history of the undone decisions is dropped along with them
+        assert!(b.history.values().flatten().all(
+            |(depth, index)| *depth < b.frames.len() && b.frames[*depth].changes.len() > *index
+        ));
+        assert!(!states_at(&grid, banned.position).contains(&banned.state));
+        let frame = b.frames.last().expect("an earlier decision should be left");
+        let ban = frame
+            .changes
+            .iter()
+            .find(|change| change.position == banned.position)
+            .expect("the ban should be recorded");
+        assert_eq!(ban.cause, Cause::Ban(conflict.into_iter().collect()));
+    }
+
+    #[test]
+    fn conflicts_are_kept_between_runs() {
+        let mut grid = any_combination_grid(5, 1, 0..3);
+        let mut b = BacktrackerByBackjumping::new();
+        for _ in 0..2 {
+            let result = grid.run_with_heuristics(
+                1,
+                Some(&mut b),
+                MinimumEntropy,
+                WeightedRandom::default(),
+            );
+            assert_eq!(
+                result,
+                Err(WaveFunctionCollapseInterruption::MaxIterationsReached)
+            );
+        }
+        assert_eq!(b.depth(), 2);
+
+        // the decisions of both runs are still known, and each tile is blamed on its own one
+        let decided: Vec<_> = b.frames.iter().map(|frame| frame.position).collect();
+        assert_eq!(b.conflict_set(&grid, decided[0]), BTreeSet::from([0]));
+        assert_eq!(b.conflict_set(&grid, decided[1]), BTreeSet::from([1]));
+    }
+
+    #[test]
+    fn exhausted_search_is_a_contradiction() {
+        let rules = crate::rules::samples::checkers::rules();
+        let mut grid = DynamicSizeGrid2D::<Tile>::new_periodic(
+            3,
+            2,
+            rules,
+            0,
+            Default::default(),
+            Vector2D { x: true, y: false },
+        );
+        let mut b = BacktrackerByBackjumping::new();
+        let Err(WaveFunctionCollapseInterruption::Contradiction(location)) =
+            tick(&mut b, &mut grid)
+        else {
+            panic!("the first decision should fail");
+        };
+        assert!(matches!(
+            b.contradiction_handler(&mut grid, location),
+            Err(WaveFunctionCollapseInterruption::Contradiction(_))
+        ));
+        assert_eq!(b.depth(), 0);
+    }
+}
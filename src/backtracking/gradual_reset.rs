@@ -90,19 +90,26 @@ impl<
         }
 
         let rules_possible = grid.get_rules().possible.clone();
+        // tiles with a mask only get the states it allows back
+        let initial_states = |grid: &TGrid, location| match grid.get_state_masks() {
+            Some(masks) => masks.allowed(location, &rules_possible),
+            None => rules_possible.clone(),
+        };
 
         // locations in the radius will be reset
         for location in &locations_in_radius {
+            let states = initial_states(grid, *location);
             grid.with_tile(*location, |t, _| {
-                t.set_possible_states(rules_possible.clone());
+                t.set_possible_states(states.clone());
             });
         }
 
         let mut propagation_queue = VecDeque::new();
         for border_location in locations_neighboring_radius {
             // the bordering tiles will have their possible states recalculated
+            let states = initial_states(grid, border_location);
             grid.with_tile(border_location, |t, _| {
-                t.set_possible_states(rules_possible.clone());
+                t.set_possible_states(states.clone());
             });
         }
 
@@ -137,12 +144,13 @@ mod tests {
     use crate::{
         grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D},
         rules::RuleSet2D,
+        tile::Tile,
         utils::space::s2d::{Direction2D, Location2D},
     };
 
     use super::*;
 
-    fn gen_grid(target: Location2D) -> DynamicSizeGrid2D {
+    fn gen_grid(target: Location2D) -> DynamicSizeGrid2D<Tile> {
         const STATE_A: TileState = 0;
         const STATE_B: TileState = 1;
 
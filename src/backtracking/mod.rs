@@ -1,13 +1,20 @@
+pub mod backjumping;
 pub mod gradual_reset;
 pub mod reset;
+pub mod undo;
 
-use std::hash::Hash;
+use std::{collections::VecDeque, hash::Hash};
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    tile::interface::TileInterface,
+    tile::{TileState, interface::TileInterface},
     utils::space::{Direction, Location},
-    wave_function_collapse::interface::{
-        TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption,
+    wave_function_collapse::{
+        interface::{
+            PropagateQueueEntry, TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption,
+        },
+        propagate_from_tile,
     },
 };
 
@@ -20,6 +27,21 @@ pub trait Backtracker<
     TGrid: WaveFunctionCollapse<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T>,
 >
 {
+    /// Collapses the tile picked by the selection heuristic into the state picked by the value
+    /// selector, `run` calls this instead of `WaveFunctionCollapse::collapse` when a backtracker
+    /// is present.
+    ///
+    /// Backtrackers that need to remember the decisions can override it, see
+    /// `BacktrackingByUndo`.
+    fn tick(
+        &mut self,
+        grid: &mut TGrid,
+        position: TPosition,
+        value: TState,
+    ) -> TickResult<TPosition> {
+        grid.collapse(position, Some(value))
+    }
+
     /// Returns a closure that handles contradictions.
     fn contradiction_handler(
         &mut self,
@@ -45,11 +67,143 @@ pub trait Backtracker<
                 Err(WaveFunctionCollapseInterruption::Contradiction(p)) => {
                     result = self.contradiction_handler(grid, p)
                 }
-                Err(WaveFunctionCollapseInterruption::MaxIterationsReached) => {
-                    return Err(WaveFunctionCollapseInterruption::MaxIterationsReached);
-                }
+                Err(
+                    error @ (WaveFunctionCollapseInterruption::MaxIterationsReached
+                    | WaveFunctionCollapseInterruption::InvalidCountConstraint(_)),
+                ) => return Err(error),
             }
             tries += 1;
         }
     }
 }
+
+/// Allows reusing the same backtracker, along with the decisions it remembers, across multiple
+/// runs
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+    B: Backtracker<N, TState, TPosition, TDirection, T, TGrid>,
+> Backtracker<N, TState, TPosition, TDirection, T, TGrid> for &mut B
+{
+    fn tick(
+        &mut self,
+        grid: &mut TGrid,
+        position: TPosition,
+        value: TState,
+    ) -> TickResult<TPosition> {
+        (**self).tick(grid, position, value)
+    }
+
+    fn contradiction_handler(
+        &mut self,
+        grid: &mut TGrid,
+        contradiction_location: TPosition,
+    ) -> TickResult<TPosition> {
+        (**self).contradiction_handler(grid, contradiction_location)
+    }
+}
+
+/// Restricts the masked tiles of the grid to their masks again, after they have been rewound to
+/// an earlier state. Masks and pins added in the middle of a run would be lost otherwise.
+///
+/// Returns the propagation caused by the changes.
+pub(crate) fn reapply_masks<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState>,
+    TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+>(
+    grid: &mut TGrid,
+) -> VecDeque<PropagateQueueEntry<TPosition>> {
+    let masks: Vec<_> = match grid.get_state_masks() {
+        Some(masks) => masks
+            .iter()
+            .map(|(position, mask)| (*position, mask.clone()))
+            .collect(),
+        None => return VecDeque::new(),
+    };
+    let mut queue = VecDeque::new();
+    for (position, mask) in masks {
+        let changed = grid.with_tile(position, |tile, _| {
+            tile.retain(|state| mask.contains(state))
+        });
+        if changed == Some(true) {
+            queue.extend(propagate_from_tile(grid, position));
+        }
+    }
+    queue
+}
+
+/// Finds the tiles that may have changed since a backtracker last compared its copy of the grid
+/// with it, using `GridInterface::get_update_log`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct ChangeTracker<TPosition> {
+    /// Length of the update log at the last comparison, only tiles updated after that can differ
+    pub(crate) logged: Option<usize>,
+    /// Reset count of the grid at the last comparison, see `GridInterface::get_reset_count`
+    resets: usize,
+    /// Tiles whose copy was changed by the backtracker itself, without going through the grid
+    pending: Vec<TPosition>,
+}
+
+impl<TPosition: Location> ChangeTracker<TPosition> {
+    pub(crate) fn new() -> Self {
+        Self {
+            logged: None,
+            resets: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Marks a tile whose copy no longer matches the grid
+    pub(crate) fn push_pending(&mut self, position: TPosition) {
+        self.pending.push(position);
+    }
+
+    /// Returns the positions that might differ from the copy, which are all of them if the grid
+    /// doesn't keep an update log or has been reset since. Positions can be listed more than
+    /// once.
+    ///
+    /// The copy has to be brought up to date with them, and `compared` called afterwards.
+    pub(crate) fn take_dirty<
+        const N: usize,
+        TState: Hash + Eq + Copy,
+        TDirection: Direction<N>,
+        T: TileInterface<TState>,
+        TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+    >(
+        &mut self,
+        grid: &TGrid,
+    ) -> Vec<TPosition> {
+        let mut dirty = std::mem::take(&mut self.pending);
+        match (grid.get_update_log(), self.logged) {
+            (Some(log), Some(logged))
+                if logged <= log.len() && grid.get_reset_count() == self.resets =>
+            {
+                dirty.extend(log[logged..].iter().map(|(position, _)| *position));
+            }
+            _ => dirty.extend(grid.positions()),
+        }
+        dirty
+    }
+
+    /// Remembers that the copy matches the grid as of now
+    pub(crate) fn compared<
+        const N: usize,
+        TState: Hash + Eq + Copy,
+        TDirection: Direction<N>,
+        T: TileInterface<TState>,
+        TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+    >(
+        &mut self,
+        grid: &TGrid,
+    ) {
+        self.logged = grid.get_update_log().map(<[_]>::len);
+        self.resets = grid.get_reset_count();
+    }
+}
@@ -39,13 +39,13 @@ mod tests {
     use crate::{
         grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D},
         rules::RuleSet2D,
-        tile::TileState,
+        tile::{Tile, TileState},
         utils::space::s2d::{Direction2D, Location2D},
     };
 
     use super::*;
 
-    fn gen_grid(target: Location2D) -> DynamicSizeGrid2D {
+    fn gen_grid(target: Location2D) -> DynamicSizeGrid2D<Tile> {
         const STATE_A: TileState = 0;
         const STATE_B: TileState = 1;
 
@@ -1,6 +1,338 @@
-use crate::{tile::Tile, utils::space::Location2D};
+//! Chronological (depth-first) backtracking
+//!
+//! Every collapse decision is recorded together with the tiles it changed. On a contradiction the
+//! grid is rewound to right before the latest decision, and the chosen state is banned from that
+//! tile. If that leaves the tile without states, or the ban itself leads to a contradiction, the
+//! decision before it is undone as well, and so on.
+//!
+//! Unlike resetting, this explores the possible outputs exhaustively, so a contradiction is only
+//! returned when the grid can't be collapsed at all.
 
-pub struct BacktrackingByUndo {
-    /// Keeps history of tile modifications that we can undo and then ban
-    pub update_log: Vec<(Location2D, Tile)>,
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+    wave_function_collapse::{
+        interface::{TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+        propagate_from_tile,
+    },
+};
+
+use super::{Backtracker, ChangeTracker, reapply_masks};
+
+/// A collapse made by the backtracker, along with what is needed to undo it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Decision<TPosition> {
+    position: TPosition,
+    state: TileState,
+    /// Tiles that changed between the previous decision and this one, with their possible states
+    /// from right before the previous decision
+    previous: Vec<(TPosition, Vec<TileState>)>,
+}
+
+/// States are stored as plain lists, so the same backtracker works with any tile implementation
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BacktrackingByUndo<TPosition: Location> {
+    /// Possible states of every tile right before the latest decision
+    snapshot: HashMap<TPosition, Vec<TileState>>,
+    /// Tiles that might differ from the snapshot
+    changes: ChangeTracker<TPosition>,
+    /// Decisions in the order they were made
+    decisions: Vec<Decision<TPosition>>,
+}
+
+impl<TPosition: Location> BacktrackingByUndo<TPosition> {
+    pub fn new() -> Self {
+        Self {
+            snapshot: HashMap::new(),
+            changes: ChangeTracker::new(),
+            decisions: Vec::new(),
+        }
+    }
+
+    /// Amount of decisions that can still be undone
+    pub fn depth(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// Updates the snapshot to match the grid, returns the tiles that changed along with their
+    /// previous states
+    fn take_snapshot<
+        const N: usize,
+        TDirection: Direction<N>,
+        T: TileInterface<TileState>,
+        TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+    >(
+        &mut self,
+        grid: &TGrid,
+    ) -> Vec<(TPosition, Vec<TileState>)> {
+        let mut changed = Vec::new();
+        for position in self.changes.take_dirty(grid) {
+            let Some(tile) = grid.get_tile(position) else {
+                continue;
+            };
+            let current: Vec<_> = tile.possible_states().collect();
+            match self.snapshot.get_mut(&position) {
+                Some(previous) if *previous != current => {
+                    changed.push((position, std::mem::replace(previous, current)));
+                }
+                Some(_) => {}
+                None => {
+                    self.snapshot.insert(position, current);
+                }
+            }
+        }
+        self.changes.compared(grid);
+        changed
+    }
+
+    /// Rewinds every tile of the grid that differs from the snapshot
+    fn restore_snapshot<
+        const N: usize,
+        TDirection: Direction<N>,
+        T: TileInterface<TileState>,
+        TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+    >(
+        &mut self,
+        grid: &mut TGrid,
+    ) {
+        for position in self.changes.take_dirty(grid) {
+            let Some(states) = self.snapshot.get(&position) else {
+                continue;
+            };
+            let differs = grid
+                .get_tile(position)
+                .is_some_and(|tile| !tile.possible_states().eq(states.iter().cloned()));
+            if differs {
+                grid.with_tile(position, |tile, _| {
+                    tile.set_possible_states(states.iter().cloned());
+                });
+            }
+        }
+        self.changes.compared(grid);
+    }
+}
+
+impl<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState> + Clone,
+    TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+> Backtracker<N, TileState, TPosition, TDirection, T, TGrid> for BacktrackingByUndo<TPosition>
+{
+    /// Collapses the tile like `WaveFunctionCollapse::collapse`, but
+    /// remembers the decision
+    fn tick(
+        &mut self,
+        grid: &mut TGrid,
+        position: TPosition,
+        state: TileState,
+    ) -> TickResult<TPosition> {
+        let previous = self.take_snapshot(grid);
+        self.decisions.push(Decision {
+            position,
+            state,
+            previous,
+        });
+
+        grid.collapse(position, Some(state))
+    }
+
+    fn contradiction_handler(
+        &mut self,
+        grid: &mut TGrid,
+        contradiction_location: TPosition,
+    ) -> TickResult<TPosition> {
+        while let Some(decision) = self.decisions.pop() {
+            // back to right before the decision
+            self.restore_snapshot(grid);
+            // the snapshot now has to describe the grid before the decision preceding it
+            for (position, states) in decision.previous {
+                self.snapshot.insert(position, states);
+                self.changes.push_pending(position);
+            }
+            let mut queue = reapply_masks(grid);
+
+            let remaining = grid
+                .with_tile(decision.position, |tile, _| {
+                    tile.retain(|state| *state != decision.state);
+                    tile.possible_states_ref().count()
+                })
+                .unwrap_or_default();
+            if remaining == 0 {
+                // every state of the tile has been tried, undo the decision before it
+                continue;
+            }
+
+            queue.extend(propagate_from_tile(grid, decision.position));
+            match grid.propagate(queue) {
+                Err(WaveFunctionCollapseInterruption::Contradiction(_)) => continue,
+                result => return result,
+            }
+        }
+
+        // no decisions left to undo, the grid can't be collapsed
+        Err(WaveFunctionCollapseInterruption::Contradiction(
+            contradiction_location,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D, tests::any_combination_grid},
+        selection::entropy::MinimumEntropy,
+        tile::Tile,
+        utils::space::s2d::{Location2D, Vector2D},
+        value_selection::{ValueSelector, weighted::WeightedRandom},
+    };
+
+    use super::*;
+
+    /// Decides the tile with the lowest entropy, like `run` does by default
+    fn tick(
+        b: &mut BacktrackingByUndo<Location2D>,
+        grid: &mut DynamicSizeGrid2D<Tile>,
+    ) -> TickResult<Location2D> {
+        let position = grid.get_lowest_entropy_position().unwrap();
+        let value = WeightedRandom::default()
+            .select_value(grid, position)
+            .unwrap();
+        b.tick(grid, position, value)
+    }
+
+    fn states_at(grid: &DynamicSizeGrid2D<Tile>, location: Location2D) -> Vec<TileState> {
+        grid.get_tile(location).unwrap().possible_states().collect()
+    }
+
+    #[test]
+    fn rewinds_and_bans() {
+        let mut grid = any_combination_grid(2, 2, 0..3);
+        let mut b = BacktrackingByUndo::new();
+        tick(&mut b, &mut grid).expect("the first decision should succeed");
+        let decision = b.decisions[0].clone();
+        assert_eq!(states_at(&grid, decision.position), vec![decision.state]);
+
+        // break some other tile
+        let other = grid
+            .positions()
+            .find(|location| *location != decision.position)
+            .unwrap();
+        grid.with_tile(other, |t, _| t.set_possible_states([]));
+
+        b.contradiction_handler(&mut grid, other)
+            .expect("contradiction should've resolved");
+        assert_eq!(b.depth(), 0);
+        for location in grid.positions() {
+            let expected: Vec<_> = if location == decision.position {
+                (0..3).filter(|state| *state != decision.state).collect()
+            } else {
+                vec![0, 1, 2]
+            };
+            assert_eq!(states_at(&grid, location), expected, "{location:?}");
+        }
+    }
+
+    #[test]
+    fn only_logged_tiles_are_compared() {
+        let mut grid = any_combination_grid(2, 2, 0..3);
+        let mut b = BacktrackingByUndo::new();
+        tick(&mut b, &mut grid).unwrap();
+        let before_first = b.changes.logged.expect("the grid keeps an update log");
+        assert!(grid.update_log.len() > before_first);
+
+        tick(&mut b, &mut grid).unwrap();
+        let changed_by_first = &grid.update_log[before_first..];
+        let previous = &b.decisions[1].previous;
+        assert!(!previous.is_empty());
+        for (position, _) in previous {
+            assert!(
+                changed_by_first
+                    .iter()
+                    .any(|(logged, _)| logged == position)
+            );
+        }
+    }
+
+    #[test]
+    fn bans_are_undone_with_earlier_decisions() {
+        let mut grid = any_combination_grid(2, 2, 0..2);
+        let mut b = BacktrackingByUndo::new();
+        tick(&mut b, &mut grid).unwrap();
+        tick(&mut b, &mut grid).unwrap();
+        let first = b.decisions[0].clone();
+        let second = b.decisions[1].clone();
+
+        // undoing the second decision bans its state, which collapses the tile
+        grid.with_tile(second.position, |t, _| t.set_possible_states([]));
+        b.contradiction_handler(&mut grid, second.position).unwrap();
+        assert_eq!(b.depth(), 1);
+        assert_eq!(states_at(&grid, first.position), vec![first.state]);
+        assert_eq!(states_at(&grid, second.position), vec![1 - second.state]);
+
+        // undoing the first one should forget about the ban, it depended on the first decision
+        grid.with_tile(second.position, |t, _| t.set_possible_states([]));
+        b.contradiction_handler(&mut grid, second.position).unwrap();
+        assert_eq!(b.depth(), 0);
+        assert_eq!(states_at(&grid, first.position), vec![1 - first.state]);
+        assert_eq!(states_at(&grid, second.position), vec![0, 1]);
+    }
+
+    #[test]
+    fn decisions_are_kept_between_runs() {
+        let mut grid = any_combination_grid(2, 2, 0..3);
+        let mut b = BacktrackingByUndo::new();
+        for _ in 0..2 {
+            let result = grid.run_with_heuristics(
+                1,
+                Some(&mut b),
+                MinimumEntropy,
+                WeightedRandom::default(),
+            );
+            assert_eq!(
+                result,
+                Err(WaveFunctionCollapseInterruption::MaxIterationsReached)
+            );
+        }
+        assert_eq!(b.depth(), 2);
+
+        // the decision of the first run can still be undone, after the one of the second run
+        let first = b.decisions[0].clone();
+        for depth in [1, 0] {
+            grid.with_tile(first.position, |t, _| t.set_possible_states([]));
+            b.contradiction_handler(&mut grid, first.position).unwrap();
+            assert_eq!(b.depth(), depth);
+        }
+        assert!(!states_at(&grid, first.position).contains(&first.state));
+    }
+
+    #[test]
+    fn exhausted_search_is_a_contradiction() {
+        // an odd row of checkers can't wrap around
+        let rules = crate::rules::samples::checkers::rules();
+        let mut grid = DynamicSizeGrid2D::<Tile>::new_periodic(
+            3,
+            2,
+            rules,
+            0,
+            Default::default(),
+            Vector2D { x: true, y: false },
+        );
+        let mut b = BacktrackingByUndo::new();
+        let Err(WaveFunctionCollapseInterruption::Contradiction(location)) =
+            tick(&mut b, &mut grid)
+        else {
+            panic!("the first decision should fail");
+        };
+        assert!(matches!(
+            b.contradiction_handler(&mut grid, location),
+            Err(WaveFunctionCollapseInterruption::Contradiction(_))
+        ));
+        assert_eq!(b.depth(), 0);
+    }
 }
@@ -10,21 +10,30 @@ use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    rules::RuleSet,
+    rules::{RuleSet, compiled::CompiledRuleSet},
     tile::{Tile, TileState, interface::TileInterface},
     utils::{
         entropy::EntropyHeapEntry,
-        space::s2d::{Delta2D, Direction2D, Location2D, NEIGHBOUR_COUNT_2D},
+        space::{
+            apply_on_axis,
+            s2d::{Delta2D, Direction2D, Location2D, NEIGHBOUR_COUNT_2D, Vector2D},
+        },
+    },
+    wave_function_collapse::{
+        constraints::StateCounts,
+        interface::WaveFunctionCollapse,
+        propagate_from_tile,
+        propagation::{PropagationStrategy, SupportCounts},
     },
-    wave_function_collapse::{interface::WaveFunctionCollapse, propagate_from_tile},
 };
 
 use super::GridInterface;
 
 #[derive(Debug)]
-pub struct ConstantSizeGrid2D<const W: usize, const H: usize> {
+pub struct ConstantSizeGrid2D<const W: usize, const H: usize, T = Tile> {
     pub rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
-    tiles: [[Tile; H]; W],
+    compiled_rules: CompiledRuleSet<Direction2D>,
+    tiles: [[T; H]; W],
     /// Priority queue based on tile entropy
     entropy_heap: BinaryHeap<EntropyHeapEntry>,
     /// Used to invalidate entries in the entropy_heap
@@ -32,9 +41,18 @@ pub struct ConstantSizeGrid2D<const W: usize, const H: usize> {
 
     /// Dictates random events
     rng: ChaCha8Rng,
+    pub propagation: PropagationStrategy,
+    /// Axes along which the grid wraps around, see `DynamicSizeGrid2D::new_periodic`
+    pub periodic: Vector2D<bool>,
+    /// Only kept up to date when using `PropagationStrategy::SupportCount`
+    support: Option<SupportCounts<Location2D, Direction2D>>,
+    /// Only kept when the rules have count constraints
+    counts: Option<StateCounts>,
 }
-impl<const W: usize, const H: usize> ConstantSizeGrid2D<W, H> {
-    fn update_tile(&mut self, location: Location2D, state: Tile) -> Option<()> {
+impl<const W: usize, const H: usize, T: TileInterface<TileState> + Clone + PartialEq>
+    ConstantSizeGrid2D<W, H, T>
+{
+    fn update_tile(&mut self, location: Location2D, state: T) -> Option<()> {
         let current_state = self.get_tile(location)?;
 
         if state == *current_state {
@@ -42,6 +60,15 @@ impl<const W: usize, const H: usize> ConstantSizeGrid2D<W, H> {
             return Some(());
         }
 
+        if self.support.is_some() {
+            let neighbours = self.get_neighbours(location);
+            if let Some(support) = &mut self.support {
+                support.tile_updated(neighbours, &self.tiles[location.x][location.y], &state);
+            }
+        }
+        if let Some(counts) = &mut self.counts {
+            counts.tile_updated(&self.tiles[location.x][location.y], &state);
+        }
         self.tiles[location.x][location.y] = state;
         self.update_tile_entropy(location);
 
@@ -70,20 +97,61 @@ impl<const W: usize, const H: usize> ConstantSizeGrid2D<W, H> {
 
 impl<const W: usize, const H: usize> ConstantSizeGrid2D<W, H> {
     pub fn new(rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>, rng_seed: u64) -> Self {
-        let tiles =
-            std::array::from_fn(|_| std::array::from_fn(|_| Tile::new(rules.possible.clone())));
+        Self::new_with_propagation(rules, rng_seed, PropagationStrategy::default())
+    }
+}
+
+impl<const W: usize, const H: usize, T: TileInterface<TileState> + Clone + PartialEq>
+    ConstantSizeGrid2D<W, H, T>
+{
+    pub fn new_with_propagation(
+        rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+    ) -> Self {
+        Self::new_periodic(rules, rng_seed, propagation, Vector2D::default())
+    }
+
+    /// Creates a grid that wraps around along the axes set in `periodic`.
+    ///
+    /// `initialize_edges` rules pointing along a periodic axis are ignored, as the grid has no
+    /// edges in that direction.
+    pub fn new_periodic(
+        rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+        periodic: Vector2D<bool>,
+    ) -> Self {
+        let compiled_rules = rules.compile();
+        let tiles = std::array::from_fn(|_| {
+            std::array::from_fn(|_| {
+                T::with_index(compiled_rules.state_index(), rules.possible.iter().copied())
+            })
+        });
         let tile_invalidation_matrix = std::array::from_fn(|_| std::array::from_fn(|_| 0));
         let mut new = Self {
             rules: rules.clone(),
+            compiled_rules,
             tiles,
             entropy_heap: BinaryHeap::new(),
             entropy_invalidation_matrix: tile_invalidation_matrix,
             rng: ChaCha8Rng::seed_from_u64(rng_seed),
+            propagation,
+            periodic,
+            support: None,
+            counts: None,
         };
+        if propagation == PropagationStrategy::SupportCount {
+            new.support = Some(SupportCounts::from_grid(&new));
+        }
+        new.counts = StateCounts::from_grid(&new)
+            .expect("The count constraints can't be met on a grid of this size");
 
         let mut initial_propagation_queue = VecDeque::new();
         for (direction, tile_state) in &rules.initialize_edges {
             let edge_tile_locations: Vec<_> = match Delta2D::from(*direction) {
+                Delta2D { x: _, y: 0 } if periodic.x => continue,
+                Delta2D { x: 0, y: _ } if periodic.y => continue,
                 Delta2D { x: dx, y: 0 } => {
                     let x = if dx > 0 { W - 1 } else { 0 };
                     (0..H).map(|y| Location2D { x, y }).collect()
@@ -116,18 +184,23 @@ impl<const W: usize, const H: usize> ConstantSizeGrid2D<W, H> {
     }
 }
 
-impl<const W: usize, const H: usize> GridInterface<4, TileState, Location2D, Direction2D, Tile>
-    for ConstantSizeGrid2D<W, H>
+impl<const W: usize, const H: usize, T: TileInterface<TileState> + Clone + PartialEq>
+    GridInterface<4, TileState, Location2D, Direction2D, T> for ConstantSizeGrid2D<W, H, T>
 {
     fn get_dimensions(&self) -> Location2D {
         Location2D { x: W, y: H }
     }
 
     fn reset(&mut self) {
-        *self = Self::new(self.rules.clone(), self.rng.random())
+        *self = Self::new_periodic(
+            self.rules.clone(),
+            self.rng.random(),
+            self.propagation,
+            self.periodic,
+        )
     }
 
-    fn image(&self) -> std::collections::HashMap<Location2D, Tile> {
+    fn image(&self) -> std::collections::HashMap<Location2D, T> {
         let mut map = HashMap::new();
         for (x, col) in self.tiles.iter().enumerate() {
             for (y, tile) in col.iter().enumerate() {
@@ -138,7 +211,7 @@ impl<const W: usize, const H: usize> GridInterface<4, TileState, Location2D, Dir
         map
     }
 
-    fn get_tile(&self, location: Location2D) -> Option<&Tile> {
+    fn get_tile(&self, location: Location2D) -> Option<&T> {
         self.tiles
             .get(location.x)
             .and_then(|col| col.get(location.y))
@@ -148,20 +221,14 @@ impl<const W: usize, const H: usize> GridInterface<4, TileState, Location2D, Dir
         std::array::from_fn(|index| {
             let direction = Direction2D::try_from(index).unwrap();
             let direction_delta = Delta2D::from(direction);
-            let location = if let Ok(neighbour_location) = location.try_apply(direction_delta) {
-                if neighbour_location.x >= W || neighbour_location.y >= H {
-                    None
-                } else {
-                    Some(neighbour_location)
-                }
-            } else {
-                None
-            };
+            let x = apply_on_axis(location.x, direction_delta.x, W, self.periodic.x);
+            let y = apply_on_axis(location.y, direction_delta.y, H, self.periodic.y);
+            let location = x.zip(y).map(|(x, y)| Location2D { x, y });
             (direction, location)
         })
     }
 
-    fn get_neighbour_tiles(&self, location: Location2D) -> [(Direction2D, Option<&Tile>); 4] {
+    fn get_neighbour_tiles(&self, location: Location2D) -> [(Direction2D, Option<&T>); 4] {
         let locations = self.get_neighbours(location);
         std::array::from_fn(|index| {
             let (direction, neighbour_location) = locations[index];
@@ -193,7 +260,7 @@ impl<const W: usize, const H: usize> GridInterface<4, TileState, Location2D, Dir
         None
     }
 
-    fn with_tile<R, F: Fn(&mut Tile, &mut ChaCha8Rng) -> R>(
+    fn with_tile<R, F: Fn(&mut T, &mut ChaCha8Rng) -> R>(
         &mut self,
         location: Location2D,
         f: F,
@@ -210,13 +277,25 @@ impl<const W: usize, const H: usize> GridInterface<4, TileState, Location2D, Dir
         &self.rules
     }
 
+    fn get_compiled_rules(&self) -> &CompiledRuleSet<Direction2D> {
+        &self.compiled_rules
+    }
+
     fn positions(&self) -> impl Iterator<Item = Location2D> {
         (0..W).flat_map(|x| (0..H).map(move |y| Location2D { x, y }))
     }
 
-    fn get_tiles_at_time(&self, _time_index: usize) -> HashMap<Location2D, Tile> {
+    fn get_tiles_at_time(&self, _time_index: usize) -> HashMap<Location2D, T> {
         unimplemented!()
     }
+
+    fn get_support_counts(&self) -> Option<&SupportCounts<Location2D, Direction2D>> {
+        self.support.as_ref()
+    }
+
+    fn get_state_counts(&self) -> Option<&StateCounts> {
+        self.counts.as_ref()
+    }
 }
 
 #[cfg(test)]
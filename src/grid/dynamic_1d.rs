@@ -1,7 +1,7 @@
 //! A Grid that can be initialized at any size
 //!
 
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
@@ -9,11 +9,19 @@ use serde::{Deserialize, Serialize};
 use tsify_next::Tsify;
 
 use crate::{
-    rules::RuleSet,
+    rules::{RuleSet, compiled::CompiledRuleSet},
     tile::{Tile, TileState, interface::TileInterface},
     utils::{
         entropy::EntropyHeapEntry1D,
-        space::s1d::{Delta1D, Direction1D, Location1D, NEIGHBOUR_COUNT_1D},
+        space::{
+            apply_on_axis,
+            s1d::{Delta1D, Direction1D, Location1D, NEIGHBOUR_COUNT_1D, Vector1D},
+        },
+    },
+    wave_function_collapse::{
+        constraints::StateCounts,
+        interface::{WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+        propagate_from_tile,
     },
 };
 
@@ -24,7 +32,11 @@ use super::GridInterface;
 pub struct DynamicSizeGrid1D {
     #[tsify(type = "RuleSet<Direction1D>")]
     pub rules: RuleSet<NEIGHBOUR_COUNT_1D, Direction1D>,
+    #[tsify(type = "any")]
+    compiled_rules: CompiledRuleSet<Direction1D>,
     pub width: usize,
+    /// If set, the first and last tiles are neighbours
+    pub periodic: Vector1D<bool>,
     // A one dimensional array is used for potentionally better performance
     // (cache locality, fewer bounds checks - if enabled)
     tiles: Vec<Tile>,
@@ -35,8 +47,14 @@ pub struct DynamicSizeGrid1D {
     entropy_invalidation_matrix: Vec<usize>,
     /// Keeps history of tile modifications for backtracking
     pub update_log: Vec<(Location1D, Tile)>,
+    /// How often the grid has been reset, which changes every tile without logging it
+    #[serde(default)]
+    resets: usize,
     /// Dictates random events
     rng: ChaCha8Rng,
+    /// Only kept when the rules have count constraints
+    #[tsify(type = "any")]
+    counts: Option<StateCounts>,
 }
 
 impl DynamicSizeGrid1D {
@@ -50,6 +68,9 @@ impl DynamicSizeGrid1D {
         }
 
         let tile_index = self.location_to_index(location);
+        if let Some(counts) = &mut self.counts {
+            counts.tile_updated(&self.tiles[tile_index], &state);
+        }
         self.tiles[tile_index] = state.clone();
         self.update_tile_entropy(location);
         self.update_log.push((location, state));
@@ -93,27 +114,69 @@ impl DynamicSizeGrid1D {
         rules: RuleSet<NEIGHBOUR_COUNT_1D, Direction1D>,
         rng_seed: u64,
     ) -> Self {
+        Self::new_periodic(width, rules, rng_seed, Vector1D::default())
+    }
+
+    /// Creates a grid whose ends wrap around if `periodic.x` is set, in which case
+    /// `initialize_edges` is ignored
+    pub fn new_periodic(
+        width: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_1D, Direction1D>,
+        rng_seed: u64,
+        periodic: Vector1D<bool>,
+    ) -> Self {
+        Self::try_new_periodic(width, rules, rng_seed, periodic).expect(
+            "Propagation got interrupted after an edge was collapsed, please revise your ruleset",
+        )
+    }
+
+    /// Like `new_periodic`, but returns the contradiction instead of panicking if the edges
+    /// contradict each other
+    pub fn try_new_periodic(
+        width: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_1D, Direction1D>,
+        rng_seed: u64,
+        periodic: Vector1D<bool>,
+    ) -> Result<Self, WaveFunctionCollapseInterruption<Location1D>> {
         let tiles = vec![Tile::new(rules.possible.clone()); width];
         let tile_invalidation_matrix = vec![0; width];
         let mut new = Self {
             width,
+            periodic,
             rules: rules.clone(),
+            compiled_rules: rules.compile(),
             tiles,
             entropy_heap: BinaryHeap::new(),
             entropy_invalidation_matrix: tile_invalidation_matrix,
             update_log: Vec::new(),
+            resets: 0,
             rng: ChaCha8Rng::seed_from_u64(rng_seed),
+            counts: None,
         };
+        new.counts = StateCounts::from_grid(&new)?;
 
-        if !rules.initialize_edges.is_empty() {
-            unimplemented!();
+        let mut initial_propagation_queue = VecDeque::new();
+        for (direction, tile_state) in &rules.initialize_edges {
+            if width == 0 || periodic.x {
+                break;
+            }
+            let location = match direction {
+                Direction1D::RIGHT => Location1D { x: width - 1 },
+                Direction1D::LEFT => Location1D { x: 0 },
+            };
+            new.with_tile(location, |t, _| {
+                t.set_possible_states([*tile_state]);
+            });
+            initial_propagation_queue.extend(propagate_from_tile(&new, location));
         }
 
+        new.propagate(initial_propagation_queue)?;
+
         for x in 0..width {
             new.update_tile_entropy(Location1D { x });
         }
 
-        new
+        Ok(new)
     }
 
     #[inline]
@@ -136,7 +199,14 @@ impl GridInterface<NEIGHBOUR_COUNT_1D, TileState, Location1D, Direction1D, Tile>
     }
 
     fn reset(&mut self) {
-        *self = Self::new(self.width, self.rules.clone(), self.rng.random())
+        let resets = self.resets + 1;
+        *self = Self::new_periodic(
+            self.width,
+            self.rules.clone(),
+            self.rng.random(),
+            self.periodic,
+        );
+        self.resets = resets;
     }
 
     fn image(&self) -> std::collections::HashMap<Location1D, Tile> {
@@ -174,15 +244,9 @@ impl GridInterface<NEIGHBOUR_COUNT_1D, TileState, Location1D, Direction1D, Tile>
         std::array::from_fn(|index| {
             let direction = Direction1D::try_from(index).unwrap();
             let direction_delta = Delta1D::from(direction);
-            let location = if let Ok(neighbour_location) = location.try_apply(direction_delta) {
-                if neighbour_location.x >= self.width {
-                    None
-                } else {
-                    Some(neighbour_location)
-                }
-            } else {
-                None
-            };
+            let location =
+                apply_on_axis(location.x, direction_delta.x, self.width, self.periodic.x)
+                    .map(|x| Location1D { x });
             (direction, location)
         })
     }
@@ -239,6 +303,22 @@ impl GridInterface<NEIGHBOUR_COUNT_1D, TileState, Location1D, Direction1D, Tile>
         &self.rules
     }
 
+    fn get_compiled_rules(&self) -> &CompiledRuleSet<Direction1D> {
+        &self.compiled_rules
+    }
+
+    fn get_state_counts(&self) -> Option<&StateCounts> {
+        self.counts.as_ref()
+    }
+
+    fn get_update_log(&self) -> Option<&[(Location1D, Tile)]> {
+        Some(&self.update_log)
+    }
+
+    fn get_reset_count(&self) -> usize {
+        self.resets
+    }
+
     fn positions(&self) -> impl Iterator<Item = Location1D> {
         (0..self.width).map(|x| Location1D { x })
     }
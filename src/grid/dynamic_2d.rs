@@ -10,40 +10,86 @@ use serde::{Deserialize, Serialize};
 use tsify_next::Tsify;
 
 use crate::{
-    rules::RuleSet,
+    rules::{RuleSet, compiled::CompiledRuleSet},
     tile::{Tile, TileState, interface::TileInterface},
     utils::{
         entropy::Entropy,
-        space::s2d::{Delta2D, Direction2D, Location2D, NEIGHBOUR_COUNT_2D},
+        space::{
+            apply_on_axis,
+            s2d::{Delta2D, Direction2D, Location2D, NEIGHBOUR_COUNT_2D, Vector2D},
+        },
+    },
+    wave_function_collapse::{
+        connectivity::ConnectivityConstraint,
+        constraints::StateCounts,
+        interface::{TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+        propagate_from_tile,
+        propagation::{PropagationStrategy, SupportCounts},
+        symmetry::{Symmetry2D, SymmetryConstraint, SymmetryError},
     },
-    wave_function_collapse::{interface::WaveFunctionCollapse, propagate_from_tile},
 };
 
-use super::GridInterface;
+use super::{
+    GridInterface,
+    masks::{PinError, StateMasks},
+    weights::WeightField,
+};
 
+/// `T` selects how the possible states of each tile are stored, see `Tile` and `BitsetTile`
 #[derive(Tsify, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
-pub struct DynamicSizeGrid2D {
+pub struct DynamicSizeGrid2D<T> {
     #[tsify(type = "RuleSet<Direction2D>")]
     pub rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
+    #[tsify(type = "any")]
+    compiled_rules: CompiledRuleSet<Direction2D>,
     pub width: usize,
     pub height: usize,
     // A one dimensional array is used for potentionally better performance
     // (cache locality, fewer bounds checks - if enabled)
-    tiles: Vec<Tile>,
+    tiles: Vec<T>,
     /// Priority queue based on tile entropy
     #[tsify(type = "any")]
     entropy_heap: PriorityQueue<Location2D, Entropy>,
     /// Keeps history of tile modifications for UI
-    pub update_log: Vec<(Location2D, Tile)>,
+    pub update_log: Vec<(Location2D, T)>,
+    /// How often the grid has been reset, which changes every tile without logging it
+    #[serde(default)]
+    resets: usize,
     /// Dictates random events
     #[tsify(type = "any")]
     rng: ChaCha8Rng,
+    pub propagation: PropagationStrategy,
+    /// Axes along which the grid wraps around, tiles on opposite edges of a periodic axis are
+    /// neighbours
+    pub periodic: Vector2D<bool>,
+    /// Only kept up to date when using `PropagationStrategy::SupportCount`
+    #[tsify(type = "any")]
+    support: Option<SupportCounts<Location2D, Direction2D>>,
+    /// Only kept when the rules have count constraints
+    #[tsify(type = "any")]
+    counts: Option<StateCounts>,
+    /// See `set_masks`
+    #[tsify(type = "any")]
+    masks: StateMasks<Location2D>,
+    /// See `set_connectivity`
+    #[tsify(type = "any")]
+    connectivity: Option<ConnectivityConstraint<Location2D>>,
+    /// See `set_symmetry`
+    #[tsify(type = "any")]
+    symmetry: Option<SymmetryConstraint<Location2D>>,
+    /// See `set_weight_field`
+    #[tsify(type = "any")]
+    weight_field: Option<WeightField<Location2D>>,
+    /// Tiles as they were before their first update since `pin` started, so that a failed pin
+    /// can be rolled back
+    #[serde(skip)]
+    rollback: Option<HashMap<Location2D, T>>,
 }
 
-impl DynamicSizeGrid2D {
+impl<T: TileInterface<TileState> + Clone + PartialEq> DynamicSizeGrid2D<T> {
     /// Updates a tile at the given location and it's entry in the entropy heap
-    fn update_tile(&mut self, location: Location2D, state: Tile) -> Option<()> {
+    fn update_tile(&mut self, location: Location2D, state: T) -> Option<()> {
         let current_state = self.get_tile(location)?;
 
         if state == *current_state {
@@ -52,6 +98,20 @@ impl DynamicSizeGrid2D {
         }
 
         let tile_index = self.location_to_index(location);
+        if self.support.is_some() {
+            let neighbours = self.get_neighbours(location);
+            if let Some(support) = &mut self.support {
+                support.tile_updated(neighbours, &self.tiles[tile_index], &state);
+            }
+        }
+        if let Some(counts) = &mut self.counts {
+            counts.tile_updated(&self.tiles[tile_index], &state);
+        }
+        if let Some(rollback) = &mut self.rollback {
+            rollback
+                .entry(location)
+                .or_insert_with(|| self.tiles[tile_index].clone());
+        }
         self.tiles[tile_index] = state.clone();
         self.update_tile_entropy(location);
         self.update_log.push((location, state));
@@ -66,8 +126,17 @@ impl DynamicSizeGrid2D {
     #[inline]
     fn update_tile_entropy(&mut self, location: Location2D) {
         let matrix_index = self.location_to_index(location);
+        let local_weights;
+        let weights = match &self.weight_field {
+            Some(field) => {
+                let states = self.tiles[matrix_index].possible_states();
+                local_weights = field.weights_at(location, states, &self.rules.weights);
+                &local_weights
+            }
+            None => &self.rules.weights,
+        };
         if let Some(new_entropy) =
-            self.tiles[matrix_index].calculate_entropy(&self.rules.weights, &mut self.rng)
+            self.tiles[matrix_index].calculate_entropy(weights, &mut self.rng)
         {
             // priority_queue expects a max-heap, whereas our own previous implementation expected
             // a min-heap, so we flip the entropy in this hacky way
@@ -77,32 +146,261 @@ impl DynamicSizeGrid2D {
         }
     }
 
-    pub fn tiles_ref(&self) -> &Vec<Tile> {
+    pub fn tiles_ref(&self) -> &Vec<T> {
         &self.tiles
     }
+
+    /// Only allows the given states in the masked tiles. The masks are applied to the tiles right
+    /// away, and again whenever the tiles are reset.
+    ///
+    /// The new masks replace the stored ones, but the tiles keep any states the previous masks
+    /// already removed until the grid is reset, so call `reset` afterwards to loosen a mask.
+    /// Meant to be called before solving, masks that can't be met on an empty grid can't be met
+    /// after a reset either.
+    pub fn set_masks(&mut self, masks: StateMasks<Location2D>) -> TickResult<Location2D> {
+        self.masks = masks;
+        self.apply_masks()
+    }
+
+    fn apply_masks(&mut self) -> TickResult<Location2D> {
+        let masks = self.masks.clone();
+        let mut queue = VecDeque::new();
+        for (location, mask) in masks.iter() {
+            // masks outside of the grid are ignored
+            let Some(changed) =
+                self.with_tile(*location, |t, _| t.retain(|state| mask.contains(state)))
+            else {
+                continue;
+            };
+            if self
+                .get_tile(*location)
+                .is_some_and(|t| t.possible_states_ref().next().is_none())
+            {
+                return Err(WaveFunctionCollapseInterruption::Contradiction(*location));
+            }
+            if changed {
+                queue.extend(propagate_from_tile(self, *location));
+            }
+        }
+        self.propagate(queue)
+    }
+
+    /// Pins the tile at `location` to `state`, and propagates the change right away.
+    ///
+    /// Unlike `WaveFunctionCollapse::collapse`, the state is checked against the rules and the
+    /// current possible states of the tile first. If pinning fails the grid is left as it was,
+    /// though the attempt and its rollback stay in the update log.
+    /// Pins are stored as masks, so they are applied again after resets and backtracking.
+    pub fn pin(
+        &mut self,
+        location: Location2D,
+        state: TileState,
+    ) -> Result<(), PinError<Location2D, Direction2D>> {
+        let tile = self
+            .get_tile(location)
+            .ok_or(PinError::OutOfBounds(location))?;
+        if !self.rules.possible.contains(&state) {
+            return Err(PinError::UnknownState(state));
+        }
+        if !tile.possible_states_ref().any(|s| *s == state) {
+            // blame the first neighbour that doesn't allow the state
+            for (direction, neighbour) in self.get_neighbours(location) {
+                let Some(neighbour) = neighbour else {
+                    continue;
+                };
+                let allowed = self.get_tile(neighbour).is_some_and(|n| {
+                    n.possible_states_ref()
+                        .any(|n| self.rules.allowed.contains(&(state, direction, *n)))
+                });
+                if !allowed {
+                    return Err(PinError::ConflictsWithNeighbour {
+                        position: location,
+                        state,
+                        neighbour,
+                        direction,
+                    });
+                }
+            }
+            return Err(PinError::Unavailable {
+                position: location,
+                state,
+            });
+        }
+
+        let rng = self.rng.clone();
+        self.rollback = Some(HashMap::new());
+        self.with_tile(location, |t, _| t.set_possible_states([state]));
+        let queue = propagate_from_tile(self, location);
+        let result = self.propagate(queue);
+        let before = self.rollback.take().unwrap_or_default();
+        if let Err(interruption) = result {
+            // only the tiles touched by the pin are restored, as if it never happened. The
+            // restored tiles are logged, so trackers of the log notice them.
+            for (changed, tile) in before {
+                self.update_tile(changed, tile);
+            }
+            self.rng = rng;
+            let contradiction = match interruption {
+                WaveFunctionCollapseInterruption::Contradiction(at) => at,
+                _ => location,
+            };
+            return Err(PinError::Contradiction {
+                position: location,
+                state,
+                contradiction,
+            });
+        }
+        self.masks.restrict(location, [state]);
+        Ok(())
+    }
+
+    /// Requires the walkable states to form a single connected region, see
+    /// `ConnectivityConstraint`. The constraint is applied to the tiles right away, and kept
+    /// over resets.
+    pub fn set_connectivity(
+        &mut self,
+        connectivity: Option<ConnectivityConstraint<Location2D>>,
+    ) -> TickResult<Location2D> {
+        self.connectivity = connectivity;
+        self.propagate(VecDeque::new())
+    }
+
+    /// Scales the weights of the rules per tile, see `WeightField`. Kept over resets.
+    pub fn set_weight_field(&mut self, weight_field: Option<WeightField<Location2D>>) {
+        self.weight_field = weight_field;
+        for i in 0..self.tiles.len() {
+            // the cached entropies were calculated with the old weights
+            let states: Vec<_> = self.tiles[i].possible_states().collect();
+            self.tiles[i].set_possible_states(states);
+            self.update_tile_entropy(self.index_to_location(i));
+        }
+    }
+
+    /// Makes the output symmetric, turning states into each other with
+    /// `RuleSet::transforms`. The symmetry is applied to the tiles right away, and kept over
+    /// resets.
+    ///
+    /// Returns an error if `Symmetry2D::Rotation90` is used on a grid that isn't square, in which
+    /// case the previous symmetry is kept
+    pub fn set_symmetry(
+        &mut self,
+        symmetry: Option<Symmetry2D>,
+    ) -> Result<(), SymmetryError<Location2D>> {
+        self.symmetry = symmetry
+            .map(|symmetry| {
+                SymmetryConstraint::new_2d(
+                    symmetry,
+                    self.width,
+                    self.height,
+                    &self.rules.possible,
+                    &self.rules.transforms,
+                )
+            })
+            .transpose()?;
+        self.propagate(VecDeque::new())
+            .map_err(SymmetryError::Interrupted)
+    }
 }
 
-impl DynamicSizeGrid2D {
+impl DynamicSizeGrid2D<Tile> {
     pub fn new(
         width: usize,
         height: usize,
         rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
         rng_seed: u64,
     ) -> Self {
-        let tiles = vec![Tile::new(rules.possible.clone()); width * height];
+        Self::new_with_propagation(
+            width,
+            height,
+            rules,
+            rng_seed,
+            PropagationStrategy::default(),
+        )
+    }
+}
+
+impl<T: TileInterface<TileState> + Clone + PartialEq> DynamicSizeGrid2D<T> {
+    pub fn new_with_propagation(
+        width: usize,
+        height: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+    ) -> Self {
+        Self::new_periodic(
+            width,
+            height,
+            rules,
+            rng_seed,
+            propagation,
+            Vector2D::default(),
+        )
+    }
+
+    /// Creates a grid that wraps around along the axes set in `periodic`.
+    ///
+    /// `initialize_edges` rules pointing along a periodic axis are ignored, as the grid has no
+    /// edges in that direction.
+    ///
+    /// Panics if the edges contradict each other, see `try_new_periodic`
+    pub fn new_periodic(
+        width: usize,
+        height: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+        periodic: Vector2D<bool>,
+    ) -> Self {
+        Self::try_new_periodic(width, height, rules, rng_seed, propagation, periodic).expect(
+            "Propagation got interrupted after an edge was collapsed, please revise your ruleset",
+        )
+    }
+
+    /// Like `new_periodic`, but returns the contradiction instead of panicking if the edges
+    /// contradict each other
+    pub fn try_new_periodic(
+        width: usize,
+        height: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+        periodic: Vector2D<bool>,
+    ) -> Result<Self, WaveFunctionCollapseInterruption<Location2D>> {
+        let compiled_rules = rules.compile();
+        let tiles = vec![
+            T::with_index(compiled_rules.state_index(), rules.possible.iter().copied());
+            width * height
+        ];
         let mut new = Self {
             width,
             height,
             rules: rules.clone(),
+            compiled_rules,
             tiles,
             entropy_heap: PriorityQueue::new(),
             update_log: Vec::new(),
+            resets: 0,
             rng: ChaCha8Rng::seed_from_u64(rng_seed),
+            propagation,
+            periodic,
+            support: None,
+            counts: None,
+            masks: StateMasks::new(),
+            connectivity: None,
+            symmetry: None,
+            weight_field: None,
+            rollback: None,
         };
+        if propagation == PropagationStrategy::SupportCount {
+            new.support = Some(SupportCounts::from_grid(&new));
+        }
+        new.counts = StateCounts::from_grid(&new)?;
 
         let mut initial_propagation_queue = VecDeque::new();
         for (direction, tile_state) in &rules.initialize_edges {
             let edge_tile_locations: Vec<_> = match Delta2D::from(*direction) {
+                Delta2D { x: _, y: 0 } if periodic.x => continue,
+                Delta2D { x: 0, y: _ } if periodic.y => continue,
                 Delta2D { x: dx, y: 0 } => {
                     let x = if dx > 0 { width - 1 } else { 0 };
                     (0..height).map(|y| Location2D { x, y }).collect()
@@ -121,9 +419,7 @@ impl DynamicSizeGrid2D {
             }
         }
 
-        new.propagate(initial_propagation_queue).expect(
-            "Propagation got interrupted after an edge was collapsed, please revise your ruleset",
-        );
+        new.propagate(initial_propagation_queue)?;
 
         for x in 0..width {
             for y in 0..height {
@@ -131,7 +427,7 @@ impl DynamicSizeGrid2D {
             }
         }
 
-        new
+        Ok(new)
     }
 
     /// Using a 1D array for storing 2D locations requires a bit of additional math
@@ -150,8 +446,9 @@ impl DynamicSizeGrid2D {
 }
 
 // See `GridInterface` for further documentation
-impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
-    for DynamicSizeGrid2D
+impl<T: TileInterface<TileState> + Clone + PartialEq>
+    GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, T>
+    for DynamicSizeGrid2D<T>
 {
     fn get_dimensions(&self) -> Location2D {
         Location2D {
@@ -162,16 +459,31 @@ impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
 
     fn reset(&mut self) {
         let update_log = self.update_log.clone();
-        *self = Self::new(
+        let resets = self.resets + 1;
+        let masks = std::mem::take(&mut self.masks);
+        let connectivity = self.connectivity.take();
+        let symmetry = self.symmetry.take();
+        let weight_field = self.weight_field.take();
+        *self = Self::new_periodic(
             self.width,
             self.height,
             self.rules.clone(),
             self.rng.random(),
+            self.propagation,
+            self.periodic,
         );
         self.update_log = update_log;
+        self.resets = resets;
+        self.set_weight_field(weight_field);
+        // on an empty grid these can only fail if they contradict the rules, in which case the
+        // grid couldn't be solved anyway
+        let _ = self.set_masks(masks);
+        let _ = self.set_connectivity(connectivity);
+        self.symmetry = symmetry;
+        let _ = self.propagate(VecDeque::new());
     }
 
-    fn image(&self) -> std::collections::HashMap<Location2D, Tile> {
+    fn image(&self) -> std::collections::HashMap<Location2D, T> {
         let mut map = HashMap::new();
         for (i, tile) in self.tiles.iter().enumerate() {
             let position = self.index_to_location(i);
@@ -180,7 +492,7 @@ impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
         map
     }
 
-    fn get_tiles_at_time(&self, time_index: usize) -> HashMap<Location2D, Tile> {
+    fn get_tiles_at_time(&self, time_index: usize) -> HashMap<Location2D, T> {
         let mut tiles = HashMap::new();
         let mut i = 0;
         for (location, new_state) in &self.update_log {
@@ -193,7 +505,11 @@ impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
         tiles
     }
 
-    fn get_tile(&self, location: Location2D) -> Option<&Tile> {
+    fn get_tile(&self, location: Location2D) -> Option<&T> {
+        if location.x >= self.width {
+            // would wrap around to the next row otherwise
+            return None;
+        }
         let index = self.location_to_index(location);
         self.tiles.get(index)
     }
@@ -206,15 +522,9 @@ impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
         std::array::from_fn(|index| {
             let direction = Direction2D::try_from(index).unwrap();
             let direction_delta = Delta2D::from(direction);
-            let location = if let Ok(neighbour_location) = location.try_apply(direction_delta) {
-                if neighbour_location.x >= self.width || neighbour_location.y >= self.height {
-                    None
-                } else {
-                    Some(neighbour_location)
-                }
-            } else {
-                None
-            };
+            let x = apply_on_axis(location.x, direction_delta.x, self.width, self.periodic.x);
+            let y = apply_on_axis(location.y, direction_delta.y, self.height, self.periodic.y);
+            let location = x.zip(y).map(|(x, y)| Location2D { x, y });
             (direction, location)
         })
     }
@@ -222,7 +532,7 @@ impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
     fn get_neighbour_tiles(
         &self,
         location: Location2D,
-    ) -> [(Direction2D, Option<&Tile>); NEIGHBOUR_COUNT_2D] {
+    ) -> [(Direction2D, Option<&T>); NEIGHBOUR_COUNT_2D] {
         let locations = self.get_neighbours(location);
         std::array::from_fn(|index| {
             let (direction, neighbour_location) = locations[index];
@@ -241,7 +551,7 @@ impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
             .map(|(location, _entropy)| *location)
     }
 
-    fn with_tile<R, F: Fn(&mut Tile, &mut ChaCha8Rng) -> R>(
+    fn with_tile<R, F: Fn(&mut T, &mut ChaCha8Rng) -> R>(
         &mut self,
         location: Location2D,
         f: F,
@@ -258,9 +568,45 @@ impl GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>
         &self.rules
     }
 
+    fn get_compiled_rules(&self) -> &CompiledRuleSet<Direction2D> {
+        &self.compiled_rules
+    }
+
     fn positions(&self) -> impl Iterator<Item = Location2D> {
         (0..(self.width * self.height)).map(|i| self.index_to_location(i))
     }
+
+    fn get_support_counts(&self) -> Option<&SupportCounts<Location2D, Direction2D>> {
+        self.support.as_ref()
+    }
+
+    fn get_state_counts(&self) -> Option<&StateCounts> {
+        self.counts.as_ref()
+    }
+
+    fn get_update_log(&self) -> Option<&[(Location2D, T)]> {
+        Some(&self.update_log)
+    }
+
+    fn get_reset_count(&self) -> usize {
+        self.resets
+    }
+
+    fn get_state_masks(&self) -> Option<&StateMasks<Location2D>> {
+        Some(&self.masks)
+    }
+
+    fn get_connectivity(&self) -> Option<&ConnectivityConstraint<Location2D>> {
+        self.connectivity.as_ref()
+    }
+
+    fn get_symmetry(&self) -> Option<&SymmetryConstraint<Location2D>> {
+        self.symmetry.as_ref()
+    }
+
+    fn get_weight_field(&self) -> Option<&WeightField<Location2D>> {
+        self.weight_field.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -268,8 +614,9 @@ mod tests {
     use std::collections::{BTreeMap, BTreeSet, HashSet};
 
     use super::*;
+    use crate::{backtracking::ChangeTracker, tile::bitset::BitsetTile};
 
-    fn debug_print(grid: &DynamicSizeGrid2D) {
+    fn debug_print<T: TileInterface<TileState> + Clone + PartialEq>(grid: &DynamicSizeGrid2D<T>) {
         for y in 0..grid.height {
             for x in 0..grid.width {
                 let tile = grid.get_tile(Location2D { x, y }).unwrap();
@@ -284,7 +631,11 @@ mod tests {
         (position.y * h + position.x) as u64
     }
 
-    fn init_and_check(possible: BTreeSet<TileState>, w: usize, h: usize) -> DynamicSizeGrid2D {
+    fn init_and_check<T: TileInterface<TileState> + Clone + PartialEq>(
+        possible: BTreeSet<TileState>,
+        w: usize,
+        h: usize,
+    ) -> DynamicSizeGrid2D<T> {
         let allowed = HashSet::from([]);
         let rules = RuleSet::new(
             possible,
@@ -293,14 +644,33 @@ mod tests {
             HashMap::new(),
             BTreeMap::new(),
         );
-        let grid = DynamicSizeGrid2D::new(w, h, rules, 0);
+        let grid =
+            DynamicSizeGrid2D::new_with_propagation(w, h, rules, 0, PropagationStrategy::default());
         assert_eq!(grid.tiles.len(), w * h);
 
         grid
     }
 
-    fn init_id(w: usize, h: usize) -> DynamicSizeGrid2D {
-        let mut grid = init_and_check(BTreeSet::new(), w, h);
+    fn init_id<T: TileInterface<TileState> + Clone + PartialEq>(
+        w: usize,
+        h: usize,
+    ) -> DynamicSizeGrid2D<T> {
+        init_id_from(BTreeSet::new(), w, h)
+    }
+
+    /// Bitset tiles only hold states of the ruleset, so the ids and the states used by the
+    /// shared tests have to be part of it
+    fn init_id_bitset(w: usize, h: usize) -> DynamicSizeGrid2D<BitsetTile> {
+        let known = (0..(w + h * h).max(8) as TileState).collect();
+        init_id_from(known, w, h)
+    }
+
+    fn init_id_from<T: TileInterface<TileState> + Clone + PartialEq>(
+        possible: BTreeSet<TileState>,
+        w: usize,
+        h: usize,
+    ) -> DynamicSizeGrid2D<T> {
+        let mut grid = init_and_check::<T>(possible, w, h);
         for x in 0..w {
             for y in 0..h {
                 let location = Location2D { x, y };
@@ -313,14 +683,214 @@ mod tests {
         grid
     }
 
+    #[test]
+    fn masks_survive_reset() {
+        let mut allowed = HashSet::new();
+        for a in 0..3 {
+            for b in 0..3 {
+                allowed.insert((a, Direction2D::RIGHT, b));
+                allowed.insert((a, Direction2D::DOWN, b));
+            }
+        }
+        let rules = RuleSet::new(
+            BTreeSet::from_iter(0..3),
+            allowed,
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(4, 4, rules, 0);
+        let mut masks = StateMasks::new();
+        masks.restrict_border(4, 4, [0, 1]);
+        masks.restrict(Location2D { x: 1, y: 1 }, [2]);
+        grid.set_masks(masks).unwrap();
+
+        for _ in 0..2 {
+            for location in grid.positions() {
+                let expected: Vec<_> = match location {
+                    Location2D { x: 1, y: 1 } => vec![2],
+                    Location2D { x: 1..=2, y: 1..=2 } => vec![0, 1, 2],
+                    _ => vec![0, 1],
+                };
+                let states: Vec<_> = grid.get_tile(location).unwrap().possible_states().collect();
+                assert_eq!(states, expected, "{location:?}");
+            }
+            grid.reset();
+        }
+
+        // the tiles only lose the old masks when they are reset
+        let states_at = |grid: &DynamicSizeGrid2D<Tile>, location| {
+            let tile = grid.get_tile(location).unwrap();
+            tile.possible_states().collect::<Vec<_>>()
+        };
+        let mut masks = StateMasks::new();
+        masks.restrict(Location2D { x: 1, y: 1 }, [1, 2]);
+        grid.set_masks(masks).unwrap();
+        assert_eq!(states_at(&grid, Location2D { x: 0, y: 0 }), vec![0, 1]);
+        assert_eq!(states_at(&grid, Location2D { x: 1, y: 1 }), vec![2]);
+        grid.reset();
+        assert_eq!(states_at(&grid, Location2D { x: 0, y: 0 }), vec![0, 1, 2]);
+        assert_eq!(states_at(&grid, Location2D { x: 1, y: 1 }), vec![1, 2]);
+
+        let mut masks = StateMasks::new();
+        masks.restrict(Location2D { x: 0, y: 0 }, [3]);
+        assert_eq!(
+            grid.set_masks(masks),
+            Err(WaveFunctionCollapseInterruption::Contradiction(
+                Location2D { x: 0, y: 0 }
+            ))
+        );
+    }
+
+    #[test]
+    fn pins_are_validated() {
+        use crate::rules::samples::checkers::{STATE_BLACK, STATE_WHITE, rules};
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(2, 1, rules(), 0);
+        let origin = Location2D { x: 0, y: 0 };
+        let right = Location2D { x: 1, y: 0 };
+        assert_eq!(
+            grid.pin(Location2D { x: 2, y: 0 }, STATE_BLACK),
+            Err(PinError::OutOfBounds(Location2D { x: 2, y: 0 }))
+        );
+        assert_eq!(grid.pin(origin, 7), Err(PinError::UnknownState(7)));
+
+        grid.pin(origin, STATE_BLACK).unwrap();
+        assert!(
+            grid.get_tile(right)
+                .unwrap()
+                .possible_states()
+                .eq([STATE_WHITE])
+        );
+        assert_eq!(
+            grid.pin(right, STATE_BLACK),
+            Err(PinError::ConflictsWithNeighbour {
+                position: right,
+                state: STATE_BLACK,
+                neighbour: origin,
+                direction: Direction2D::LEFT,
+            })
+        );
+
+        // the pin is kept over resets
+        grid.reset();
+        assert!(
+            grid.get_tile(right)
+                .unwrap()
+                .possible_states()
+                .eq([STATE_WHITE])
+        );
+    }
+
+    #[test]
+    fn failed_pins_leave_the_grid_as_is() {
+        use crate::rules::samples::checkers::{STATE_BLACK, rules};
+        // checkers can't wrap around an odd width
+        let mut grid = DynamicSizeGrid2D::<Tile>::new_periodic(
+            3,
+            1,
+            rules(),
+            0,
+            PropagationStrategy::default(),
+            Vector2D { x: true, y: false },
+        );
+        let before = grid.image();
+        let before_log = grid.update_log.clone();
+        let logged = before_log.len();
+        let rng = grid.rng.clone();
+        let mut changes = ChangeTracker::new();
+        changes.compared(&grid);
+        let location = Location2D { x: 0, y: 0 };
+        assert!(matches!(
+            grid.pin(location, STATE_BLACK),
+            Err(PinError::Contradiction { .. })
+        ));
+        assert_eq!(grid.image(), before);
+        assert!(grid.get_state_masks().unwrap().is_empty());
+        assert_eq!(grid.rng, rng);
+        assert!(grid.rollback.is_none());
+
+        // the log only grows, so trackers still find the tiles that were touched
+        assert_eq!(grid.update_log[..logged], before_log[..]);
+        assert!(changes.take_dirty(&grid).contains(&location));
+    }
+
+    #[test]
+    fn pins_survive_backtracking() {
+        use crate::backtracking::{
+            Backtracker, backjumping::BacktrackerByBackjumping, undo::BacktrackingByUndo,
+        };
+
+        let mut allowed = HashSet::new();
+        for a in 0..3 {
+            for b in 0..3 {
+                allowed.insert((a, Direction2D::RIGHT, b));
+                allowed.insert((a, Direction2D::DOWN, b));
+            }
+        }
+        let rules = RuleSet::new(
+            BTreeSet::from_iter(0..3),
+            allowed,
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+        let origin = Location2D { x: 0, y: 0 };
+        let pinned = Location2D { x: 1, y: 0 };
+        let check = |grid: &DynamicSizeGrid2D<Tile>| {
+            assert!(grid.get_tile(origin).unwrap().possible_states().eq([1, 2]));
+            assert!(grid.get_tile(pinned).unwrap().possible_states().eq([2]));
+        };
+
+        // the pin is made after the decision, so rewinding the decision undoes it at first
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(2, 1, rules.clone(), 0);
+        let mut undo = BacktrackingByUndo::new();
+        undo.tick(&mut grid, origin, 0).unwrap();
+        grid.pin(pinned, 2).unwrap();
+        undo.contradiction_handler(&mut grid, origin).unwrap();
+        check(&grid);
+
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(2, 1, rules, 0);
+        let mut backjumping = BacktrackerByBackjumping::new();
+        backjumping.tick(&mut grid, origin, 0).unwrap();
+        grid.pin(pinned, 2).unwrap();
+        backjumping
+            .contradiction_handler(&mut grid, origin)
+            .unwrap();
+        check(&grid);
+    }
+
+    #[test]
+    fn edge_contradictions_are_returned() {
+        // nothing is allowed next to the bottom edge
+        let rules = RuleSet::new(
+            BTreeSet::from([0, 1]),
+            HashSet::new(),
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::from([(Direction2D::DOWN, 0)]),
+        );
+        let result = DynamicSizeGrid2D::<Tile>::try_new_periodic(
+            2,
+            2,
+            rules,
+            0,
+            PropagationStrategy::default(),
+            Vector2D::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(WaveFunctionCollapseInterruption::Contradiction(_))
+        ));
+    }
+
     #[test]
     fn init() {
-        init_and_check(BTreeSet::new(), 3, 3);
+        init_and_check::<Tile>(BTreeSet::new(), 3, 3);
     }
 
     #[test]
     fn init_asymmetric() {
-        init_and_check(BTreeSet::new(), 3, 4);
+        init_and_check::<Tile>(BTreeSet::new(), 3, 4);
     }
 
     #[test]
@@ -328,7 +898,7 @@ mod tests {
         const W: usize = 5;
         const H: usize = 3;
         let init_possible: BTreeSet<TileState> = BTreeSet::from([0, 1, 2, 3]);
-        let grid = init_and_check(init_possible.clone(), W, H);
+        let grid = init_and_check::<Tile>(init_possible.clone(), W, H);
         let image = grid.image();
         (0..W).for_each(|x| {
             (0..H).for_each(|y| {
@@ -345,7 +915,7 @@ mod tests {
     fn init_and_access() {
         const W: usize = 4;
         const H: usize = 6;
-        let grid = init_id(W, H);
+        let grid = init_id::<Tile>(W, H);
         debug_print(&grid);
 
         crate::grid::tests::get_tile(W, H, grid);
@@ -355,17 +925,46 @@ mod tests {
     fn get_neighbours_sanity() {
         const W: usize = 3;
         const H: usize = 3;
-        let grid = init_id(W, H);
+        let grid = init_id::<Tile>(W, H);
         debug_print(&grid);
 
         crate::grid::tests::get_neighbours_sanity(W, H, grid);
     }
 
+    #[test]
+    fn get_neighbours_periodic() {
+        let mut grid = init_id::<Tile>(3, 2);
+        grid.periodic = Vector2D { x: true, y: false };
+
+        let corner = grid.get_neighbours(Location2D { x: 0, y: 0 });
+        assert_eq!(
+            corner,
+            [
+                (Direction2D::UP, None),
+                (Direction2D::RIGHT, Some(Location2D { x: 1, y: 0 })),
+                (Direction2D::DOWN, Some(Location2D { x: 0, y: 1 })),
+                (Direction2D::LEFT, Some(Location2D { x: 2, y: 0 })),
+            ]
+        );
+
+        grid.periodic = Vector2D { x: true, y: true };
+        let corner = grid.get_neighbours(Location2D { x: 2, y: 1 });
+        assert_eq!(
+            corner,
+            [
+                (Direction2D::UP, Some(Location2D { x: 2, y: 0 })),
+                (Direction2D::RIGHT, Some(Location2D { x: 0, y: 1 })),
+                (Direction2D::DOWN, Some(Location2D { x: 2, y: 0 })),
+                (Direction2D::LEFT, Some(Location2D { x: 1, y: 1 })),
+            ]
+        );
+    }
+
     #[test]
     fn update_tiles() {
         const W: usize = 3;
         const H: usize = 3;
-        let mut grid = init_id(W, H);
+        let mut grid = init_id::<Tile>(W, H);
         debug_print(&grid);
 
         crate::grid::tests::update_tiles_sanity(W, H, &mut grid);
@@ -375,7 +974,7 @@ mod tests {
     fn entropy_heap_empty() {
         const W: usize = 0;
         const H: usize = 0;
-        let mut grid = init_id(W, H);
+        let mut grid = init_id::<Tile>(W, H);
 
         assert!(grid.get_lowest_entropy_position().is_none());
     }
@@ -384,7 +983,7 @@ mod tests {
     fn update_entropy() {
         const W: usize = 3;
         const H: usize = 3;
-        let mut grid = init_id(W, H);
+        let mut grid = init_id::<Tile>(W, H);
         debug_print(&grid);
 
         crate::grid::tests::update_tiles_entropy(W, H, &mut grid);
@@ -394,4 +993,54 @@ mod tests {
     fn edge_initialization_2x2() {
         crate::grid::tests::edges_2x2(|rules| DynamicSizeGrid2D::new(2, 2, rules, 0));
     }
+
+    #[test]
+    fn init_and_access_bitset() {
+        const W: usize = 4;
+        const H: usize = 6;
+        let grid = init_id_bitset(W, H);
+        debug_print(&grid);
+
+        crate::grid::tests::get_tile(W, H, grid);
+    }
+
+    #[test]
+    fn get_neighbours_sanity_bitset() {
+        const W: usize = 3;
+        const H: usize = 3;
+        let grid = init_id_bitset(W, H);
+
+        crate::grid::tests::get_neighbours_sanity(W, H, grid);
+    }
+
+    #[test]
+    fn update_tiles_bitset() {
+        const W: usize = 3;
+        const H: usize = 3;
+        let mut grid = init_id_bitset(W, H);
+
+        crate::grid::tests::update_tiles_sanity(W, H, &mut grid);
+    }
+
+    #[test]
+    fn update_entropy_bitset() {
+        const W: usize = 3;
+        const H: usize = 3;
+        let mut grid = init_id_bitset(W, H);
+
+        crate::grid::tests::update_tiles_entropy(W, H, &mut grid);
+    }
+
+    #[test]
+    fn edge_initialization_2x2_bitset() {
+        crate::grid::tests::edges_2x2(|rules| {
+            DynamicSizeGrid2D::<BitsetTile>::new_with_propagation(
+                2,
+                2,
+                rules,
+                0,
+                PropagationStrategy::default(),
+            )
+        });
+    }
 }
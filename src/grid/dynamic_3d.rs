@@ -0,0 +1,515 @@
+//! A three dimensional Grid that can be initialized at any size
+//!
+//! Works the same way as `DynamicSizeGrid2D`, only with an extra axis. `Direction3D::UP` and
+//! `Direction3D::DOWN` move along the y-axis, `Direction3D::FORWARDS` and
+//! `Direction3D::BACKWARDS` along the z-axis.
+
+use std::collections::{HashMap, VecDeque};
+
+use priority_queue::PriorityQueue;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    rules::{RuleSet, compiled::CompiledRuleSet},
+    tile::{Tile, TileState, interface::TileInterface},
+    utils::{
+        entropy::Entropy,
+        space::{
+            apply_on_axis,
+            s3d::{Delta3D, Direction3D, Location3D, NEIGHBOUR_COUNT_3D, Vector3D},
+        },
+    },
+    wave_function_collapse::{
+        constraints::StateCounts,
+        interface::WaveFunctionCollapse,
+        propagate_from_tile,
+        propagation::{PropagationStrategy, SupportCounts},
+    },
+};
+
+use super::GridInterface;
+
+/// `T` selects how the possible states of each tile are stored, see `Tile` and `BitsetTile`
+#[derive(Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DynamicSizeGrid3D<T> {
+    #[tsify(type = "RuleSet<Direction3D>")]
+    pub rules: RuleSet<NEIGHBOUR_COUNT_3D, Direction3D>,
+    #[tsify(type = "any")]
+    compiled_rules: CompiledRuleSet<Direction3D>,
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    // A one dimensional array is used for potentionally better performance
+    // (cache locality, fewer bounds checks - if enabled)
+    tiles: Vec<T>,
+    /// Priority queue based on tile entropy
+    #[tsify(type = "any")]
+    entropy_heap: PriorityQueue<Location3D, Entropy>,
+    /// Keeps history of tile modifications for UI
+    pub update_log: Vec<(Location3D, T)>,
+    /// How often the grid has been reset, which changes every tile without logging it
+    #[serde(default)]
+    resets: usize,
+    /// Dictates random events
+    #[tsify(type = "any")]
+    rng: ChaCha8Rng,
+    pub propagation: PropagationStrategy,
+    /// Axes along which the grid wraps around, see `new_periodic`
+    pub periodic: Vector3D<bool>,
+    /// Only kept up to date when using `PropagationStrategy::SupportCount`
+    #[tsify(type = "any")]
+    support: Option<SupportCounts<Location3D, Direction3D>>,
+    /// Only kept when the rules have count constraints
+    #[tsify(type = "any")]
+    counts: Option<StateCounts>,
+}
+
+impl<T: TileInterface<TileState> + Clone + PartialEq> DynamicSizeGrid3D<T> {
+    /// Updates a tile at the given location and its entry in the entropy heap
+    fn update_tile(&mut self, location: Location3D, state: T) -> Option<()> {
+        let current_state = self.get_tile(location)?;
+
+        if state == *current_state {
+            // no update needed
+            return Some(());
+        }
+
+        let tile_index = self.location_to_index(location);
+        if self.support.is_some() {
+            let neighbours = self.get_neighbours(location);
+            if let Some(support) = &mut self.support {
+                support.tile_updated(neighbours, &self.tiles[tile_index], &state);
+            }
+        }
+        if let Some(counts) = &mut self.counts {
+            counts.tile_updated(&self.tiles[tile_index], &state);
+        }
+        self.tiles[tile_index] = state.clone();
+        self.update_tile_entropy(location);
+        self.update_log.push((location, state));
+
+        Some(())
+    }
+
+    /// Calculates an entropy for the tile at the given location
+    ///
+    /// If the value has changed the last time, the current entry is invalidated and a new one is
+    /// inserted
+    #[inline]
+    fn update_tile_entropy(&mut self, location: Location3D) {
+        let matrix_index = self.location_to_index(location);
+        if let Some(new_entropy) =
+            self.tiles[matrix_index].calculate_entropy(&self.rules.weights, &mut self.rng)
+        {
+            // priority_queue is a max-heap, see `DynamicSizeGrid2D`
+            self.entropy_heap.push(location, Entropy(-new_entropy.0));
+        } else {
+            self.entropy_heap.remove(&location);
+        }
+    }
+
+    pub fn tiles_ref(&self) -> &Vec<T> {
+        &self.tiles
+    }
+}
+
+impl DynamicSizeGrid3D<Tile> {
+    pub fn new(
+        width: usize,
+        height: usize,
+        depth: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_3D, Direction3D>,
+        rng_seed: u64,
+    ) -> Self {
+        Self::new_with_propagation(
+            width,
+            height,
+            depth,
+            rules,
+            rng_seed,
+            PropagationStrategy::default(),
+        )
+    }
+}
+
+impl<T: TileInterface<TileState> + Clone + PartialEq> DynamicSizeGrid3D<T> {
+    pub fn new_with_propagation(
+        width: usize,
+        height: usize,
+        depth: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_3D, Direction3D>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+    ) -> Self {
+        Self::new_periodic(
+            width,
+            height,
+            depth,
+            rules,
+            rng_seed,
+            propagation,
+            Vector3D::default(),
+        )
+    }
+
+    /// Creates a grid that wraps around along the axes set in `periodic`, tiles on opposite
+    /// faces of a periodic axis are neighbours.
+    ///
+    /// `initialize_edges` rules pointing along a periodic axis are ignored, as the grid has no
+    /// faces in that direction.
+    pub fn new_periodic(
+        width: usize,
+        height: usize,
+        depth: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_3D, Direction3D>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+        periodic: Vector3D<bool>,
+    ) -> Self {
+        let compiled_rules = rules.compile();
+        let tiles = vec![
+            T::with_index(compiled_rules.state_index(), rules.possible.iter().copied());
+            width * height * depth
+        ];
+        let mut new = Self {
+            width,
+            height,
+            depth,
+            rules: rules.clone(),
+            compiled_rules,
+            tiles,
+            entropy_heap: PriorityQueue::new(),
+            update_log: Vec::new(),
+            resets: 0,
+            rng: ChaCha8Rng::seed_from_u64(rng_seed),
+            propagation,
+            periodic,
+            support: None,
+            counts: None,
+        };
+        if propagation == PropagationStrategy::SupportCount {
+            new.support = Some(SupportCounts::from_grid(&new));
+        }
+        new.counts = StateCounts::from_grid(&new)
+            .expect("The count constraints can't be met on a grid of this size");
+
+        let mut initial_propagation_queue = VecDeque::new();
+        for (direction, tile_state) in &rules.initialize_edges {
+            // every tile on the face of the grid that `direction` points towards
+            let delta = Delta3D::from(*direction);
+            if (delta.x != 0 && periodic.x)
+                || (delta.y != 0 && periodic.y)
+                || (delta.z != 0 && periodic.z)
+            {
+                continue;
+            }
+            let edge_tile_locations: Vec<_> = new
+                .positions()
+                .filter(|location| match delta {
+                    Delta3D { x: -1, .. } => location.x == 0,
+                    Delta3D { x: 1, .. } => location.x == width - 1,
+                    Delta3D { y: -1, .. } => location.y == 0,
+                    Delta3D { y: 1, .. } => location.y == height - 1,
+                    Delta3D { z: -1, .. } => location.z == 0,
+                    Delta3D { z: 1, .. } => location.z == depth - 1,
+                    _ => unreachable!(),
+                })
+                .collect();
+            for location in edge_tile_locations {
+                new.with_tile(location, |t, _| {
+                    t.set_possible_states([*tile_state]);
+                });
+                initial_propagation_queue.extend(propagate_from_tile(&new, location));
+            }
+        }
+
+        new.propagate(initial_propagation_queue).expect(
+            "Propagation got interrupted after an edge was collapsed, please revise your ruleset",
+        );
+
+        for location in new.positions().collect::<Vec<_>>() {
+            new.update_tile_entropy(location);
+        }
+
+        new
+    }
+
+    /// Using a 1D array for storing 3D locations requires a bit of additional math
+    #[inline]
+    fn index_to_location(&self, i: usize) -> Location3D {
+        let x = i % self.width;
+        let y = (i / self.width) % self.height;
+        let z = i / (self.width * self.height);
+        Location3D { x, y, z }
+    }
+
+    /// Using a 1D array for storing 3D locations requires a bit of additional math
+    #[inline]
+    fn location_to_index(&self, location: Location3D) -> usize {
+        (location.z * self.height + location.y) * self.width + location.x
+    }
+
+    #[inline]
+    fn contains(&self, location: Location3D) -> bool {
+        location.x < self.width && location.y < self.height && location.z < self.depth
+    }
+}
+
+// See `GridInterface` for further documentation
+impl<T: TileInterface<TileState> + Clone + PartialEq>
+    GridInterface<NEIGHBOUR_COUNT_3D, TileState, Location3D, Direction3D, T>
+    for DynamicSizeGrid3D<T>
+{
+    fn get_dimensions(&self) -> Location3D {
+        Location3D {
+            x: self.width,
+            y: self.height,
+            z: self.depth,
+        }
+    }
+
+    fn reset(&mut self) {
+        let update_log = self.update_log.clone();
+        let resets = self.resets + 1;
+        *self = Self::new_periodic(
+            self.width,
+            self.height,
+            self.depth,
+            self.rules.clone(),
+            self.rng.random(),
+            self.propagation,
+            self.periodic,
+        );
+        self.update_log = update_log;
+        self.resets = resets;
+    }
+
+    fn image(&self) -> HashMap<Location3D, T> {
+        let mut map = HashMap::new();
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let position = self.index_to_location(i);
+            map.insert(position, tile.clone());
+        }
+        map
+    }
+
+    fn get_tiles_at_time(&self, time_index: usize) -> HashMap<Location3D, T> {
+        let mut tiles = HashMap::new();
+        let mut i = 0;
+        for (location, new_state) in &self.update_log {
+            tiles.insert(*location, new_state.clone());
+            i += 1;
+            if i > time_index {
+                break;
+            }
+        }
+        tiles
+    }
+
+    fn get_tile(&self, location: Location3D) -> Option<&T> {
+        if !self.contains(location) {
+            return None;
+        }
+        let index = self.location_to_index(location);
+        self.tiles.get(index)
+    }
+
+    fn get_neighbours(
+        &self,
+        location: Location3D,
+    ) -> [(Direction3D, Option<Location3D>); NEIGHBOUR_COUNT_3D] {
+        // index is 0..6
+        std::array::from_fn(|index| {
+            let direction = Direction3D::try_from(index).unwrap();
+            let direction_delta = Delta3D::from(direction);
+            let x = apply_on_axis(location.x, direction_delta.x, self.width, self.periodic.x);
+            let y = apply_on_axis(location.y, direction_delta.y, self.height, self.periodic.y);
+            let z = apply_on_axis(location.z, direction_delta.z, self.depth, self.periodic.z);
+            let location = match (x, y, z) {
+                (Some(x), Some(y), Some(z)) => Some(Location3D { x, y, z }),
+                _ => None,
+            };
+            (direction, location)
+        })
+    }
+
+    fn get_neighbour_tiles(
+        &self,
+        location: Location3D,
+    ) -> [(Direction3D, Option<&T>); NEIGHBOUR_COUNT_3D] {
+        let locations = self.get_neighbours(location);
+        std::array::from_fn(|index| {
+            let (direction, neighbour_location) = locations[index];
+            let neighbour = if let Some(neighbour_location) = neighbour_location {
+                self.get_tile(neighbour_location)
+            } else {
+                None
+            };
+            (direction, neighbour)
+        })
+    }
+
+    fn get_lowest_entropy_position(&mut self) -> Option<Location3D> {
+        self.entropy_heap
+            .peek()
+            .map(|(location, _entropy)| *location)
+    }
+
+    fn with_tile<R, F: Fn(&mut T, &mut ChaCha8Rng) -> R>(
+        &mut self,
+        location: Location3D,
+        f: F,
+    ) -> Option<R> {
+        // give the caller mutable access to a copied version of the tile
+        let mut mutable_copy = self.get_tile(location)?.clone();
+        let result = f(&mut mutable_copy, &mut self.rng);
+        // update the actual tile, updating the entropy heap if needed
+        self.update_tile(location, mutable_copy)?;
+        Some(result)
+    }
+
+    fn get_rules(&self) -> &RuleSet<NEIGHBOUR_COUNT_3D, Direction3D> {
+        &self.rules
+    }
+
+    fn get_compiled_rules(&self) -> &CompiledRuleSet<Direction3D> {
+        &self.compiled_rules
+    }
+
+    fn positions(&self) -> impl Iterator<Item = Location3D> {
+        (0..(self.width * self.height * self.depth)).map(|i| self.index_to_location(i))
+    }
+
+    fn get_support_counts(&self) -> Option<&SupportCounts<Location3D, Direction3D>> {
+        self.support.as_ref()
+    }
+
+    fn get_state_counts(&self) -> Option<&StateCounts> {
+        self.counts.as_ref()
+    }
+
+    fn get_update_log(&self) -> Option<&[(Location3D, T)]> {
+        Some(&self.update_log)
+    }
+
+    fn get_reset_count(&self) -> usize {
+        self.resets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    use super::*;
+    use crate::{grid::tests::assert_tile_state, rules::RuleSet3D};
+
+    fn init_id(w: usize, h: usize, d: usize) -> DynamicSizeGrid3D<Tile> {
+        let rules = RuleSet::new(
+            BTreeSet::new(),
+            HashSet::new(),
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+        let mut grid = DynamicSizeGrid3D::new(w, h, d, rules, 0);
+        assert_eq!(grid.tiles.len(), w * h * d);
+        for location in grid.positions().collect::<Vec<_>>() {
+            let unique = grid.location_to_index(location) as TileState;
+            grid.with_tile(location, |t, _| t.set_possible_states([unique]));
+        }
+
+        grid
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let grid = init_id(3, 4, 5);
+        let locations: HashSet<_> = grid.positions().collect();
+        assert_eq!(locations.len(), 3 * 4 * 5);
+        for (i, location) in grid.positions().enumerate() {
+            assert!(grid.contains(location));
+            assert_eq!(grid.location_to_index(location), i);
+            assert_tile_state(&grid.get_tile(location).unwrap(), i as TileState);
+        }
+        assert!(grid.get_tile(Location3D { x: 3, y: 0, z: 0 }).is_none());
+        assert!(grid.get_tile(Location3D { x: 0, y: 4, z: 0 }).is_none());
+        assert!(grid.get_tile(Location3D { x: 0, y: 0, z: 5 }).is_none());
+    }
+
+    #[test]
+    fn get_neighbours_sanity() {
+        let grid = init_id(3, 3, 3);
+
+        let corner = grid.get_neighbours(Location3D { x: 0, y: 0, z: 0 });
+        let present: Vec<_> = corner
+            .iter()
+            .filter_map(|(direction, neighbour)| neighbour.map(|_| *direction))
+            .collect();
+        assert_eq!(
+            present,
+            vec![Direction3D::RIGHT, Direction3D::DOWN, Direction3D::FORWARDS]
+        );
+
+        let middle = Location3D { x: 1, y: 1, z: 1 };
+        for (direction, neighbour) in grid.get_neighbours(middle) {
+            let neighbour = neighbour.expect("the middle tile should have all neighbours");
+            assert_eq!(middle.delta(neighbour).unwrap(), Delta3D::from(direction));
+        }
+    }
+
+    #[test]
+    fn update_entropy() {
+        let mut grid = init_id(2, 2, 2);
+        for location in grid.positions().collect::<Vec<_>>() {
+            grid.with_tile(location, |t, _| t.set_possible_states([1, 2, 3, 4]));
+        }
+        let target = Location3D { x: 1, y: 0, z: 1 };
+        grid.with_tile(target, |t, _| t.set_possible_states([1, 2]));
+        assert_eq!(grid.get_lowest_entropy_position(), Some(target));
+    }
+
+    #[test]
+    fn edge_initialization_all_faces() {
+        const STATE_A: TileState = 0;
+        const STATE_EDGE: TileState = 1;
+
+        for index in 0..NEIGHBOUR_COUNT_3D {
+            let direction = Direction3D::try_from(index).unwrap();
+            let mut allowed = HashSet::new();
+            for state in [STATE_A, STATE_EDGE] {
+                for other in [STATE_A, STATE_EDGE] {
+                    allowed.insert((state, Direction3D::RIGHT, other));
+                    allowed.insert((state, Direction3D::DOWN, other));
+                    allowed.insert((state, Direction3D::FORWARDS, other));
+                }
+            }
+            let rules = RuleSet3D::new(
+                BTreeSet::from([STATE_A, STATE_EDGE]),
+                allowed,
+                HashMap::new(),
+                HashMap::new(),
+                BTreeMap::from([(direction, STATE_EDGE)]),
+            );
+            let grid = DynamicSizeGrid3D::new(3, 4, 5, rules, 0);
+            for location in grid.positions() {
+                let on_face =
+                    grid.get_neighbours(location)
+                        .iter()
+                        .any(|(neighbour_direction, neighbour)| {
+                            *neighbour_direction == direction && neighbour.is_none()
+                        });
+                let tile = grid.get_tile(location).unwrap();
+                if on_face {
+                    assert_tile_state(&tile, STATE_EDGE);
+                } else {
+                    assert_eq!(tile.possible_states().count(), 2, "{direction:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! A Grid of hexagons that can be initialized at any size
+//!
+//! The grid is a rectangle of `width` columns and `height` rows in "odd-r" layout, every odd row
+//! is shifted half a hexagon to the east. Tiles are addressed with axial `LocationHex`
+//! coordinates, see `LocationHex::to_offset` for how they map to columns and rows.
+
+use std::collections::{HashMap, VecDeque};
+
+use priority_queue::PriorityQueue;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    rules::{RuleSet, compiled::CompiledRuleSet},
+    tile::{Tile, TileState, interface::TileInterface},
+    utils::{
+        entropy::Entropy,
+        space::s2d_hex::{DeltaHex, DirectionHex, LocationHex, NEIGHBOUR_COUNT_HEX},
+    },
+    wave_function_collapse::{
+        constraints::StateCounts,
+        interface::WaveFunctionCollapse,
+        propagate_from_tile,
+        propagation::{PropagationStrategy, SupportCounts},
+    },
+};
+
+use super::GridInterface;
+
+/// `T` selects how the possible states of each tile are stored, see `Tile` and `BitsetTile`
+#[derive(Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DynamicSizeHexGrid<T> {
+    #[tsify(type = "RuleSet<DirectionHex>")]
+    pub rules: RuleSet<NEIGHBOUR_COUNT_HEX, DirectionHex>,
+    #[tsify(type = "any")]
+    compiled_rules: CompiledRuleSet<DirectionHex>,
+    /// Amount of columns
+    pub width: usize,
+    /// Amount of rows
+    pub height: usize,
+    // A one dimensional array is used for potentionally better performance
+    // (cache locality, fewer bounds checks - if enabled)
+    tiles: Vec<T>,
+    /// Priority queue based on tile entropy
+    #[tsify(type = "any")]
+    entropy_heap: PriorityQueue<LocationHex, Entropy>,
+    /// Keeps history of tile modifications for UI
+    pub update_log: Vec<(LocationHex, T)>,
+    /// How often the grid has been reset, which changes every tile without logging it
+    #[serde(default)]
+    resets: usize,
+    /// Dictates random events
+    #[tsify(type = "any")]
+    rng: ChaCha8Rng,
+    pub propagation: PropagationStrategy,
+    /// Only kept up to date when using `PropagationStrategy::SupportCount`
+    #[tsify(type = "any")]
+    support: Option<SupportCounts<LocationHex, DirectionHex>>,
+    /// Only kept when the rules have count constraints
+    #[tsify(type = "any")]
+    counts: Option<StateCounts>,
+}
+
+impl<T: TileInterface<TileState> + Clone + PartialEq> DynamicSizeHexGrid<T> {
+    /// Updates a tile at the given location and its entry in the entropy heap
+    fn update_tile(&mut self, location: LocationHex, state: T) -> Option<()> {
+        let current_state = self.get_tile(location)?;
+
+        if state == *current_state {
+            // no update needed
+            return Some(());
+        }
+
+        let tile_index = self.location_to_index(location)?;
+        if self.support.is_some() {
+            let neighbours = self.get_neighbours(location);
+            if let Some(support) = &mut self.support {
+                support.tile_updated(neighbours, &self.tiles[tile_index], &state);
+            }
+        }
+        if let Some(counts) = &mut self.counts {
+            counts.tile_updated(&self.tiles[tile_index], &state);
+        }
+        self.tiles[tile_index] = state.clone();
+        self.update_tile_entropy(location);
+        self.update_log.push((location, state));
+
+        Some(())
+    }
+
+    /// Calculates an entropy for the tile at the given location
+    ///
+    /// If the value has changed the last time, the current entry is invalidated and a new one is
+    /// inserted
+    #[inline]
+    fn update_tile_entropy(&mut self, location: LocationHex) {
+        let Some(matrix_index) = self.location_to_index(location) else {
+            return;
+        };
+        if let Some(new_entropy) =
+            self.tiles[matrix_index].calculate_entropy(&self.rules.weights, &mut self.rng)
+        {
+            // priority_queue is a max-heap, see `DynamicSizeGrid2D`
+            self.entropy_heap.push(location, Entropy(-new_entropy.0));
+        } else {
+            self.entropy_heap.remove(&location);
+        }
+    }
+
+    pub fn tiles_ref(&self) -> &Vec<T> {
+        &self.tiles
+    }
+}
+
+impl DynamicSizeHexGrid<Tile> {
+    pub fn new(
+        width: usize,
+        height: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_HEX, DirectionHex>,
+        rng_seed: u64,
+    ) -> Self {
+        Self::new_with_propagation(
+            width,
+            height,
+            rules,
+            rng_seed,
+            PropagationStrategy::default(),
+        )
+    }
+}
+
+impl<T: TileInterface<TileState> + Clone + PartialEq> DynamicSizeHexGrid<T> {
+    pub fn new_with_propagation(
+        width: usize,
+        height: usize,
+        rules: RuleSet<NEIGHBOUR_COUNT_HEX, DirectionHex>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+    ) -> Self {
+        let compiled_rules = rules.compile();
+        let tiles = vec![
+            T::with_index(compiled_rules.state_index(), rules.possible.iter().copied());
+            width * height
+        ];
+        let mut new = Self {
+            width,
+            height,
+            rules: rules.clone(),
+            compiled_rules,
+            tiles,
+            entropy_heap: PriorityQueue::new(),
+            update_log: Vec::new(),
+            resets: 0,
+            rng: ChaCha8Rng::seed_from_u64(rng_seed),
+            propagation,
+            support: None,
+            counts: None,
+        };
+        if propagation == PropagationStrategy::SupportCount {
+            new.support = Some(SupportCounts::from_grid(&new));
+        }
+        new.counts = StateCounts::from_grid(&new)
+            .expect("The count constraints can't be met on a grid of this size");
+
+        let mut initial_propagation_queue = VecDeque::new();
+        for (direction, tile_state) in &rules.initialize_edges {
+            // the border of a hex map is jagged, so an edge is made out of every tile that is
+            // missing a neighbour in `direction`
+            let direction_index = *direction as usize;
+            let edge_tile_locations: Vec<_> = new
+                .positions()
+                .filter(|location| new.get_neighbours(*location)[direction_index].1.is_none())
+                .collect();
+            for location in edge_tile_locations {
+                new.with_tile(location, |t, _| {
+                    t.set_possible_states([*tile_state]);
+                });
+                initial_propagation_queue.extend(propagate_from_tile(&new, location));
+            }
+        }
+
+        new.propagate(initial_propagation_queue).expect(
+            "Propagation got interrupted after an edge was collapsed, please revise your ruleset",
+        );
+
+        for location in new.positions().collect::<Vec<_>>() {
+            new.update_tile_entropy(location);
+        }
+
+        new
+    }
+
+    /// Using a 1D array for storing hex locations requires converting them to offset
+    /// coordinates first
+    #[inline]
+    fn index_to_location(&self, i: usize) -> LocationHex {
+        let column = (i % self.width) as isize;
+        let row = (i / self.width) as isize;
+        LocationHex::from_offset(column, row)
+    }
+
+    /// Returns None if the location falls outside the grid
+    #[inline]
+    fn location_to_index(&self, location: LocationHex) -> Option<usize> {
+        let (column, row) = location.to_offset();
+        let column = usize::try_from(column).ok().filter(|c| *c < self.width)?;
+        let row = usize::try_from(row).ok().filter(|r| *r < self.height)?;
+        Some(row * self.width + column)
+    }
+}
+
+// See `GridInterface` for further documentation
+impl<T: TileInterface<TileState> + Clone + PartialEq>
+    GridInterface<NEIGHBOUR_COUNT_HEX, TileState, LocationHex, DirectionHex, T>
+    for DynamicSizeHexGrid<T>
+{
+    /// Returns the amount of columns as `q` and the amount of rows as `r`
+    fn get_dimensions(&self) -> LocationHex {
+        LocationHex {
+            q: self.width as isize,
+            r: self.height as isize,
+        }
+    }
+
+    fn reset(&mut self) {
+        let update_log = self.update_log.clone();
+        let resets = self.resets + 1;
+        *self = Self::new_with_propagation(
+            self.width,
+            self.height,
+            self.rules.clone(),
+            self.rng.random(),
+            self.propagation,
+        );
+        self.update_log = update_log;
+        self.resets = resets;
+    }
+
+    fn image(&self) -> HashMap<LocationHex, T> {
+        let mut map = HashMap::new();
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let position = self.index_to_location(i);
+            map.insert(position, tile.clone());
+        }
+        map
+    }
+
+    fn get_tiles_at_time(&self, time_index: usize) -> HashMap<LocationHex, T> {
+        let mut tiles = HashMap::new();
+        let mut i = 0;
+        for (location, new_state) in &self.update_log {
+            tiles.insert(*location, new_state.clone());
+            i += 1;
+            if i > time_index {
+                break;
+            }
+        }
+        tiles
+    }
+
+    fn get_tile(&self, location: LocationHex) -> Option<&T> {
+        let index = self.location_to_index(location)?;
+        self.tiles.get(index)
+    }
+
+    fn get_neighbours(
+        &self,
+        location: LocationHex,
+    ) -> [(DirectionHex, Option<LocationHex>); NEIGHBOUR_COUNT_HEX] {
+        // index is 0..6
+        std::array::from_fn(|index| {
+            let direction = DirectionHex::try_from(index).unwrap();
+            let neighbour_location = location.apply(DeltaHex::from(direction));
+            let location = self
+                .location_to_index(neighbour_location)
+                .map(|_| neighbour_location);
+            (direction, location)
+        })
+    }
+
+    fn get_neighbour_tiles(
+        &self,
+        location: LocationHex,
+    ) -> [(DirectionHex, Option<&T>); NEIGHBOUR_COUNT_HEX] {
+        let locations = self.get_neighbours(location);
+        std::array::from_fn(|index| {
+            let (direction, neighbour_location) = locations[index];
+            let neighbour = if let Some(neighbour_location) = neighbour_location {
+                self.get_tile(neighbour_location)
+            } else {
+                None
+            };
+            (direction, neighbour)
+        })
+    }
+
+    fn get_lowest_entropy_position(&mut self) -> Option<LocationHex> {
+        self.entropy_heap
+            .peek()
+            .map(|(location, _entropy)| *location)
+    }
+
+    fn with_tile<R, F: Fn(&mut T, &mut ChaCha8Rng) -> R>(
+        &mut self,
+        location: LocationHex,
+        f: F,
+    ) -> Option<R> {
+        // give the caller mutable access to a copied version of the tile
+        let mut mutable_copy = self.get_tile(location)?.clone();
+        let result = f(&mut mutable_copy, &mut self.rng);
+        // update the actual tile, updating the entropy heap if needed
+        self.update_tile(location, mutable_copy)?;
+        Some(result)
+    }
+
+    fn get_rules(&self) -> &RuleSet<NEIGHBOUR_COUNT_HEX, DirectionHex> {
+        &self.rules
+    }
+
+    fn get_compiled_rules(&self) -> &CompiledRuleSet<DirectionHex> {
+        &self.compiled_rules
+    }
+
+    fn positions(&self) -> impl Iterator<Item = LocationHex> {
+        (0..(self.width * self.height)).map(|i| self.index_to_location(i))
+    }
+
+    fn get_support_counts(&self) -> Option<&SupportCounts<LocationHex, DirectionHex>> {
+        self.support.as_ref()
+    }
+
+    fn get_state_counts(&self) -> Option<&StateCounts> {
+        self.counts.as_ref()
+    }
+
+    fn get_update_log(&self) -> Option<&[(LocationHex, T)]> {
+        Some(&self.update_log)
+    }
+
+    fn get_reset_count(&self) -> usize {
+        self.resets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    use super::*;
+    use crate::{grid::tests::assert_tile_state, rules::RuleSetHex};
+
+    fn init_id(w: usize, h: usize) -> DynamicSizeHexGrid<Tile> {
+        let rules = RuleSet::new(
+            BTreeSet::new(),
+            HashSet::new(),
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+        let mut grid = DynamicSizeHexGrid::new(w, h, rules, 0);
+        assert_eq!(grid.tiles.len(), w * h);
+        for location in grid.positions().collect::<Vec<_>>() {
+            let unique = grid.location_to_index(location).unwrap() as TileState;
+            grid.with_tile(location, |t, _| t.set_possible_states([unique]));
+        }
+
+        grid
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let grid = init_id(4, 5);
+        let locations: HashSet<_> = grid.positions().collect();
+        assert_eq!(locations.len(), 4 * 5);
+        for (i, location) in grid.positions().enumerate() {
+            assert_eq!(grid.location_to_index(location), Some(i));
+            assert_tile_state(&grid.get_tile(location).unwrap(), i as TileState);
+        }
+        assert!(grid.get_tile(LocationHex::from_offset(4, 0)).is_none());
+        assert!(grid.get_tile(LocationHex::from_offset(0, 5)).is_none());
+        assert!(grid.get_tile(LocationHex::from_offset(-1, 1)).is_none());
+    }
+
+    #[test]
+    fn get_neighbours_sanity() {
+        let grid = init_id(4, 4);
+
+        // the first row isn't shifted, so the top left corner only has two neighbours
+        let corner = grid.get_neighbours(LocationHex::from_offset(0, 0));
+        let present: Vec<_> = corner
+            .iter()
+            .filter_map(|(direction, neighbour)| neighbour.map(|_| *direction))
+            .collect();
+        assert_eq!(present, vec![DirectionHex::EAST, DirectionHex::SOUTHEAST]);
+
+        // the second row is shifted to the east, so its first tile touches both tiles above it
+        let shifted = grid.get_neighbours(LocationHex::from_offset(0, 1));
+        let present: Vec<_> = shifted
+            .iter()
+            .filter_map(|(direction, neighbour)| neighbour.map(|_| *direction))
+            .collect();
+        assert_eq!(
+            present,
+            vec![
+                DirectionHex::EAST,
+                DirectionHex::NORTHEAST,
+                DirectionHex::NORTHWEST,
+                DirectionHex::SOUTHWEST,
+                DirectionHex::SOUTHEAST,
+            ]
+        );
+
+        let middle = LocationHex::from_offset(1, 1);
+        for (direction, neighbour) in grid.get_neighbours(middle) {
+            let neighbour = neighbour.expect("the middle tile should have all neighbours");
+            assert_eq!(middle.delta(neighbour), DeltaHex::from(direction));
+            assert_eq!(middle.distance(neighbour), 1);
+        }
+    }
+
+    #[test]
+    fn update_entropy() {
+        let mut grid = init_id(3, 3);
+        for location in grid.positions().collect::<Vec<_>>() {
+            grid.with_tile(location, |t, _| t.set_possible_states([1, 2, 3, 4]));
+        }
+        let target = LocationHex::from_offset(2, 1);
+        grid.with_tile(target, |t, _| t.set_possible_states([1, 2]));
+        assert_eq!(grid.get_lowest_entropy_position(), Some(target));
+    }
+
+    #[test]
+    fn edge_initialization() {
+        const STATE_A: TileState = 0;
+        const STATE_EDGE: TileState = 1;
+
+        let mut allowed = HashSet::new();
+        for state in [STATE_A, STATE_EDGE] {
+            for other in [STATE_A, STATE_EDGE] {
+                for index in 0..NEIGHBOUR_COUNT_HEX {
+                    allowed.insert((state, DirectionHex::try_from(index).unwrap(), other));
+                }
+            }
+        }
+        let rules = RuleSetHex::new(
+            BTreeSet::from([STATE_A, STATE_EDGE]),
+            allowed,
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::from([(DirectionHex::NORTHWEST, STATE_EDGE)]),
+        );
+        let grid = DynamicSizeHexGrid::new(4, 3, rules, 0);
+        for location in grid.positions() {
+            let tile = grid.get_tile(location).unwrap();
+            let (column, row) = location.to_offset();
+            // the first row and the unshifted tiles on the western border
+            if row == 0 || (column == 0 && row % 2 == 0) {
+                assert_tile_state(&tile, STATE_EDGE);
+            } else {
+                assert_eq!(tile.possible_states().count(), 2, "{location:?}");
+            }
+        }
+    }
+}
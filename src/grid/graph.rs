@@ -0,0 +1,625 @@
+//! A Grid made out of the nodes of an arbitrary graph
+//!
+//! Useful for content that isn't a lattice, like room graphs or road networks. Every edge of the
+//! graph is labelled with a user-defined `Direction`, and each node can have at most one
+//! neighbour per label. Rules are written against the labels the same way as against
+//! `Direction2D`, so rulesets and backtrackers work on graphs without changes.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+};
+
+use priority_queue::PriorityQueue;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rules::{RuleSet, compiled::CompiledRuleSet},
+    tile::{Tile, TileState, interface::TileInterface},
+    utils::{
+        entropy::Entropy,
+        space::{Direction, graph::GraphNode},
+    },
+    wave_function_collapse::{
+        constraints::StateCounts,
+        interface::{WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+        propagate_from_tile,
+        propagation::{PropagationStrategy, SupportCounts},
+    },
+};
+
+use super::GridInterface;
+
+/// Serializable description of the shape of a graph
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphDescription<TDirection> {
+    /// Every label an edge can have. The neighbours of a node are reported in this order, so
+    /// there has to be exactly one label per neighbour slot (`N` of them), and the mirror of
+    /// every label has to be listed as well.
+    ///
+    /// `GridInterface::get_neighbours` reports a single neighbour per direction, so a node can
+    /// have at most one neighbour per label. Nodes with more neighbours need more labels, e.g.
+    /// `NORTH_1` and `NORTH_2`, with rules for each of them.
+    pub directions: Vec<TDirection>,
+    pub node_count: usize,
+    /// (A, label, B) places B in `label` of A. The edge back from B to A is added automatically
+    /// with the mirrored label. Giving A a second neighbour with the same label is an error.
+    pub edges: Vec<(usize, TDirection, usize)>,
+}
+
+/// Reasons a `GraphDescription` can't be turned into a `GraphGrid`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GraphError<TDirection: Debug> {
+    #[error("expected {expected} directions, got {actual}")]
+    WrongDirectionCount { expected: usize, actual: usize },
+    #[error("direction {0:?} is listed more than once")]
+    DuplicateDirection(TDirection),
+    #[error("the mirror of direction {0:?} is not listed")]
+    MissingMirror(TDirection),
+    #[error("edge label {0:?} is not one of the listed directions")]
+    UnknownDirection(TDirection),
+    #[error("node {node} doesn't exist, the graph has {node_count} nodes")]
+    UnknownNode { node: usize, node_count: usize },
+    #[error(
+        "node {node} already has neighbour {existing} in direction {direction:?}, can't add {new}"
+    )]
+    ConflictingEdge {
+        node: usize,
+        direction: TDirection,
+        existing: usize,
+        new: usize,
+    },
+    #[error("propagating the edges failed: {0:?}")]
+    Interrupted(WaveFunctionCollapseInterruption<GraphNode>),
+}
+
+/// `T` selects how the possible states of each tile are stored, see `Tile` and `BitsetTile`
+#[derive(Serialize, Deserialize)]
+pub struct GraphGrid<const N: usize, TDirection: Direction<N>, T> {
+    pub rules: RuleSet<N, TDirection>,
+    compiled_rules: CompiledRuleSet<TDirection>,
+    /// Kept around for resetting the grid
+    description: GraphDescription<TDirection>,
+    /// node -> neighbour in each of `description.directions`
+    neighbours: Vec<Vec<Option<usize>>>,
+    tiles: Vec<T>,
+    /// Priority queue based on tile entropy
+    entropy_heap: PriorityQueue<GraphNode, Entropy>,
+    /// Keeps history of tile modifications for UI
+    pub update_log: Vec<(GraphNode, T)>,
+    /// How often the grid has been reset, which changes every tile without logging it
+    #[serde(default)]
+    resets: usize,
+    /// Dictates random events
+    rng: ChaCha8Rng,
+    pub propagation: PropagationStrategy,
+    /// Only kept up to date when using `PropagationStrategy::SupportCount`
+    support: Option<SupportCounts<GraphNode, TDirection>>,
+    /// Only kept when the rules have count constraints
+    counts: Option<StateCounts>,
+}
+
+impl<TDirection: Debug> GraphDescription<TDirection> {
+    /// Checks the description and builds the neighbour lists of every node
+    fn build_neighbours<const N: usize>(
+        &self,
+    ) -> Result<Vec<Vec<Option<usize>>>, GraphError<TDirection>>
+    where
+        TDirection: Direction<N>,
+    {
+        if self.directions.len() != N {
+            return Err(GraphError::WrongDirectionCount {
+                expected: N,
+                actual: self.directions.len(),
+            });
+        }
+        for (i, direction) in self.directions.iter().enumerate() {
+            if self.directions[..i].contains(direction) {
+                return Err(GraphError::DuplicateDirection(*direction));
+            }
+        }
+        for direction in &self.directions {
+            if !self.directions.contains(&direction.mirror()) {
+                return Err(GraphError::MissingMirror(*direction));
+            }
+        }
+
+        let direction_index = |direction: TDirection| {
+            self.directions
+                .iter()
+                .position(|listed| *listed == direction)
+                .ok_or(GraphError::UnknownDirection(direction))
+        };
+        let mut neighbours = vec![vec![None; N]; self.node_count];
+        for &(node, direction, neighbour) in &self.edges {
+            for endpoint in [node, neighbour] {
+                if endpoint >= self.node_count {
+                    return Err(GraphError::UnknownNode {
+                        node: endpoint,
+                        node_count: self.node_count,
+                    });
+                }
+            }
+            for (from, direction, to) in [
+                (node, direction, neighbour),
+                (neighbour, direction.mirror(), node),
+            ] {
+                let slot = &mut neighbours[from][direction_index(direction)?];
+                match slot {
+                    Some(existing) if *existing != to => {
+                        return Err(GraphError::ConflictingEdge {
+                            node: from,
+                            direction,
+                            existing: *existing,
+                            new: to,
+                        });
+                    }
+                    _ => *slot = Some(to),
+                }
+            }
+        }
+        Ok(neighbours)
+    }
+}
+
+impl<
+    const N: usize,
+    TDirection: Direction<N> + Debug,
+    T: TileInterface<TileState> + Clone + PartialEq,
+> GraphGrid<N, TDirection, T>
+{
+    /// Updates a tile at the given location and its entry in the entropy heap
+    fn update_tile(&mut self, location: GraphNode, state: T) -> Option<()> {
+        let current_state = self.get_tile(location)?;
+
+        if state == *current_state {
+            // no update needed
+            return Some(());
+        }
+
+        if self.support.is_some() {
+            let neighbours = self.get_neighbours(location);
+            if let Some(support) = &mut self.support {
+                support.tile_updated(neighbours, &self.tiles[location.0], &state);
+            }
+        }
+        if let Some(counts) = &mut self.counts {
+            counts.tile_updated(&self.tiles[location.0], &state);
+        }
+        self.tiles[location.0] = state.clone();
+        self.update_tile_entropy(location);
+        self.update_log.push((location, state));
+
+        Some(())
+    }
+
+    /// Calculates an entropy for the tile at the given location
+    ///
+    /// If the value has changed the last time, the current entry is invalidated and a new one is
+    /// inserted
+    #[inline]
+    fn update_tile_entropy(&mut self, location: GraphNode) {
+        if let Some(new_entropy) =
+            self.tiles[location.0].calculate_entropy(&self.rules.weights, &mut self.rng)
+        {
+            // priority_queue is a max-heap, see `DynamicSizeGrid2D`
+            self.entropy_heap.push(location, Entropy(-new_entropy.0));
+        } else {
+            self.entropy_heap.remove(&location);
+        }
+    }
+
+    pub fn tiles_ref(&self) -> &Vec<T> {
+        &self.tiles
+    }
+
+    pub fn description(&self) -> &GraphDescription<TDirection> {
+        &self.description
+    }
+}
+
+impl<const N: usize, TDirection: Direction<N> + Debug> GraphGrid<N, TDirection, Tile> {
+    pub fn new(
+        description: GraphDescription<TDirection>,
+        rules: RuleSet<N, TDirection>,
+        rng_seed: u64,
+    ) -> Result<Self, GraphError<TDirection>> {
+        Self::new_with_propagation(description, rules, rng_seed, PropagationStrategy::default())
+    }
+}
+
+impl<
+    const N: usize,
+    TDirection: Direction<N> + Debug,
+    T: TileInterface<TileState> + Clone + PartialEq,
+> GraphGrid<N, TDirection, T>
+{
+    /// Creates a grid with one tile per node of the graph.
+    ///
+    /// A node counts as being on the edge towards a direction if it has no neighbour in that
+    /// direction, `initialize_edges` is applied to all such nodes. Fails if the description is
+    /// invalid (see `GraphDescription`) or the edges contradict each other.
+    pub fn new_with_propagation(
+        description: GraphDescription<TDirection>,
+        rules: RuleSet<N, TDirection>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+    ) -> Result<Self, GraphError<TDirection>> {
+        let neighbours = description.build_neighbours::<N>()?;
+        Self::from_neighbours(description, neighbours, rules, rng_seed, propagation)
+            .map_err(GraphError::Interrupted)
+    }
+
+    /// Creates the grid from neighbour lists that were already checked
+    fn from_neighbours(
+        description: GraphDescription<TDirection>,
+        neighbours: Vec<Vec<Option<usize>>>,
+        rules: RuleSet<N, TDirection>,
+        rng_seed: u64,
+        propagation: PropagationStrategy,
+    ) -> Result<Self, WaveFunctionCollapseInterruption<GraphNode>> {
+        let compiled_rules = rules.compile();
+        let tiles = vec![
+            T::with_index(compiled_rules.state_index(), rules.possible.iter().copied());
+            description.node_count
+        ];
+        let mut new = Self {
+            rules: rules.clone(),
+            compiled_rules,
+            description,
+            neighbours,
+            tiles,
+            entropy_heap: PriorityQueue::new(),
+            update_log: Vec::new(),
+            resets: 0,
+            rng: ChaCha8Rng::seed_from_u64(rng_seed),
+            propagation,
+            support: None,
+            counts: None,
+        };
+        if propagation == PropagationStrategy::SupportCount {
+            new.support = Some(SupportCounts::from_grid(&new));
+        }
+        new.counts = StateCounts::from_grid(&new)?;
+
+        let mut initial_propagation_queue = VecDeque::new();
+        for (direction, tile_state) in &rules.initialize_edges {
+            let Some(direction_index) = new
+                .description
+                .directions
+                .iter()
+                .position(|listed| listed == direction)
+            else {
+                continue;
+            };
+            let edge_tile_locations: Vec<_> = new
+                .positions()
+                .filter(|location| new.neighbours[location.0][direction_index].is_none())
+                .collect();
+            for location in edge_tile_locations {
+                new.with_tile(location, |t, _| {
+                    t.set_possible_states([*tile_state]);
+                });
+                initial_propagation_queue.extend(propagate_from_tile(&new, location));
+            }
+        }
+
+        new.propagate(initial_propagation_queue)?;
+
+        for location in new.positions().collect::<Vec<_>>() {
+            new.update_tile_entropy(location);
+        }
+
+        Ok(new)
+    }
+}
+
+// See `GridInterface` for further documentation
+impl<
+    const N: usize,
+    TDirection: Direction<N> + Debug,
+    T: TileInterface<TileState> + Clone + PartialEq,
+> GridInterface<N, TileState, GraphNode, TDirection, T> for GraphGrid<N, TDirection, T>
+{
+    /// Returns the amount of nodes
+    fn get_dimensions(&self) -> GraphNode {
+        GraphNode(self.tiles.len())
+    }
+
+    fn reset(&mut self) {
+        let update_log = self.update_log.clone();
+        let resets = self.resets + 1;
+        // propagating the edges doesn't depend on the rng, and it already succeeded when the
+        // grid was created
+        if let Ok(new) = Self::from_neighbours(
+            self.description.clone(),
+            self.neighbours.clone(),
+            self.rules.clone(),
+            self.rng.random(),
+            self.propagation,
+        ) {
+            *self = new;
+        }
+        self.update_log = update_log;
+        self.resets = resets;
+    }
+
+    fn image(&self) -> HashMap<GraphNode, T> {
+        let mut map = HashMap::new();
+        for (i, tile) in self.tiles.iter().enumerate() {
+            map.insert(GraphNode(i), tile.clone());
+        }
+        map
+    }
+
+    fn get_tiles_at_time(&self, time_index: usize) -> HashMap<GraphNode, T> {
+        let mut tiles = HashMap::new();
+        let mut i = 0;
+        for (location, new_state) in &self.update_log {
+            tiles.insert(*location, new_state.clone());
+            i += 1;
+            if i > time_index {
+                break;
+            }
+        }
+        tiles
+    }
+
+    fn get_tile(&self, location: GraphNode) -> Option<&T> {
+        self.tiles.get(location.0)
+    }
+
+    fn get_neighbours(&self, location: GraphNode) -> [(TDirection, Option<GraphNode>); N] {
+        let neighbours = self.neighbours.get(location.0);
+        std::array::from_fn(|index| {
+            let neighbour = neighbours.and_then(|neighbours| neighbours[index]);
+            (self.description.directions[index], neighbour.map(GraphNode))
+        })
+    }
+
+    fn get_neighbour_tiles(&self, location: GraphNode) -> [(TDirection, Option<&T>); N] {
+        let locations = self.get_neighbours(location);
+        std::array::from_fn(|index| {
+            let (direction, neighbour_location) = locations[index];
+            let neighbour = if let Some(neighbour_location) = neighbour_location {
+                self.get_tile(neighbour_location)
+            } else {
+                None
+            };
+            (direction, neighbour)
+        })
+    }
+
+    fn get_lowest_entropy_position(&mut self) -> Option<GraphNode> {
+        self.entropy_heap
+            .peek()
+            .map(|(location, _entropy)| *location)
+    }
+
+    fn with_tile<R, F: Fn(&mut T, &mut ChaCha8Rng) -> R>(
+        &mut self,
+        location: GraphNode,
+        f: F,
+    ) -> Option<R> {
+        // give the caller mutable access to a copied version of the tile
+        let mut mutable_copy = self.get_tile(location)?.clone();
+        let result = f(&mut mutable_copy, &mut self.rng);
+        // update the actual tile, updating the entropy heap if needed
+        self.update_tile(location, mutable_copy)?;
+        Some(result)
+    }
+
+    fn get_rules(&self) -> &RuleSet<N, TDirection> {
+        &self.rules
+    }
+
+    fn get_compiled_rules(&self) -> &CompiledRuleSet<TDirection> {
+        &self.compiled_rules
+    }
+
+    fn positions(&self) -> impl Iterator<Item = GraphNode> {
+        (0..self.tiles.len()).map(GraphNode)
+    }
+
+    fn get_support_counts(&self) -> Option<&SupportCounts<GraphNode, TDirection>> {
+        self.support.as_ref()
+    }
+
+    fn get_state_counts(&self) -> Option<&StateCounts> {
+        self.counts.as_ref()
+    }
+
+    fn get_update_log(&self) -> Option<&[(GraphNode, T)]> {
+        Some(&self.update_log)
+    }
+
+    fn get_reset_count(&self) -> usize {
+        self.resets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    use super::*;
+    use crate::{
+        grid::tests::assert_tile_state,
+        rules::RuleSet1D,
+        utils::space::s1d::{Direction1D, NEIGHBOUR_COUNT_1D},
+    };
+
+    /// A chain of `length` nodes, each node's RIGHT neighbour is the next one
+    fn chain(length: usize) -> GraphDescription<Direction1D> {
+        GraphDescription {
+            directions: vec![Direction1D::RIGHT, Direction1D::LEFT],
+            node_count: length,
+            edges: (1..length)
+                .map(|node| (node - 1, Direction1D::RIGHT, node))
+                .collect(),
+        }
+    }
+
+    fn alternating_rules() -> RuleSet1D {
+        RuleSet1D::new(
+            BTreeSet::from([0, 1]),
+            HashSet::from([(0, Direction1D::RIGHT, 1), (1, Direction1D::RIGHT, 0)]),
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::from([(Direction1D::LEFT, 0)]),
+        )
+    }
+
+    #[test]
+    fn neighbours_follow_edges() {
+        let grid = GraphGrid::new(chain(3), alternating_rules(), 0).unwrap();
+        assert_eq!(
+            grid.get_neighbours(GraphNode(0)),
+            [
+                (Direction1D::RIGHT, Some(GraphNode(1))),
+                (Direction1D::LEFT, None)
+            ]
+        );
+        assert_eq!(
+            grid.get_neighbours(GraphNode(1)),
+            [
+                (Direction1D::RIGHT, Some(GraphNode(2))),
+                (Direction1D::LEFT, Some(GraphNode(0)))
+            ]
+        );
+
+        // the left edge is pinned to 0, so the whole chain alternates
+        for node in 0..3 {
+            assert_tile_state(&grid.get_tile(GraphNode(node)).unwrap(), node as u64 % 2);
+        }
+    }
+
+    #[test]
+    fn invalid_descriptions() {
+        let rules = alternating_rules();
+        let check = |description: GraphDescription<Direction1D>| {
+            GraphGrid::<NEIGHBOUR_COUNT_1D, _, Tile>::new(description, rules.clone(), 0)
+                .err()
+                .expect("the description should be rejected")
+        };
+
+        let mut description = chain(2);
+        description.directions = vec![Direction1D::RIGHT];
+        assert_eq!(
+            check(description),
+            GraphError::WrongDirectionCount {
+                expected: 2,
+                actual: 1
+            }
+        );
+
+        let mut description = chain(2);
+        description.directions = vec![Direction1D::RIGHT, Direction1D::RIGHT];
+        assert_eq!(
+            check(description),
+            GraphError::DuplicateDirection(Direction1D::RIGHT)
+        );
+
+        let mut description = chain(2);
+        description.edges.push((1, Direction1D::RIGHT, 5));
+        assert_eq!(
+            check(description),
+            GraphError::UnknownNode {
+                node: 5,
+                node_count: 2
+            }
+        );
+
+        // 0 already has 1 on its right
+        let mut description = chain(3);
+        description.edges.push((0, Direction1D::RIGHT, 2));
+        assert_eq!(
+            check(description),
+            GraphError::ConflictingEdge {
+                node: 0,
+                direction: Direction1D::RIGHT,
+                existing: 1,
+                new: 2
+            }
+        );
+
+        // both ends of a 2 node chain can't be 0
+        let mut rules = alternating_rules();
+        rules.initialize_edges.insert(Direction1D::RIGHT, 0);
+        assert!(matches!(
+            GraphGrid::<NEIGHBOUR_COUNT_1D, _, Tile>::new(chain(2), rules, 0),
+            Err(GraphError::Interrupted(
+                WaveFunctionCollapseInterruption::Contradiction(_)
+            ))
+        ));
+    }
+
+    /// Has more labels than neighbour slots, so the mirror of a listed label can be left out
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    enum Link {
+        Incoming,
+        Outgoing,
+        Loop,
+    }
+
+    impl Direction<2> for Link {
+        fn mirror(self) -> Self {
+            match self {
+                Link::Incoming => Link::Outgoing,
+                Link::Outgoing => Link::Incoming,
+                Link::Loop => Link::Loop,
+            }
+        }
+    }
+
+    #[test]
+    fn missing_mirror() {
+        let rules = RuleSet::new(
+            BTreeSet::from([0]),
+            HashSet::from([(0, Link::Loop, 0)]),
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+        let description = GraphDescription {
+            directions: vec![Link::Incoming, Link::Loop],
+            node_count: 1,
+            edges: vec![],
+        };
+        assert_eq!(
+            GraphGrid::new(description, rules.clone(), 0).err(),
+            Some(GraphError::MissingMirror(Link::Incoming))
+        );
+
+        let description = GraphDescription {
+            directions: vec![Link::Incoming, Link::Outgoing],
+            node_count: 2,
+            edges: vec![(0, Link::Loop, 1)],
+        };
+        assert_eq!(
+            GraphGrid::new(description, rules, 0).err(),
+            Some(GraphError::UnknownDirection(Link::Loop))
+        );
+    }
+
+    #[test]
+    fn load_from_json() {
+        let json = r#"{
+            "directions": ["RIGHT", "LEFT"],
+            "node_count": 3,
+            "edges": [[0, "RIGHT", 1], [2, "LEFT", 1]]
+        }"#;
+        let description: GraphDescription<Direction1D> = serde_json::from_str(json).unwrap();
+        assert_eq!(description.edges.len(), 2);
+        let grid = GraphGrid::new(description, alternating_rules(), 0).unwrap();
+        assert_eq!(
+            grid.get_neighbours(GraphNode(2)),
+            [
+                (Direction1D::RIGHT, None),
+                (Direction1D::LEFT, Some(GraphNode(1)))
+            ]
+        );
+    }
+}
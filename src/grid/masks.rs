@@ -0,0 +1,201 @@
+//! Restricting the states of individual tiles before solving
+//!
+//! `RuleSet::initialize_edges` can only set a whole side of the grid to a single state.
+//! `StateMasks` instead give any tile a set of allowed states, which is kept by the grid and
+//! applied again whenever the tile is reset.
+//!
+//! Pinning a tile to a single state is a mask as well, see `DynamicSizeGrid2D::pin`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tile::TileState,
+    utils::space::{Location, s2d::Location2D},
+};
+
+/// Reasons a tile can't be pinned to a state. The grid is left as it was.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PinError<TPosition: Debug, TDirection: Debug> {
+    #[error("tile {0:?} is not in the grid")]
+    OutOfBounds(TPosition),
+    #[error("state {0} is not in the rules")]
+    UnknownState(TileState),
+    #[error(
+        "state {state} at {position:?} is not allowed next to any state of the neighbour \
+         {neighbour:?} in direction {direction:?}"
+    )]
+    ConflictsWithNeighbour {
+        position: TPosition,
+        state: TileState,
+        neighbour: TPosition,
+        direction: TDirection,
+    },
+    /// The state was removed by a mask, an earlier pin or a global constraint
+    #[error("state {state} is no longer possible at {position:?}")]
+    Unavailable {
+        position: TPosition,
+        state: TileState,
+    },
+    #[error("pinning {position:?} to {state} leads to a contradiction at {contradiction:?}")]
+    Contradiction {
+        position: TPosition,
+        state: TileState,
+        contradiction: TPosition,
+    },
+}
+
+/// Allowed states for some of the tiles of a grid, tiles without a mask may be in any state
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateMasks<TPosition: Location> {
+    // a BTreeMap keeps the order in which the masks are applied deterministic
+    masks: BTreeMap<TPosition, BTreeSet<TileState>>,
+}
+
+impl<TPosition: Location> StateMasks<TPosition> {
+    pub fn new() -> Self {
+        Self {
+            masks: BTreeMap::new(),
+        }
+    }
+
+    /// Only allows `states` at `position`. Restricting the same tile multiple times only allows
+    /// the states present in all of the masks.
+    pub fn restrict<I: IntoIterator<Item = TileState>>(&mut self, position: TPosition, states: I) {
+        let states: BTreeSet<_> = states.into_iter().collect();
+        self.masks
+            .entry(position)
+            .and_modify(|mask| mask.retain(|state| states.contains(state)))
+            .or_insert(states);
+    }
+
+    /// Only allows `states` in all of the given tiles
+    pub fn restrict_all<P: IntoIterator<Item = TPosition>, I: IntoIterator<Item = TileState>>(
+        &mut self,
+        positions: P,
+        states: I,
+    ) {
+        let states: BTreeSet<_> = states.into_iter().collect();
+        for position in positions {
+            self.restrict(position, states.iter().copied());
+        }
+    }
+
+    /// The allowed states of the tile, or None if it doesn't have a mask
+    pub fn get(&self, position: TPosition) -> Option<&BTreeSet<TileState>> {
+        self.masks.get(&position)
+    }
+
+    /// The states of `possible` that are allowed at `position`
+    pub fn allowed(
+        &self,
+        position: TPosition,
+        possible: &BTreeSet<TileState>,
+    ) -> BTreeSet<TileState> {
+        match self.masks.get(&position) {
+            Some(mask) => possible.intersection(mask).copied().collect(),
+            None => possible.clone(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TPosition, &BTreeSet<TileState>)> {
+        self.masks.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masks.is_empty()
+    }
+}
+
+/// Shapes for 2D grids
+impl StateMasks<Location2D> {
+    /// Only allows `states` in the rectangle between `min` and `max`, both inclusive
+    pub fn restrict_rectangle<I: IntoIterator<Item = TileState>>(
+        &mut self,
+        min: Location2D,
+        max: Location2D,
+        states: I,
+    ) {
+        let positions =
+            (min.y..=max.y).flat_map(|y| (min.x..=max.x).map(move |x| Location2D { x, y }));
+        self.restrict_all(positions, states);
+    }
+
+    /// Only allows `states` on the outermost tiles of a `width` by `height` grid
+    pub fn restrict_border<I: IntoIterator<Item = TileState>>(
+        &mut self,
+        width: usize,
+        height: usize,
+        states: I,
+    ) {
+        let positions = (0..height)
+            .flat_map(|y| (0..width).map(move |x| Location2D { x, y }))
+            .filter(|l| l.x == 0 || l.y == 0 || l.x + 1 == width || l.y + 1 == height);
+        self.restrict_all(positions, states);
+    }
+
+    /// Only allows `states` on the tiles at most `radius` away from `center`. Grids ignore the
+    /// masks of tiles they don't have, so the circle may extend past the edges.
+    pub fn restrict_circle<I: IntoIterator<Item = TileState>>(
+        &mut self,
+        center: Location2D,
+        radius: usize,
+        states: I,
+    ) {
+        let positions = (center.y.saturating_sub(radius)..=center.y + radius)
+            .flat_map(|y| {
+                (center.x.saturating_sub(radius)..=center.x + radius)
+                    .map(move |x| Location2D { x, y })
+            })
+            .filter(|l| {
+                let dx = l.x.abs_diff(center.x);
+                let dy = l.y.abs_diff(center.y);
+                dx * dx + dy * dy <= radius * radius
+            });
+        self.restrict_all(positions, states);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restrictions_intersect() {
+        let mut masks = StateMasks::new();
+        let origin = Location2D { x: 0, y: 0 };
+        masks.restrict(origin, [0, 1, 2]);
+        masks.restrict(origin, [1, 2, 3]);
+        assert_eq!(masks.get(origin), Some(&BTreeSet::from([1, 2])));
+        assert_eq!(
+            masks.allowed(origin, &BTreeSet::from([2, 3])),
+            BTreeSet::from([2])
+        );
+        assert_eq!(
+            masks.allowed(Location2D { x: 1, y: 0 }, &BTreeSet::from([2, 3])),
+            BTreeSet::from([2, 3])
+        );
+    }
+
+    #[test]
+    fn shapes() {
+        let mut masks = StateMasks::new();
+        masks.restrict_border(4, 3, [0]);
+        assert_eq!(masks.iter().count(), 10);
+        assert!(masks.get(Location2D { x: 1, y: 1 }).is_none());
+        assert!(masks.get(Location2D { x: 3, y: 1 }).is_some());
+
+        let mut masks = StateMasks::new();
+        masks.restrict_rectangle(Location2D { x: 1, y: 1 }, Location2D { x: 2, y: 3 }, [0]);
+        assert_eq!(masks.iter().count(), 6);
+
+        let mut masks = StateMasks::new();
+        masks.restrict_circle(Location2D { x: 1, y: 1 }, 1, [0]);
+        assert_eq!(masks.iter().count(), 5);
+        assert!(masks.get(Location2D { x: 0, y: 0 }).is_none());
+    }
+}
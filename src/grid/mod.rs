@@ -1,17 +1,27 @@
 //! Grid implementations and common test components for them
 
-use std::{collections::HashMap, hash::Hash};
+use std::{borrow::Cow, collections::HashMap, hash::Hash};
 
 use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    rules::RuleSet,
-    tile::interface::TileInterface,
+    grid::{masks::StateMasks, weights::WeightField},
+    rules::{RuleSet, compiled::CompiledRuleSet},
+    tile::{TileState, interface::TileInterface},
     utils::space::{Direction, Location},
+    wave_function_collapse::{
+        connectivity::ConnectivityConstraint, constraints::StateCounts, propagation::SupportCounts,
+        symmetry::SymmetryConstraint,
+    },
 };
 
 pub mod constant_2d;
 pub mod dynamic_2d;
+pub mod dynamic_3d;
+pub mod dynamic_hex;
+pub mod graph;
+pub mod masks;
+pub mod weights;
 // 1d version of the grid is not a part of the core algorithm
 // as such, it won't be unit tested
 #[cfg(not(tarpaulin_include))]
@@ -71,6 +81,73 @@ pub trait GridInterface<
     /// correctly.
     fn get_rules(&self) -> &RuleSet<NEIGHBOURS_PER_TILE, TDirection>;
 
+    /// Returns the rules of the grid with precomputed state indices and adjacency tables, used
+    /// in the hot paths of the solver
+    fn get_compiled_rules(&self) -> &CompiledRuleSet<TDirection>;
+
     /// Returns an iterator over all valid tile positions in the grid
     fn positions(&self) -> impl Iterator<Item = TPosition>;
+
+    /// Returns the support counters of the grid, if it was set up to propagate changes by
+    /// counting supports. Otherwise changes are propagated with pairwise checks.
+    fn get_support_counts(&self) -> Option<&SupportCounts<TPosition, TDirection>> {
+        None
+    }
+
+    /// Returns the counters of constrained states, if the rules of the grid have count
+    /// constraints
+    fn get_state_counts(&self) -> Option<&StateCounts> {
+        None
+    }
+
+    /// Returns the allowed states of individual tiles, if the grid has any. Tiles that are
+    /// reset should only get the states allowed by their mask.
+    fn get_state_masks(&self) -> Option<&StateMasks<TPosition>> {
+        None
+    }
+
+    /// Returns the connectivity constraint of the grid, if it has one
+    fn get_connectivity(&self) -> Option<&ConnectivityConstraint<TPosition>> {
+        None
+    }
+
+    /// Returns the symmetry constraint of the grid, if it has one
+    fn get_symmetry(&self) -> Option<&SymmetryConstraint<TPosition>> {
+        None
+    }
+
+    /// Returns the per tile weight multipliers of the grid, if it has any
+    fn get_weight_field(&self) -> Option<&WeightField<TPosition>> {
+        None
+    }
+
+    /// Returns every tile update in the order it happened, if the grid keeps a log. Lets
+    /// backtrackers find the tiles that changed without scanning the whole grid, so entries
+    /// should never be removed from it.
+    fn get_update_log(&self) -> Option<&[(TPosition, T)]> {
+        None
+    }
+
+    /// Returns how often the grid has been reset. Resets change every tile without logging
+    /// them, so the changes across a reset can't be found in the update log.
+    fn get_reset_count(&self) -> usize {
+        0
+    }
+
+    /// Returns the weights of the states at `location`: the weights of the rules, scaled by the
+    /// weight field of the grid if it has one
+    fn get_weights<'a>(&'a self, location: TPosition) -> Cow<'a, HashMap<TileState, usize>>
+    where
+        TState: Into<TileState>,
+        TDirection: 'a,
+    {
+        let weights = &self.get_rules().weights;
+        match (self.get_weight_field(), self.get_tile(location)) {
+            (Some(field), Some(tile)) => {
+                let states = tile.possible_states().map(|state| state.into());
+                Cow::Owned(field.weights_at(location, states, weights))
+            }
+            _ => Cow::Borrowed(weights),
+        }
+    }
 }
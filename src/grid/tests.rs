@@ -3,6 +3,7 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use crate::{
+    grid::dynamic_2d::DynamicSizeGrid2D,
     rules::RuleSet2D,
     tile::{Tile, TileState, interface::TileInterface},
     utils::space::s2d::{Direction2D, Location2D, NEIGHBOUR_COUNT_2D},
@@ -10,11 +11,41 @@ use crate::{
 
 use super::GridInterface;
 
+/// Rules where all of `states` are allowed next to each other in any combination
+pub fn any_combination_rules<I: IntoIterator<Item = TileState>>(states: I) -> RuleSet2D {
+    let states = BTreeSet::from_iter(states);
+    let mut allowed = HashSet::new();
+    for &a in &states {
+        for &b in &states {
+            allowed.insert((a, Direction2D::RIGHT, b));
+            allowed.insert((a, Direction2D::DOWN, b));
+        }
+    }
+    RuleSet2D::new(
+        states,
+        allowed,
+        HashMap::new(),
+        HashMap::new(),
+        BTreeMap::new(),
+    )
+}
+
+/// A grid following `any_combination_rules` of `states`
+pub fn any_combination_grid<I: IntoIterator<Item = TileState>>(
+    width: usize,
+    height: usize,
+    states: I,
+) -> DynamicSizeGrid2D<Tile> {
+    DynamicSizeGrid2D::new(width, height, any_combination_rules(states), 0)
+}
+
 fn id(position: Location2D, _w: usize, h: usize) -> TileState {
     (position.y * h + position.x) as u64
 }
 
-pub fn assert_tile_state(tile: &Tile, expected: TileState) {
+/// Takes the reference returned by `get_tile` by reference, which is how the tests have
+/// always called it
+pub fn assert_tile_state<T: TileInterface<TileState>>(tile: &&T, expected: TileState) {
     let mut tile_possible = tile.possible_states();
     assert_eq!(
         tile_possible
@@ -29,7 +60,10 @@ pub fn assert_tile_state(tile: &Tile, expected: TileState) {
     )
 }
 
-pub fn get_tile<T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>>(
+pub fn get_tile<
+    TTile: TileInterface<TileState>,
+    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, TTile>,
+>(
     w: usize,
     h: usize,
     grid: T,
@@ -62,7 +96,8 @@ pub fn get_tile<T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Dire
 }
 
 pub fn get_neighbours_sanity<
-    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>,
+    TTile: TileInterface<TileState>,
+    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, TTile>,
 >(
     w: usize,
     h: usize,
@@ -134,7 +169,8 @@ pub fn get_neighbours_sanity<
 }
 
 pub fn update_tiles_sanity<
-    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>,
+    TTile: TileInterface<TileState>,
+    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, TTile>,
 >(
     w: usize,
     h: usize,
@@ -170,7 +206,8 @@ pub fn update_tiles_sanity<
 }
 
 pub fn update_tiles_entropy<
-    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>,
+    TTile: TileInterface<TileState>,
+    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, TTile>,
 >(
     w: usize,
     h: usize,
@@ -223,7 +260,8 @@ pub fn update_tiles_entropy<
 }
 
 pub fn edges_2x2<
-    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>,
+    TTile: TileInterface<TileState>,
+    T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, TTile>,
     F,
 >(
     init: F,
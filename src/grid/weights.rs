@@ -0,0 +1,194 @@
+//! Varying the weights of states over the grid
+//!
+//! `RuleSet::weights` are the same everywhere in the output. A `WeightField` scales them per
+//! tile, for example to let forests thin out into plains. Each layer of the field scales a
+//! group of states, where the multipliers can come from a closure, a channel of a guide image
+//! or seeded noise.
+//!
+//! The local weights are used both for picking the tile with the lowest entropy and for picking
+//! its state, see `GridInterface::get_weights`.
+
+use std::collections::{BTreeSet, HashMap};
+
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tile::TileState,
+    utils::{
+        noise::Noise,
+        space::{Location, s2d::Location2D},
+    },
+};
+
+/// Weights are whole numbers, so the scaled weights are multiplied by this to keep some
+/// precision. Scaling every weight by the same amount changes neither the entropy nor the
+/// chances of each state.
+const WEIGHT_SCALE: f64 = 1024.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct WeightLayer<TPosition: Location> {
+    states: BTreeSet<TileState>,
+    multipliers: HashMap<TPosition, f64>,
+}
+
+/// Per tile multipliers for the weights of the rules. States in multiple layers are scaled by
+/// all of them, tiles missing from a layer aren't scaled by it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WeightField<TPosition: Location> {
+    layers: Vec<WeightLayer<TPosition>>,
+}
+
+impl<TPosition: Location> WeightField<TPosition> {
+    pub fn new() -> Self {
+        Self { layers: Vec::new() }
+    }
+
+    /// Scales the weights of `states` at each of the `positions` by `multiplier(position)`
+    pub fn with_layer<
+        I: IntoIterator<Item = TileState>,
+        P: IntoIterator<Item = TPosition>,
+        F: Fn(TPosition) -> f64,
+    >(
+        mut self,
+        states: I,
+        positions: P,
+        multiplier: F,
+    ) -> Self {
+        self.layers.push(WeightLayer {
+            states: states.into_iter().collect(),
+            multipliers: positions
+                .into_iter()
+                .map(|position| (position, multiplier(position).max(0.0)))
+                .collect(),
+        });
+        self
+    }
+
+    /// The product of the multipliers of `state` at `position`
+    pub fn multiplier(&self, position: TPosition, state: TileState) -> f64 {
+        self.layers
+            .iter()
+            .filter(|layer| layer.states.contains(&state))
+            .filter_map(|layer| layer.multipliers.get(&position))
+            .product()
+    }
+
+    /// The weights of `states` at `position`, scaled from `weights`. States with a weight keep
+    /// a weight of at least 1, so a multiplier of 0 makes a state very unlikely instead of
+    /// impossible. Use `StateMasks` to forbid states.
+    pub fn weights_at<I: IntoIterator<Item = TileState>>(
+        &self,
+        position: TPosition,
+        states: I,
+        weights: &HashMap<TileState, usize>,
+    ) -> HashMap<TileState, usize> {
+        states
+            .into_iter()
+            .map(|state| {
+                let weight = weights.get(&state).copied().unwrap_or(1);
+                let scaled = weight as f64 * WEIGHT_SCALE * self.multiplier(position, state);
+                let scaled = (scaled.round() as usize).max(weight.min(1));
+                (state, scaled)
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+/// Sources for 2D grids. Each value between 0 and 1 is mapped to a multiplier between `low`
+/// and `high`.
+impl WeightField<Location2D> {
+    fn positions(width: usize, height: usize) -> impl Iterator<Item = Location2D> {
+        (0..height).flat_map(move |y| (0..width).map(move |x| Location2D { x, y }))
+    }
+
+    /// Reads the multipliers from one channel (0 to 3 for RGBA) of `image`, stretched over a
+    /// `width` by `height` grid. Grayscale images give the same value on all colour channels.
+    pub fn with_image_channel<I: IntoIterator<Item = TileState>>(
+        self,
+        states: I,
+        image: &DynamicImage,
+        channel: usize,
+        (width, height): (usize, usize),
+        (low, high): (f64, f64),
+    ) -> Self {
+        let image = image.to_rgba8();
+        let sample = |l: Location2D| {
+            // nearest neighbour, pixel centres line up with tile centres
+            let x = (l.x * image.width() as usize + image.width() as usize / 2) / width;
+            let y = (l.y * image.height() as usize + image.height() as usize / 2) / height;
+            image.get_pixel(x as u32, y as u32).0[channel] as f64 / 255.0
+        };
+        self.with_layer(states, Self::positions(width, height), |l| {
+            low + (high - low) * sample(l)
+        })
+    }
+
+    /// Samples the multipliers from `noise` at the centre of each tile of a `width` by `height`
+    /// grid
+    pub fn with_noise<I: IntoIterator<Item = TileState>>(
+        self,
+        states: I,
+        noise: &Noise,
+        (width, height): (usize, usize),
+        (low, high): (f64, f64),
+    ) -> Self {
+        self.with_layer(states, Self::positions(width, height), |l| {
+            low + (high - low) * noise.sample(l.x as f64 + 0.5, l.y as f64 + 0.5)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use crate::utils::noise::NoiseKind;
+
+    use super::*;
+
+    #[test]
+    fn layers_multiply() {
+        let origin = Location2D { x: 0, y: 0 };
+        let other = Location2D { x: 1, y: 0 };
+        let field = WeightField::new()
+            .with_layer(
+                [0, 1],
+                [origin, other],
+                |l| if l == origin { 2.0 } else { 0.0 },
+            )
+            .with_layer([1], [origin], |_| 0.25);
+        assert_eq!(field.multiplier(origin, 0), 2.0);
+        assert_eq!(field.multiplier(origin, 1), 0.5);
+        assert_eq!(field.multiplier(origin, 2), 1.0);
+
+        let weights = HashMap::from([(0, 3), (2, 0)]);
+        let local = field.weights_at(other, [0, 1, 2], &weights);
+        // states with weight are never fully removed
+        assert_eq!(local, HashMap::from([(0, 1), (1, 1), (2, 0)]));
+        let local = field.weights_at(origin, [0, 1, 2], &weights);
+        assert_eq!(local[&0], 6 * WEIGHT_SCALE as usize);
+        assert_eq!(local[&1], WEIGHT_SCALE as usize / 2);
+    }
+
+    #[test]
+    fn guides() {
+        // a horizontal gradient on the red channel
+        let image = RgbaImage::from_fn(2, 1, |x, _| Rgba([x as u8 * 255, 0, 0, 255]));
+        let image = DynamicImage::ImageRgba8(image);
+        let field = WeightField::new().with_image_channel([0], &image, 0, (4, 2), (1.0, 3.0));
+        assert_eq!(field.multiplier(Location2D { x: 1, y: 1 }, 0), 1.0);
+        assert_eq!(field.multiplier(Location2D { x: 2, y: 0 }, 0), 3.0);
+
+        let noise = Noise::new(NoiseKind::Perlin, 3, 4.0);
+        let field = WeightField::new().with_noise([0], &noise, (8, 8), (0.5, 1.5));
+        for x in 0..8 {
+            let multiplier = field.multiplier(Location2D { x, y: 5 }, 0);
+            assert!((0.5..=1.5).contains(&multiplier));
+        }
+    }
+}
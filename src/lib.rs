@@ -4,7 +4,9 @@ pub mod tile;
 pub mod utils;
 
 pub mod backtracking;
+pub mod selection;
 pub mod tile_extraction;
+pub mod value_selection;
 pub mod wave_function_collapse;
 
 #[cfg(not(tarpaulin_include))] // the wasm bindings don't need to be unit tested
@@ -3,12 +3,9 @@
 
 use std::fs;
 
-use aaltofunktionromautus::{
-    rules::RuleSet2D,
-    tile_extraction::{
-        TileExtractor,
-        overlapping_bitmap::{OverlappingBitmapExtractor, OverlappingBitmapExtractorOptions},
-    },
+use aaltofunktionromautus::tile_extraction::{
+    TileExtractor,
+    overlapping_bitmap::{OverlappingBitmapExtractor, OverlappingBitmapExtractorOptions},
 };
 
 #[cfg(not(tarpaulin_include))] // the utility binary doesn't need to be unit tested
@@ -22,7 +19,7 @@ fn extract_rules_to_json(
 
     println!(
         "possible tiles in \"{input_path}\": {}",
-        extractor.get_rules().possible.iter().count()
+        extractor.get_rules().possible.len()
     );
 
     let json = serde_json::to_string(extractor.get_rules()).expect("serializing ruleset to json");
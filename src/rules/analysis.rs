@@ -0,0 +1,304 @@
+//! Finding problems in a `RuleSet` before running it
+//!
+//! Most mistakes in a ruleset only show up at runtime, as contradictions that keep coming back
+//! or as a panic when the edges can't be initialized. `RuleSet::analyze` looks for them up front.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Debug,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::RuleSet;
+use crate::{tile::TileState, utils::space::Direction};
+
+/// Something that is likely wrong with a ruleset
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, thiserror::Error,
+)]
+pub enum RuleSetIssue<TDirection: Debug> {
+    /// No state may be next to the state in this direction, so it can't appear anywhere with a
+    /// neighbour in that direction. Not reported for the state of the edge in that direction.
+    #[error("state {state} has no allowed neighbour in direction {direction:?}")]
+    NoNeighbour {
+        state: TileState,
+        direction: TDirection,
+    },
+    #[error("adjacency ({0}, {1:?}, {2}) mentions a state that is not possible")]
+    UnknownAdjacency(TileState, TDirection, TileState),
+    #[error("weight given for unknown state {0}")]
+    UnknownWeight(TileState),
+    #[error("representation given for unknown state {0}")]
+    UnknownRepresentation(TileState),
+    #[error("edge {direction:?} is initialized to unknown state {state}")]
+    UnknownEdgeState {
+        direction: TDirection,
+        state: TileState,
+    },
+    /// The tiles along the edge can't be next to each other
+    #[error("state {state} of edge {direction:?} is not allowed next to itself along the edge")]
+    UnsatisfiableEdge {
+        direction: TDirection,
+        state: TileState,
+    },
+    /// The corner where two edges meet gets the state of the later edge in
+    /// `initialize_edges`, which isn't allowed next to the first tile of the earlier edge
+    #[error("edges {a:?} ({a_state}) and {b:?} ({b_state}) can't meet at a corner")]
+    UnsatisfiableCorner {
+        a: TDirection,
+        a_state: TileState,
+        b: TDirection,
+        b_state: TileState,
+    },
+    /// The state has neighbours in every direction, but some of them can never appear, so
+    /// arc-consistency removes it from every tile
+    #[error("state {0} can never appear, as some of its neighbours can't")]
+    Unreachable(TileState),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleSetReport<TDirection: Debug> {
+    pub issues: Vec<RuleSetIssue<TDirection>>,
+    pub state_count: usize,
+    /// Share of all (state, direction, state) combinations of possible states that are
+    /// allowed, between 0 and 1
+    pub adjacency_density: f64,
+    /// Shannon entropy of the weights of the possible states, in nats. The logarithm of the
+    /// state count when all weights are equal, lower when a few states dominate.
+    pub weight_entropy: f64,
+}
+
+impl<TDirection: Debug> RuleSetReport<TDirection> {
+    /// True if no issues were found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS> + TryFrom<usize> + Debug>
+    RuleSet<NEIGHBOURS, TDirection>
+{
+    /// Checks the rules for likely mistakes and collects some statistics, see `RuleSetIssue`.
+    ///
+    /// Edges are expected to meet at corners unless they lie in opposite directions, like they
+    /// do on all grids of this crate.
+    pub fn analyze(&self) -> RuleSetReport<TDirection> {
+        let directions: Vec<TDirection> = (0..NEIGHBOURS)
+            .filter_map(|i| TDirection::try_from(i).ok())
+            .collect();
+        let mut issues = Vec::new();
+
+        // state -> direction -> allowed neighbours
+        let mut neighbours: HashMap<(TileState, TDirection), BTreeSet<TileState>> = HashMap::new();
+        let mut known_entries = 0;
+        for &(state, direction, neighbour) in &self.allowed {
+            if !self.possible.contains(&state) || !self.possible.contains(&neighbour) {
+                issues.push(RuleSetIssue::UnknownAdjacency(state, direction, neighbour));
+                continue;
+            }
+            known_entries += 1;
+            neighbours
+                .entry((state, direction))
+                .or_default()
+                .insert(neighbour);
+        }
+
+        // a state at the edge doesn't need neighbours beyond it
+        let is_edge = |state: TileState, direction: TDirection| {
+            self.initialize_edges.get(&direction) == Some(&state)
+        };
+        for &state in &self.possible {
+            for &direction in &directions {
+                if !neighbours.contains_key(&(state, direction)) && !is_edge(state, direction) {
+                    issues.push(RuleSetIssue::NoNeighbour { state, direction });
+                }
+            }
+        }
+
+        issues.extend(
+            self.weights
+                .keys()
+                .filter(|state| !self.possible.contains(state))
+                .map(|state| RuleSetIssue::UnknownWeight(*state)),
+        );
+        issues.extend(
+            self.state_representations
+                .keys()
+                .filter(|state| !self.possible.contains(state))
+                .map(|state| RuleSetIssue::UnknownRepresentation(*state)),
+        );
+
+        for (&a, &a_state) in &self.initialize_edges {
+            if !self.possible.contains(&a_state) {
+                issues.push(RuleSetIssue::UnknownEdgeState {
+                    direction: a,
+                    state: a_state,
+                });
+                continue;
+            }
+            let along_edge = directions.iter().filter(|b| **b != a && **b != a.mirror());
+            if along_edge
+                .into_iter()
+                .any(|b| !self.allowed.contains(&(a_state, *b, a_state)))
+            {
+                issues.push(RuleSetIssue::UnsatisfiableEdge {
+                    direction: a,
+                    state: a_state,
+                });
+            }
+            for (&b, &b_state) in &self.initialize_edges {
+                if b == a || b == a.mirror() {
+                    continue;
+                }
+                // the corner is at the `b` end of edge `a`, which continues in the opposite
+                // direction
+                if a < b && !self.allowed.contains(&(b_state, b.mirror(), a_state)) {
+                    issues.push(RuleSetIssue::UnsatisfiableCorner {
+                        a,
+                        a_state,
+                        b,
+                        b_state,
+                    });
+                }
+            }
+        }
+
+        // arc-consistency on a grid where every tile starts with every state
+        let mut remaining = self.possible.clone();
+        loop {
+            let unsupported: Vec<_> = remaining
+                .iter()
+                .copied()
+                .filter(|state| {
+                    directions.iter().any(|direction| {
+                        !is_edge(*state, *direction)
+                            && neighbours
+                                .get(&(*state, *direction))
+                                .is_none_or(|allowed| allowed.is_disjoint(&remaining))
+                    })
+                })
+                .collect();
+            if unsupported.is_empty() {
+                break;
+            }
+            for state in unsupported {
+                remaining.remove(&state);
+            }
+        }
+        for &state in self.possible.difference(&remaining) {
+            let has_neighbours = directions.iter().all(|direction| {
+                neighbours.contains_key(&(state, *direction)) || is_edge(state, *direction)
+            });
+            // states without neighbours were reported already
+            if has_neighbours {
+                issues.push(RuleSetIssue::Unreachable(state));
+            }
+        }
+
+        issues.sort();
+        issues.dedup();
+
+        let state_count = self.possible.len();
+        let combinations = state_count * state_count * directions.len();
+        let adjacency_density = if combinations == 0 {
+            0.0
+        } else {
+            known_entries as f64 / combinations as f64
+        };
+
+        let weights: Vec<f64> = self
+            .possible
+            .iter()
+            .map(|state| self.weights.get(state).copied().unwrap_or(1) as f64)
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let weight_entropy = weights
+            .iter()
+            .filter(|w| **w > 0.0)
+            .map(|w| {
+                let p = w / total;
+                -p * p.ln()
+            })
+            .sum();
+
+        RuleSetReport {
+            issues,
+            state_count,
+            adjacency_density,
+            weight_entropy,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use crate::{rules::RuleSet2D, utils::space::s2d::Direction2D};
+
+    use super::*;
+
+    #[test]
+    fn samples_are_clean() {
+        let report = crate::rules::samples::checkers::rules().analyze();
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.state_count, 2);
+        // each state has one neighbour out of two in every direction
+        assert_eq!(report.adjacency_density, 0.5);
+        assert!((report.weight_entropy - 2f64.ln()).abs() < 1e-9);
+
+        let report = crate::rules::samples::flowers_singlepixel::rules().analyze();
+        assert!(report.is_ok(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn issues_are_found() {
+        use Direction2D::*;
+        // 0 and 1 tile everything, 2 needs 3 below it, and 3 has nothing below it
+        let mut allowed = HashSet::new();
+        for a in [0, 1] {
+            for b in [0, 1] {
+                allowed.insert((a, RIGHT, b));
+                allowed.insert((a, DOWN, b));
+            }
+        }
+        allowed.extend([(2, RIGHT, 2), (0, DOWN, 2), (2, DOWN, 3), (3, RIGHT, 3)]);
+        allowed.insert((0, RIGHT, 9));
+        let rules = RuleSet2D::new(
+            BTreeSet::from([0, 1, 2, 3]),
+            allowed,
+            HashMap::from([(0, 5), (8, 1)]),
+            HashMap::from([(7, 0)]),
+            BTreeMap::from([(DOWN, 0), (RIGHT, 2)]),
+        );
+        let report = rules.analyze();
+        assert_eq!(
+            report.issues,
+            vec![
+                RuleSetIssue::NoNeighbour {
+                    state: 3,
+                    direction: DOWN,
+                },
+                RuleSetIssue::UnknownAdjacency(0, RIGHT, 9),
+                RuleSetIssue::UnknownAdjacency(9, LEFT, 0),
+                RuleSetIssue::UnknownWeight(8),
+                RuleSetIssue::UnknownRepresentation(7),
+                // 2 can't be above or below itself
+                RuleSetIssue::UnsatisfiableEdge {
+                    direction: RIGHT,
+                    state: 2,
+                },
+                // nor above 0 in the corner
+                RuleSetIssue::UnsatisfiableCorner {
+                    a: RIGHT,
+                    a_state: 2,
+                    b: DOWN,
+                    b_state: 0,
+                },
+                RuleSetIssue::Unreachable(2),
+            ]
+        );
+        assert_eq!(report.state_count, 4);
+    }
+}
@@ -0,0 +1,313 @@
+//! A faster to query version of a `RuleSet`
+//!
+//! `RuleSet` keys everything by `TileState`, so every adjacency lookup has to hash a
+//! (state, direction, state) triple. `CompiledRuleSet` maps each state to a contiguous index and
+//! precomputes the compatible neighbours of every state in every direction, both as a list and as
+//! a bitmask. The solver works on the indices and only translates back to `TileState`s when
+//! talking to tiles.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::RuleSet;
+use crate::{tile::TileState, utils::space::Direction};
+
+pub const WORD_BITS: usize = u64::BITS as usize;
+
+/// Returns true if bit `index` is set in `mask`
+#[inline]
+pub fn mask_has_index(mask: &[u64], index: usize) -> bool {
+    mask[index / WORD_BITS] & (1 << (index % WORD_BITS)) != 0
+}
+
+/// Sorted list of states, a state's index is its position in the list
+///
+/// Looking up an index is a binary search, which is cheaper than hashing for the small state
+/// counts of typical rulesets. Clones share the list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateIndex(Arc<Vec<TileState>>);
+
+impl StateIndex {
+    pub fn new<I: IntoIterator<Item = TileState>>(states: I) -> Self {
+        let states: BTreeSet<_> = states.into_iter().collect();
+        Self(Arc::new(states.into_iter().collect()))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Amount of u64 words in a bitmask with one bit per state
+    #[inline]
+    pub fn words(&self) -> usize {
+        self.0.len().div_ceil(WORD_BITS)
+    }
+
+    #[inline]
+    pub fn states(&self) -> &[TileState] {
+        &self.0
+    }
+
+    #[inline]
+    pub fn index_of(&self, state: TileState) -> Option<usize> {
+        self.0.binary_search(&state).ok()
+    }
+
+    #[inline]
+    pub fn state(&self, index: usize) -> TileState {
+        self.0[index]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompiledRuleSet<TDirection> {
+    /// All known states, see `StateIndex`
+    states: StateIndex,
+    /// All directions that appear in the rules in ascending order
+    directions: Vec<TDirection>,
+    /// Amount of u64 words needed for a bitmask with one bit per state
+    words: usize,
+    /// (direction, neighbour) -> states that are allowed when the neighbour lies in `direction`
+    /// of them. Indexed with `direction_index * states.len() + neighbour_index`.
+    compatible: Vec<Vec<usize>>,
+    /// Same as `compatible` but as bitmasks, each entry takes `words` words
+    compatible_masks: Vec<u64>,
+}
+
+impl<TDirection: Ord + Copy> CompiledRuleSet<TDirection> {
+    pub fn new<const N: usize>(rules: &RuleSet<N, TDirection>) -> Self
+    where
+        TDirection: Direction<N>,
+    {
+        // states referenced only by the adjacency rules still need an index
+        let mut states = rules.possible.clone();
+        let mut directions = BTreeSet::new();
+        for (state, direction, neighbour_state) in &rules.allowed {
+            states.insert(*state);
+            states.insert(*neighbour_state);
+            directions.insert(*direction);
+        }
+        let states = StateIndex::new(states);
+        let directions: Vec<_> = directions.into_iter().collect();
+        let index_of =
+            |state: &TileState| states.index_of(*state).expect("all states were collected");
+        let words = states.words();
+
+        let mut compatible = vec![vec![]; directions.len() * states.len()];
+        let mut compatible_masks = vec![0; directions.len() * states.len() * words];
+        for (state, direction, neighbour_state) in &rules.allowed {
+            let direction_index = directions
+                .binary_search(direction)
+                .expect("all directions were collected");
+            let entry = direction_index * states.len() + index_of(neighbour_state);
+            let state_index = index_of(state);
+            compatible[entry].push(state_index);
+            compatible_masks[entry * words + state_index / WORD_BITS] |=
+                1 << (state_index % WORD_BITS);
+        }
+        // the iteration order of a HashSet is random
+        for list in &mut compatible {
+            list.sort_unstable();
+        }
+
+        Self {
+            states,
+            directions,
+            words,
+            compatible,
+            compatible_masks,
+        }
+    }
+
+    /// Amount of known states
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Amount of u64 words in a bitmask of states
+    #[inline]
+    pub fn words(&self) -> usize {
+        self.words
+    }
+
+    /// All known states, a state's index is its position in the slice
+    #[inline]
+    pub fn states(&self) -> &[TileState] {
+        self.states.states()
+    }
+
+    /// The shared index behind `index_of` and `state`
+    #[inline]
+    pub fn state_index(&self) -> &StateIndex {
+        &self.states
+    }
+
+    #[inline]
+    pub fn index_of(&self, state: TileState) -> Option<usize> {
+        self.states.index_of(state)
+    }
+
+    #[inline]
+    pub fn state(&self, index: usize) -> TileState {
+        self.states.state(index)
+    }
+
+    /// All directions mentioned by the rules, a direction's index is its position in the slice
+    #[inline]
+    pub fn directions(&self) -> &[TDirection] {
+        &self.directions
+    }
+
+    /// Returns the dense index of the direction, or None if no rule mentions it
+    #[inline]
+    pub fn direction_index(&self, direction: TDirection) -> Option<usize> {
+        self.directions.binary_search(&direction).ok()
+    }
+
+    #[inline]
+    fn entry(&self, direction: TDirection, neighbour: usize) -> Option<usize> {
+        self.direction_index(direction)
+            .map(|direction_index| direction_index * self.states.len() + neighbour)
+    }
+
+    /// Indices of the states that are allowed when the state `neighbour` lies in `direction` of
+    /// them
+    #[inline]
+    pub fn compatible(&self, direction: TDirection, neighbour: usize) -> &[usize] {
+        match self.entry(direction, neighbour) {
+            Some(entry) => &self.compatible[entry],
+            None => &[],
+        }
+    }
+
+    /// Same as `compatible`, but as a bitmask with one bit per state index
+    #[inline]
+    pub fn compatible_mask(&self, direction: TDirection, neighbour: usize) -> Option<&[u64]> {
+        self.entry(direction, neighbour)
+            .map(|entry| &self.compatible_masks[entry * self.words..(entry + 1) * self.words])
+    }
+
+    /// Fills `mask` with all states that at least one of the `neighbours` (given by index)
+    /// allows, when the neighbour lies in `direction`. `mask` is reused so propagation doesn't
+    /// allocate on every step.
+    pub fn supported_mask<I: IntoIterator<Item = usize>>(
+        &self,
+        direction: TDirection,
+        neighbours: I,
+        mask: &mut Vec<u64>,
+    ) {
+        mask.clear();
+        mask.resize(self.words, 0);
+        for neighbour in neighbours {
+            if let Some(compatible) = self.compatible_mask(direction, neighbour) {
+                for (word, compatible_word) in mask.iter_mut().zip(compatible) {
+                    *word |= compatible_word;
+                }
+            }
+        }
+    }
+
+    /// Returns true if `state` is set in a bitmask created by this ruleset
+    #[inline]
+    pub fn mask_contains(&self, mask: &[u64], state: TileState) -> bool {
+        self.index_of(state)
+            .is_some_and(|index| mask_has_index(mask, index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    use super::*;
+    use crate::{
+        rules::RuleSet2D,
+        tile::{Tile, interface::TileInterface},
+        utils::space::s2d::Direction2D,
+    };
+
+    #[test]
+    fn matches_ruleset() {
+        let rules = crate::rules::samples::terrain::rules();
+        let compiled = CompiledRuleSet::new(&rules);
+        assert_eq!(compiled.len(), rules.possible.len());
+
+        for direction in [
+            Direction2D::UP,
+            Direction2D::RIGHT,
+            Direction2D::DOWN,
+            Direction2D::LEFT,
+        ] {
+            for (neighbour, neighbour_state) in compiled.states().iter().enumerate() {
+                let expected: BTreeSet<_> = rules
+                    .possible
+                    .iter()
+                    .filter(|state| {
+                        rules
+                            .allowed
+                            .contains(&(**state, direction, *neighbour_state))
+                    })
+                    .cloned()
+                    .collect();
+                let listed: BTreeSet<_> = compiled
+                    .compatible(direction, neighbour)
+                    .iter()
+                    .map(|index| compiled.state(*index))
+                    .collect();
+                assert_eq!(listed, expected);
+
+                let mut mask = vec![];
+                compiled.supported_mask(direction, [neighbour], &mut mask);
+                let masked: BTreeSet<_> = rules
+                    .possible
+                    .iter()
+                    .filter(|state| compiled.mask_contains(&mask, **state))
+                    .cloned()
+                    .collect();
+                assert_eq!(masked, expected);
+
+                // check should agree when the source has collapsed into the neighbour state
+                let target = Tile::new(rules.possible.clone());
+                let source = Tile::new([*neighbour_state]);
+                assert_eq!(rules.check(&target, &source, direction), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn states_from_adjacency_rules() {
+        const STATE_A: TileState = 10;
+        const STATE_B: TileState = 20;
+        const STATE_C: TileState = 30;
+        let rules = RuleSet2D::new(
+            BTreeSet::from([STATE_A, STATE_B]),
+            HashSet::from([(STATE_B, Direction2D::RIGHT, STATE_C)]),
+            HashMap::from([(STATE_A, 4), (STATE_C, 0)]),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+        let compiled = CompiledRuleSet::new(&rules);
+
+        // C is only mentioned in the adjacency rules, but still gets an index
+        assert_eq!(compiled.states(), &[STATE_A, STATE_B, STATE_C]);
+        assert_eq!(compiled.direction_index(Direction2D::UP), None);
+        assert_eq!(compiled.compatible(Direction2D::UP, 0), &[] as &[usize]);
+        assert_eq!(
+            compiled.compatible(Direction2D::LEFT, compiled.index_of(STATE_B).unwrap()),
+            &[compiled.index_of(STATE_C).unwrap()]
+        );
+    }
+}
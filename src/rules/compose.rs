@@ -0,0 +1,293 @@
+//! Building rulesets out of smaller ones
+//!
+//! Rules can be written in modules, for example terrain, props and roads, and combined with
+//! `RuleSet::union`. Giving each module a namespace first keeps their states apart, bridges then
+//! allow states of different modules next to each other.
+//!
+//! All operations keep `allowed` mirrored like `RuleSet::new` does, and the results are plain
+//! `RuleSet`s that can be saved and loaded with serde.
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::RuleSet;
+use crate::{tile::TileState, utils::space::Direction};
+
+/// Reasons rulesets can't be combined
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CompositionError {
+    #[error("state {0} is not in the rules")]
+    UnknownState(TileState),
+    /// Both rulesets define the same property of a state differently
+    #[error("the rulesets disagree on the {property} of state {state}")]
+    Conflict {
+        state: TileState,
+        property: &'static str,
+    },
+}
+
+/// The id `state` gets in `namespace`, see `RuleSet::with_namespace`.
+///
+/// Uses FNV-1a, which unlike the standard library hasher is guaranteed to stay the same between
+/// Rust versions, so ids in saved rulesets stay valid.
+pub fn namespaced_state(namespace: &str, state: TileState) -> TileState {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    namespace
+        .bytes()
+        // separates the namespace from the state, so ("a1", 2) and ("a", 12) differ
+        .chain([0xff])
+        .chain(state.to_le_bytes())
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+}
+
+/// Adds `entries` to `target`, failing if a key already has a different value
+fn merge_map<V: Copy + PartialEq>(
+    target: &mut HashMap<TileState, V>,
+    entries: &HashMap<TileState, V>,
+    property: &'static str,
+) -> Result<(), CompositionError> {
+    for (state, value) in entries {
+        match target.get(state) {
+            Some(existing) if existing != value => {
+                return Err(CompositionError::Conflict {
+                    state: *state,
+                    property,
+                });
+            }
+            _ => {
+                target.insert(*state, *value);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS>> RuleSet<NEIGHBOURS, TDirection> {
+    /// Renames every state to `namespaced_state(namespace, state)`
+    pub fn with_namespace(&self, namespace: &str) -> Self {
+        self.map_states(|state| namespaced_state(namespace, state))
+    }
+
+    fn map_states(&self, f: impl Fn(TileState) -> TileState) -> Self {
+        let map = |states: &HashMap<TileState, TileState>| {
+            states.iter().map(|(a, b)| (f(*a), f(*b))).collect()
+        };
+        let mut transforms = self.transforms.clone();
+        transforms.mirror_x = map(&self.transforms.mirror_x);
+        transforms.mirror_y = map(&self.transforms.mirror_y);
+        transforms.rotation = map(&self.transforms.rotation);
+        Self {
+            possible: self.possible.iter().map(|s| f(*s)).collect(),
+            allowed: self
+                .allowed
+                .iter()
+                .map(|(a, direction, b)| (f(*a), *direction, f(*b)))
+                .collect(),
+            state_representations: self
+                .state_representations
+                .iter()
+                .map(|(state, representation)| (f(*state), *representation))
+                .collect(),
+            weights: self
+                .weights
+                .iter()
+                .map(|(state, weight)| (f(*state), *weight))
+                .collect(),
+            initialize_edges: self
+                .initialize_edges
+                .iter()
+                .map(|(direction, state)| (*direction, f(*state)))
+                .collect(),
+            count_constraints: self
+                .count_constraints
+                .iter()
+                .map(|constraint| {
+                    let mut constraint = *constraint;
+                    constraint.state = f(constraint.state);
+                    constraint
+                })
+                .collect(),
+            transforms,
+        }
+    }
+
+    /// Combines the states and rules of both rulesets. States that are in both are the same
+    /// state, use `with_namespace` to keep them apart.
+    ///
+    /// Fails if the rulesets give a shared state a different weight, representation or
+    /// transform, or initialize an edge to different states.
+    pub fn union(&self, other: &Self) -> Result<Self, CompositionError> {
+        let mut union = self.clone();
+        union.possible.extend(other.possible.iter().copied());
+        union.allowed.extend(other.allowed.iter().copied());
+        merge_map(&mut union.weights, &other.weights, "weight")?;
+        merge_map(
+            &mut union.state_representations,
+            &other.state_representations,
+            "representation",
+        )?;
+        for (target, source, property) in [
+            (
+                &mut union.transforms.mirror_x,
+                &other.transforms.mirror_x,
+                "horizontal mirror image",
+            ),
+            (
+                &mut union.transforms.mirror_y,
+                &other.transforms.mirror_y,
+                "vertical mirror image",
+            ),
+            (
+                &mut union.transforms.rotation,
+                &other.transforms.rotation,
+                "rotation",
+            ),
+        ] {
+            merge_map(target, source, property)?;
+        }
+        for (direction, state) in &other.initialize_edges {
+            match union.initialize_edges.get(direction) {
+                Some(existing) if existing != state => {
+                    return Err(CompositionError::Conflict {
+                        state: *state,
+                        property: "edge",
+                    });
+                }
+                _ => {
+                    union.initialize_edges.insert(*direction, *state);
+                }
+            }
+        }
+        union
+            .count_constraints
+            .extend(other.count_constraints.iter().copied());
+        Ok(union)
+    }
+
+    /// Removes all states that aren't in `states`, along with everything that mentions them
+    pub fn restrict_to<I: IntoIterator<Item = TileState>>(&self, states: I) -> Self {
+        let keep: BTreeSet<_> = states.into_iter().collect();
+        let kept = |state: &TileState| keep.contains(state);
+        let mut restricted = self.clone();
+        restricted.possible.retain(kept);
+        restricted.allowed.retain(|(a, _, b)| kept(a) && kept(b));
+        restricted.weights.retain(|state, _| kept(state));
+        restricted
+            .state_representations
+            .retain(|state, _| kept(state));
+        restricted.initialize_edges.retain(|_, state| kept(state));
+        restricted.count_constraints.retain(|c| kept(&c.state));
+        for map in [
+            &mut restricted.transforms.mirror_x,
+            &mut restricted.transforms.mirror_y,
+            &mut restricted.transforms.rotation,
+        ] {
+            map.retain(|a, b| kept(a) && kept(b));
+        }
+        restricted
+    }
+
+    /// Allows `b` in `direction` of `a`, and `a` in the opposite direction of `b`. Meant for
+    /// connecting the states of different modules after a `union`.
+    pub fn add_bridge(
+        &mut self,
+        a: TileState,
+        direction: TDirection,
+        b: TileState,
+    ) -> Result<(), CompositionError> {
+        for state in [a, b] {
+            if !self.possible.contains(&state) {
+                return Err(CompositionError::UnknownState(state));
+            }
+        }
+        self.allowed.insert((a, direction, b));
+        self.allowed.insert((b, direction.mirror(), a));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use crate::{
+        rules::{RuleSet2D, samples::checkers},
+        utils::space::s2d::Direction2D::*,
+    };
+
+    use super::*;
+
+    fn is_mirrored(rules: &RuleSet2D) -> bool {
+        rules
+            .allowed
+            .iter()
+            .all(|(a, direction, b)| rules.allowed.contains(&(*b, direction.mirror(), *a)))
+    }
+
+    /// A single state that can be next to itself
+    fn plain(state: TileState, weight: usize) -> RuleSet2D {
+        RuleSet2D::new(
+            BTreeSet::from([state]),
+            HashSet::from([(state, RIGHT, state), (state, DOWN, state)]),
+            HashMap::from([(state, weight)]),
+            HashMap::new(),
+            BTreeMap::new(),
+        )
+    }
+
+    #[test]
+    fn namespaces_keep_modules_apart() {
+        let terrain = checkers::rules().with_namespace("terrain");
+        let props = checkers::rules().with_namespace("props");
+        assert!(terrain.possible.is_disjoint(&props.possible));
+        assert_ne!(namespaced_state("a1", 2), namespaced_state("a", 12));
+
+        let mut union = terrain.union(&props).unwrap();
+        assert_eq!(union.possible.len(), 4);
+        assert_eq!(union.allowed.len(), terrain.allowed.len() * 2);
+        assert!(union.analyze().is_ok());
+
+        let black = namespaced_state("terrain", checkers::STATE_BLACK);
+        let white = namespaced_state("props", checkers::STATE_WHITE);
+        union.add_bridge(black, RIGHT, white).unwrap();
+        assert!(union.allowed.contains(&(white, LEFT, black)));
+        assert!(is_mirrored(&union));
+        assert_eq!(
+            union.add_bridge(black, RIGHT, checkers::STATE_WHITE),
+            Err(CompositionError::UnknownState(checkers::STATE_WHITE))
+        );
+
+        // the composed rules can be saved and loaded
+        let json = serde_json::to_string(&union).unwrap();
+        let loaded: RuleSet2D = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.possible, union.possible);
+        assert_eq!(loaded.allowed, union.allowed);
+        assert_eq!(loaded.state_representations, union.state_representations);
+    }
+
+    #[test]
+    fn shared_states_have_to_agree() {
+        assert!(plain(0, 1).union(&plain(0, 1)).is_ok());
+        assert_eq!(
+            plain(0, 1).union(&plain(0, 2)).unwrap_err(),
+            CompositionError::Conflict {
+                state: 0,
+                property: "weight"
+            }
+        );
+    }
+
+    #[test]
+    fn restriction_removes_mentions() {
+        let union = plain(0, 1).union(&plain(1, 1)).unwrap();
+        let mut union = union.union(&checkers::rules().with_namespace("c")).unwrap();
+        union.add_bridge(0, DOWN, 1).unwrap();
+        let restricted = union.restrict_to([0, 1]);
+        assert_eq!(restricted.possible, BTreeSet::from([0, 1]));
+        assert_eq!(restricted.allowed.len(), 2 * 4 + 2);
+        assert!(restricted.state_representations.is_empty());
+        assert!(is_mirrored(&restricted));
+    }
+}
@@ -0,0 +1,307 @@
+//! Merging states that behave the same
+//!
+//! Two states with exactly the same allowed neighbours in every direction can always replace
+//! each other, so the solver only needs to know one of them. `RuleSet::minimize` merges such
+//! states, which makes propagation faster when the rules have many of them, as is common with
+//! `OverlappingBitmapExtractor` and a high symmetry. Merged states also have to be drawn the
+//! same, and their mirror images have to be merged with each other, which is found by refining
+//! the groups until they are stable.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use rand::{
+    Rng,
+    distr::{Distribution, weighted::WeightedIndex},
+};
+use serde::{Deserialize, Serialize};
+
+use super::RuleSet;
+use crate::{tile::TileState, utils::space::Direction};
+
+/// The rules with merged states, and which original states each of them stands for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinimizedRuleSet<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS>> {
+    pub rules: RuleSet<NEIGHBOURS, TDirection>,
+    /// State of `rules` -> the original states merged into it, with their original weights.
+    /// Each merged state keeps the id of its smallest original state.
+    pub originals: BTreeMap<TileState, Vec<(TileState, usize)>>,
+}
+
+impl<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS>>
+    MinimizedRuleSet<NEIGHBOURS, TDirection>
+{
+    /// The original states `state` stands for
+    pub fn original_states(&self, state: TileState) -> impl Iterator<Item = TileState> + '_ {
+        self.originals
+            .get(&state)
+            .into_iter()
+            .flatten()
+            .map(|(original, _)| *original)
+    }
+
+    /// Picks one of the original states of `state` at random, according to their weights.
+    /// States that weren't merged with anything are returned as is.
+    pub fn expand<R: Rng>(&self, state: TileState, rng: &mut R) -> TileState {
+        let Some(originals) = self.originals.get(&state) else {
+            return state;
+        };
+        match WeightedIndex::new(originals.iter().map(|(_, weight)| *weight)) {
+            Ok(distribution) => originals[distribution.sample(rng)].0,
+            // all weights are zero
+            Err(_) => originals[0].0,
+        }
+    }
+}
+
+impl<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS>> RuleSet<NEIGHBOURS, TDirection> {
+    /// Merges the states that have the same allowed neighbours in every direction, the same
+    /// representation, and whose images under the transforms are merged as well, combining their
+    /// weights. See `MinimizedRuleSet` for getting back to the original states.
+    ///
+    /// States with count constraints and edge states are left as they are, as they have to be
+    /// told apart from the states they would be merged with.
+    pub fn minimize(&self) -> MinimizedRuleSet<NEIGHBOURS, TDirection> {
+        let mut signatures: HashMap<TileState, BTreeSet<(TDirection, TileState)>> = HashMap::new();
+        for (state, direction, neighbour) in &self.allowed {
+            signatures
+                .entry(*state)
+                .or_default()
+                .insert((*direction, *neighbour));
+        }
+        let kept: HashSet<_> = self
+            .count_constraints
+            .iter()
+            .map(|c| c.state)
+            .chain(self.initialize_edges.values().copied())
+            .collect();
+
+        // Partition refinement: the states start out grouped by everything that doesn't depend
+        // on the other groups, and the groups are split by the groups of their images until
+        // nothing changes. The neighbours are compared state by state, not group by group, as
+        // the original of a merged state is picked for each tile on its own by `expand`, which
+        // is only valid if every original can be next to every original of a merged neighbour.
+        let empty = BTreeSet::new();
+        let mut initial = HashMap::new();
+        let mut block_of: HashMap<TileState, usize> = HashMap::new();
+        for state in &self.possible {
+            let key = (
+                kept.contains(state).then_some(*state),
+                self.represent_tile(*state),
+                signatures.get(state).unwrap_or(&empty),
+            );
+            let blocks = initial.len();
+            block_of.insert(*state, *initial.entry(key).or_insert(blocks));
+        }
+        let mut blocks = initial.len();
+        let maps = [
+            &self.transforms.mirror_x,
+            &self.transforms.mirror_y,
+            &self.transforms.rotation,
+        ];
+        loop {
+            let mut refined = HashMap::new();
+            let next: HashMap<TileState, usize> = self
+                .possible
+                .iter()
+                .map(|state| {
+                    // states without a group, that aren't possible, only match themselves
+                    let images = maps.map(|map| {
+                        let image = map.get(state).unwrap_or(state);
+                        block_of.get(image).copied().ok_or(*image)
+                    });
+                    let refined_blocks = refined.len();
+                    let block = *refined
+                        .entry((block_of[state], images))
+                        .or_insert(refined_blocks);
+                    (*state, block)
+                })
+                .collect();
+            block_of = next;
+            if refined.len() == blocks {
+                break;
+            }
+            blocks = refined.len();
+        }
+
+        // the states are visited in ascending order, so the first state of a block is the
+        // smallest
+        let mut representatives = HashMap::new();
+        let merged_into: HashMap<TileState, TileState> = self
+            .possible
+            .iter()
+            .map(|state| {
+                (
+                    *state,
+                    *representatives.entry(block_of[state]).or_insert(*state),
+                )
+            })
+            .collect();
+        let merged = |state: &TileState| merged_into.get(state).copied().unwrap_or(*state);
+
+        let mut originals: BTreeMap<TileState, Vec<(TileState, usize)>> = BTreeMap::new();
+        for state in &self.possible {
+            let weight = self.weights.get(state).copied().unwrap_or(1);
+            originals
+                .entry(merged(state))
+                .or_default()
+                .push((*state, weight));
+        }
+        let weights = originals
+            .iter()
+            .map(|(state, originals)| (*state, originals.iter().map(|(_, w)| w).sum()))
+            .collect();
+
+        let rules = RuleSet {
+            possible: originals.keys().copied().collect(),
+            allowed: self
+                .allowed
+                .iter()
+                .map(|(state, direction, neighbour)| (merged(state), *direction, merged(neighbour)))
+                .collect(),
+            weights,
+            // merged states are represented the same
+            state_representations: originals
+                .keys()
+                .filter_map(|state| Some((*state, self.represent_tile(*state)?)))
+                .collect(),
+            initialize_edges: self
+                .initialize_edges
+                .iter()
+                .map(|(direction, state)| (*direction, merged(state)))
+                .collect(),
+            count_constraints: self.count_constraints.clone(),
+            // the images of merged states are merged as well, so they all map to the same state
+            transforms: {
+                let mut transforms = self.transforms.clone();
+                for map in [
+                    &mut transforms.mirror_x,
+                    &mut transforms.mirror_y,
+                    &mut transforms.rotation,
+                ] {
+                    *map = map.iter().map(|(a, b)| (merged(a), merged(b))).collect();
+                }
+                transforms
+            },
+        };
+        MinimizedRuleSet { rules, originals }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::{
+        rules::RuleSet2D, utils::space::s2d::Direction2D::*,
+        wave_function_collapse::constraints::CountConstraint,
+    };
+
+    use super::*;
+
+    /// 0 and 1 are both allowed anywhere next to 2 and 3, but not next to each other, and are
+    /// drawn the same. 2 and 3 are also allowed next to themselves, so they differ.
+    fn rules() -> RuleSet2D {
+        let mut allowed = HashSet::new();
+        for direction in [RIGHT, DOWN] {
+            for a in [0, 1] {
+                for b in [2, 3] {
+                    allowed.insert((a, direction, b));
+                    allowed.insert((b, direction, a));
+                }
+            }
+            allowed.insert((2, direction, 2));
+            allowed.insert((3, direction, 3));
+        }
+        RuleSet2D::new(
+            BTreeSet::from([0, 1, 2, 3]),
+            allowed,
+            HashMap::from([(0, 2), (1, 3)]),
+            HashMap::from([(0, 10), (1, 10), (2, 12)]),
+            BTreeMap::from([(UP, 2)]),
+        )
+    }
+
+    #[test]
+    fn equivalent_states_are_merged() {
+        let minimized = rules().minimize();
+        let rules = &minimized.rules;
+        assert_eq!(rules.possible, BTreeSet::from([0, 2, 3]));
+        assert_eq!(rules.weights[&0], 5);
+        assert_eq!(rules.weights[&2], 1);
+        assert_eq!(rules.initialize_edges[&UP], 2);
+        assert!(rules.allowed.contains(&(0, RIGHT, 2)));
+        assert!(rules.allowed.contains(&(2, LEFT, 0)));
+        assert!(!rules.allowed.contains(&(0, RIGHT, 0)));
+        assert_eq!(rules.allowed.len(), 2 * 4 * 2 + 2 * 4);
+        assert!(minimized.original_states(0).eq([0, 1]));
+        assert_eq!(rules.represent_tile(0), Some(10));
+        assert_eq!(rules.represent_tile(3), None);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let picks: Vec<_> = (0..100).map(|_| minimized.expand(0, &mut rng)).collect();
+        assert!(picks.contains(&0) && picks.contains(&1));
+        assert_eq!(minimized.expand(3, &mut rng), 3);
+    }
+
+    #[test]
+    fn counted_states_are_kept() {
+        let rules = rules().with_count_constraints([CountConstraint::exactly(1, 2)]);
+        let minimized = rules.minimize();
+        assert_eq!(minimized.rules.possible, BTreeSet::from([0, 1, 2, 3]));
+        assert_eq!(minimized.rules.weights[&0], 2);
+    }
+
+    #[test]
+    fn states_drawn_differently_are_kept() {
+        let mut rules = rules();
+        rules.state_representations.insert(1, 11);
+        assert_eq!(
+            rules.minimize().rules.possible,
+            BTreeSet::from([0, 1, 2, 3])
+        );
+
+        let mut rules = self::rules();
+        rules.initialize_edges.insert(DOWN, 1);
+        assert_eq!(
+            rules.minimize().rules.possible,
+            BTreeSet::from([0, 1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn merges_follow_the_transforms() {
+        // 4 and 5 are allowed next to anything and drawn the same as each other, so they only
+        // differ in their images
+        let mut rules = rules();
+        rules.possible.extend([4, 5]);
+        for direction in [UP, DOWN, LEFT, RIGHT] {
+            for state in 0..6 {
+                for image in [4, 5] {
+                    rules.allowed.insert((state, direction, image));
+                    rules.allowed.insert((image, direction.mirror(), state));
+                }
+            }
+        }
+        // 0 and 1 only stay merged if 4 and 5 are, which is only the case if 0 and 1 are
+        rules.transforms.mirror_x = HashMap::from([(0, 4), (4, 0), (1, 5), (5, 1)]);
+        let minimized = rules.minimize();
+        assert_eq!(minimized.rules.possible, BTreeSet::from([0, 2, 3, 4]));
+        assert!(minimized.original_states(4).eq([4, 5]));
+        assert_eq!(
+            minimized.rules.transforms.mirror_x,
+            HashMap::from([(0, 4), (4, 0)])
+        );
+
+        // once 5 maps to itself, 4 and 5 differ, and with them 0 and 1
+        rules.transforms.mirror_x.remove(&5);
+        let minimized = rules.minimize();
+        assert_eq!(minimized.rules.possible, BTreeSet::from([0, 1, 2, 3, 4, 5]));
+        assert_eq!(
+            minimized.rules.transforms.mirror_x,
+            rules.transforms.mirror_x
+        );
+    }
+}
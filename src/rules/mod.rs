@@ -1,5 +1,10 @@
 //! What tiles are allowed to exists and where
 
+pub mod analysis;
+pub mod compiled;
+pub mod compose;
+pub mod minimize;
+
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     hash::Hash,
@@ -8,13 +13,18 @@ use std::{
 use serde::{Deserialize, Serialize};
 use tsify_next::Tsify;
 
+use compiled::CompiledRuleSet;
+
 use crate::{
     tile::{TileState, interface::TileInterface},
     utils::space::{
         Direction,
         s1d::{Direction1D, NEIGHBOUR_COUNT_1D},
         s2d::{Direction2D, NEIGHBOUR_COUNT_2D},
+        s2d_hex::{DirectionHex, NEIGHBOUR_COUNT_HEX},
+        s3d::{Direction3D, NEIGHBOUR_COUNT_3D},
     },
+    wave_function_collapse::{constraints::CountConstraint, symmetry::StateTransforms},
 };
 
 /// Describes the tiles that can exist in the output and which ones can be next one another
@@ -34,10 +44,20 @@ pub struct RuleSet<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS>> {
     /// we would set Direction2D::DOWN -> STATE_GROUND
     // We use a BTreeSet, as we want the iteration order to be deterministic
     pub initialize_edges: BTreeMap<TDirection, TileState>,
+    /// Limits on how many tiles may collapse into a state, across the whole grid.
+    /// See `wave_function_collapse::constraints`
+    #[serde(default)]
+    pub count_constraints: Vec<CountConstraint>,
+    /// The states tiles turn into when the output is mirrored or rotated, needed for symmetric
+    /// outputs. See `wave_function_collapse::symmetry`
+    #[serde(default)]
+    pub transforms: StateTransforms,
 }
 
 pub type RuleSet2D = RuleSet<NEIGHBOUR_COUNT_2D, Direction2D>;
 pub type RuleSet1D = RuleSet<NEIGHBOUR_COUNT_1D, Direction1D>;
+pub type RuleSet3D = RuleSet<NEIGHBOUR_COUNT_3D, Direction3D>;
+pub type RuleSetHex = RuleSet<NEIGHBOUR_COUNT_HEX, DirectionHex>;
 
 impl<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS> + Hash + Eq + Copy>
     RuleSet<NEIGHBOURS, TDirection>
@@ -61,9 +81,20 @@ impl<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS> + Hash + Eq + Co
             weights,
             state_representations,
             initialize_edges,
+            count_constraints: Vec::new(),
+            transforms: StateTransforms::default(),
         }
     }
 
+    /// Adds global limits on the amount of tiles in some states
+    pub fn with_count_constraints<I: IntoIterator<Item = CountConstraint>>(
+        mut self,
+        constraints: I,
+    ) -> Self {
+        self.count_constraints.extend(constraints);
+        self
+    }
+
     /// Removes possible tile states for `target`,
     /// given that it has a neighbour `source` in `direction`
     pub fn check<T: TileInterface<TileState>>(
@@ -86,6 +117,11 @@ impl<const NEIGHBOURS: usize, TDirection: Direction<NEIGHBOURS> + Hash + Eq + Co
         checked_possible
     }
 
+    /// Precomputes dense state indices and adjacency tables, see `CompiledRuleSet`
+    pub fn compile(&self) -> CompiledRuleSet<TDirection> {
+        CompiledRuleSet::new(self)
+    }
+
     /// Returns some 32bit representation of a tile state, most commonly a color
     pub fn represent_tile(&self, state: TileState) -> Option<u32> {
         self.state_representations.get(&state).cloned()
@@ -439,4 +475,126 @@ pub mod samples {
             )
         }
     }
+
+    /// A three dimensional checker pattern, with alternating white and black voxels
+    pub mod checkers_3d {
+        use super::*;
+        pub const STATE_BLACK: u64 = 0;
+        pub const STATE_WHITE: u64 = 1;
+        pub fn rules() -> RuleSet3D {
+            let possible = BTreeSet::from([STATE_BLACK, STATE_WHITE]);
+            let mut allowed = HashSet::new();
+            for dir_index in 0..NEIGHBOUR_COUNT_3D {
+                let dir = Direction3D::try_from(dir_index).unwrap();
+                allowed.insert((STATE_BLACK, dir, STATE_WHITE));
+            }
+            let repr = HashMap::from([(STATE_BLACK, 0xff000000), (STATE_WHITE, 0xFFFFFFFF)]);
+            RuleSet::new(possible, allowed, HashMap::new(), repr, BTreeMap::new())
+        }
+    }
+
+    /// Stone -> Dirt -> Grass -> Air, from the bottom of the grid to the top
+    /// Air is never allowed right next to stone, so cliffs are always covered by dirt
+    pub mod voxel_terrain {
+        use super::*;
+        pub const STATE_STONE: u64 = 0;
+        pub const STATE_DIRT: u64 = 1;
+        pub const STATE_GRASS: u64 = 2;
+        pub const STATE_AIR: u64 = 3;
+        pub fn rules() -> RuleSet3D {
+            let possible = BTreeSet::from([STATE_STONE, STATE_DIRT, STATE_GRASS, STATE_AIR]);
+            let repr = HashMap::from([
+                (STATE_STONE, 0xff808080),
+                (STATE_DIRT, 0xff8b4513),
+                (STATE_GRASS, 0xff008000),
+                (STATE_AIR, 0x00000000),
+            ]);
+            let mut allowed = HashSet::from([
+                // vertical rules, (A, UP, B) allows B on top of A
+                (STATE_STONE, Direction3D::UP, STATE_STONE),
+                (STATE_STONE, Direction3D::UP, STATE_DIRT),
+                (STATE_DIRT, Direction3D::UP, STATE_DIRT),
+                (STATE_DIRT, Direction3D::UP, STATE_GRASS),
+                (STATE_GRASS, Direction3D::UP, STATE_AIR),
+                (STATE_AIR, Direction3D::UP, STATE_AIR),
+            ]);
+            // horizontal rules, anything goes except for air next to stone
+            for &state in &possible {
+                for &other in &possible {
+                    let forbidden = [state, other].contains(&STATE_AIR)
+                        && [state, other].contains(&STATE_STONE);
+                    if !forbidden {
+                        allowed.insert((state, Direction3D::RIGHT, other));
+                        allowed.insert((state, Direction3D::FORWARDS, other));
+                    }
+                }
+            }
+            RuleSet::new(
+                possible,
+                allowed,
+                HashMap::from([
+                    (STATE_STONE, 3),
+                    (STATE_DIRT, 2),
+                    (STATE_GRASS, 1),
+                    (STATE_AIR, 4),
+                ]),
+                repr,
+                // bedrock at the bottom, sky at the top
+                BTreeMap::from([
+                    (Direction3D::DOWN, STATE_STONE),
+                    (Direction3D::UP, STATE_AIR),
+                ]),
+            )
+        }
+    }
+
+    /// Water -> Sand -> Grass -> Forest -> Mountain on a hexagonal map, each terrain can only
+    /// border itself and the terrains next to it in the list
+    pub mod hex_terrain {
+        use super::*;
+        pub const STATE_WATER: u64 = 0;
+        pub const STATE_SAND: u64 = 1;
+        pub const STATE_GRASS: u64 = 2;
+        pub const STATE_FOREST: u64 = 3;
+        pub const STATE_MOUNTAIN: u64 = 4;
+        pub fn rules() -> RuleSetHex {
+            let bands = [
+                STATE_WATER,
+                STATE_SAND,
+                STATE_GRASS,
+                STATE_FOREST,
+                STATE_MOUNTAIN,
+            ];
+            let possible = BTreeSet::from(bands);
+            let repr = HashMap::from([
+                (STATE_WATER, 0xff1e64c8),
+                (STATE_SAND, 0xffe6d28c),
+                (STATE_GRASS, 0xff50b43c),
+                (STATE_FOREST, 0xff1e6e28),
+                (STATE_MOUNTAIN, 0xff8c8278),
+            ]);
+            let mut allowed = HashSet::new();
+            for (i, &state) in bands.iter().enumerate() {
+                for &other in &bands[i..(i + 2).min(bands.len())] {
+                    for dir_index in 0..NEIGHBOUR_COUNT_HEX {
+                        let dir = DirectionHex::try_from(dir_index).unwrap();
+                        allowed.insert((state, dir, other));
+                    }
+                }
+            }
+            RuleSet::new(
+                possible,
+                allowed,
+                HashMap::from([
+                    (STATE_WATER, 6),
+                    (STATE_SAND, 1),
+                    (STATE_GRASS, 4),
+                    (STATE_FOREST, 3),
+                    (STATE_MOUNTAIN, 2),
+                ]),
+                repr,
+                BTreeMap::new(),
+            )
+        }
+    }
 }
@@ -0,0 +1,63 @@
+//! The classic heuristic: the tile with the least Shannon entropy goes first
+
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::interface::TileInterface,
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::SelectionHeuristic;
+
+/// Picks the tile with the lowest entropy, this is what `WaveFunctionCollapse::tick` does.
+///
+/// The grids keep their tiles in a priority queue, so this is by far the fastest heuristic.
+#[derive(Debug, Clone, Copy, Default, Tsify, Serialize, Deserialize)]
+pub struct MinimumEntropy;
+
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+> SelectionHeuristic<N, TState, TPosition, TDirection, T, TGrid> for MinimumEntropy
+{
+    fn select(&mut self, grid: &mut TGrid) -> Option<TPosition> {
+        grid.get_lowest_entropy_position()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::GridInterface,
+        selection::tests::{gen_grid, selection_order},
+        wave_function_collapse::interface::WaveFunctionCollapseInterruption,
+    };
+
+    use super::*;
+
+    #[test]
+    fn matches_tick() {
+        let mut ticked = gen_grid(5, 4);
+        let mut order = Vec::new();
+        loop {
+            let position = ticked.get_lowest_entropy_position();
+            match ticked.tick() {
+                Ok(()) => order.push(position.unwrap()),
+                Err(WaveFunctionCollapseInterruption::Finished) => break,
+                Err(e) => panic!("{e:?}"),
+            }
+        }
+
+        let mut selected = gen_grid(5, 4);
+        assert_eq!(selection_order(&mut selected, MinimumEntropy), order);
+        assert_eq!(selected.image(), ticked.image());
+    }
+}
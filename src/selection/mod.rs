@@ -0,0 +1,209 @@
+//! Heuristics for picking the next tile to collapse
+//!
+//! `WaveFunctionCollapse::tick` always collapses the tile with the lowest entropy, but the order in
+//! which tiles are collapsed has a big effect on both the look of the output and how often
+//! contradictions happen. `WaveFunctionCollapse::run_with_heuristics` takes any of the
+//! heuristics here instead.
+
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{
+    backtracking::ChangeTracker,
+    grid::GridInterface,
+    tile::interface::TileInterface,
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+pub mod entropy;
+pub mod priority;
+pub mod random;
+pub mod remaining_values;
+pub mod scanline;
+pub mod spiral;
+
+pub trait SelectionHeuristic<
+    const NEIGHBOURS_PER_TILE: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<{ NEIGHBOURS_PER_TILE }>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T>,
+>
+{
+    /// Returns the position of the next tile to collapse, or None if every tile has collapsed
+    fn select(&mut self, grid: &mut TGrid) -> Option<TPosition>;
+}
+
+/// Allows reusing the same heuristic, along with its state, across multiple runs
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+    S: SelectionHeuristic<N, TState, TPosition, TDirection, T, TGrid>,
+> SelectionHeuristic<N, TState, TPosition, TDirection, T, TGrid> for &mut S
+{
+    fn select(&mut self, grid: &mut TGrid) -> Option<TPosition> {
+        (**self).select(grid)
+    }
+}
+
+/// Positions of the tiles that haven't collapsed yet, in the order the grid lists them
+pub fn uncollapsed<
+    'a,
+    const N: usize,
+    TState: Hash + Eq + Copy + 'a,
+    TPosition: Location + 'a,
+    TDirection: Direction<N> + 'a,
+    T: TileInterface<TState> + 'a,
+    TGrid: GridInterface<N, TState, TPosition, TDirection, T>,
+>(
+    grid: &'a TGrid,
+) -> impl Iterator<Item = TPosition> + 'a {
+    grid.positions().filter(|position| {
+        grid.get_tile(*position)
+            .is_some_and(|tile| !tile.has_collapsed())
+    })
+}
+
+/// Walks a fixed order of the positions, so tiles that collapsed earlier aren't scanned again on
+/// every pick
+#[derive(Debug)]
+pub(crate) struct Cursor<TPosition: Location> {
+    /// The dimensions the order was calculated for
+    dimensions: Option<TPosition>,
+    pub(crate) order: Vec<TPosition>,
+    /// position -> index in `order`
+    ranks: HashMap<TPosition, usize>,
+    /// Every tile before it in `order` had collapsed as of the last pick
+    next: usize,
+    changes: ChangeTracker<TPosition>,
+}
+
+impl<TPosition: Location> Default for Cursor<TPosition> {
+    fn default() -> Self {
+        Self {
+            dimensions: None,
+            order: Vec::new(),
+            ranks: HashMap::new(),
+            next: 0,
+            changes: ChangeTracker::new(),
+        }
+    }
+}
+
+impl<TPosition: Location> Cursor<TPosition> {
+    /// Returns the first uncollapsed tile in the order `calculate_order` gives for the grid's
+    /// dimensions
+    pub(crate) fn next<
+        const N: usize,
+        TState: Hash + Eq + Copy,
+        TDirection: Direction<N>,
+        T: TileInterface<TState>,
+        TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+        F: FnOnce(&TGrid) -> Vec<TPosition>,
+    >(
+        &mut self,
+        grid: &TGrid,
+        calculate_order: F,
+    ) -> Option<TPosition> {
+        let is_uncollapsed = |position: TPosition| {
+            grid.get_tile(position)
+                .is_some_and(|tile| !tile.has_collapsed())
+        };
+
+        let dimensions = grid.get_dimensions();
+        if self.dimensions != Some(dimensions) {
+            *self = Self {
+                dimensions: Some(dimensions),
+                order: calculate_order(grid),
+                ..Self::default()
+            };
+            self.ranks = (self.order.iter().enumerate())
+                .map(|(rank, position)| (*position, rank))
+                .collect();
+        }
+
+        // backtracking can bring back tiles behind the cursor
+        for position in self.changes.take_dirty(grid) {
+            if let Some(&rank) = self.ranks.get(&position)
+                && rank < self.next
+                && is_uncollapsed(position)
+            {
+                self.next = rank;
+            }
+        }
+        self.changes.compared(grid);
+
+        // another grid of the same size may have been passed in, so the tiles behind the cursor
+        // are checked before giving up
+        let found = (self.next..self.order.len())
+            .chain(0..self.next)
+            .find(|rank| is_uncollapsed(self.order[*rank]))?;
+        self.next = found;
+        Some(self.order[found])
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        grid::{dynamic_2d::DynamicSizeGrid2D, tests::any_combination_grid},
+        tile::Tile,
+        utils::space::s2d::{Direction2D, Location2D},
+        wave_function_collapse::interface::WaveFunctionCollapseInterruption,
+    };
+
+    use super::*;
+
+    /// A grid where any of the three states is allowed next to any other state
+    pub fn gen_grid(width: usize, height: usize) -> DynamicSizeGrid2D<Tile> {
+        any_combination_grid(width, height, 0..3)
+    }
+
+    /// Runs the heuristic on the grid and returns the order in which the tiles were collapsed
+    pub fn selection_order<
+        S: SelectionHeuristic<4, u64, Location2D, Direction2D, Tile, DynamicSizeGrid2D<Tile>>,
+    >(
+        grid: &mut DynamicSizeGrid2D<Tile>,
+        mut heuristic: S,
+    ) -> Vec<Location2D> {
+        let mut order = Vec::new();
+        while let Some(position) = heuristic.select(grid) {
+            order.push(position);
+            grid.collapse(position, None).unwrap();
+        }
+        order
+    }
+
+    #[test]
+    fn uncollapsed_skips_collapsed_tiles() {
+        let mut grid = gen_grid(2, 2);
+        grid.collapse(Location2D { x: 1, y: 0 }, Some(0)).unwrap();
+        let positions: Vec<_> = uncollapsed(&grid).collect();
+        assert_eq!(
+            positions,
+            vec![
+                Location2D { x: 0, y: 0 },
+                Location2D { x: 0, y: 1 },
+                Location2D { x: 1, y: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn run_with_heuristics_finishes() {
+        let mut grid = gen_grid(4, 3);
+        let result = grid.run_with_heuristics(
+            100,
+            None::<crate::backtracking::reset::BacktrackerByReset>,
+            scanline::Scanline::new(),
+            crate::value_selection::weighted::WeightedRandom::default(),
+        );
+        assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+        assert_eq!(uncollapsed(&grid).count(), 0);
+    }
+}
@@ -0,0 +1,81 @@
+//! Lets the user decide the order
+
+use std::{collections::HashMap, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::interface::TileInterface,
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::{SelectionHeuristic, uncollapsed};
+
+/// Picks the uncollapsed tile with the highest user supplied priority. Tiles missing from the map
+/// have a priority of zero, ties go to the tile listed first by the grid.
+#[derive(Debug, Clone, Default, Tsify, Serialize, Deserialize)]
+pub struct PriorityMap<TPosition: Location> {
+    pub priorities: HashMap<TPosition, f64>,
+}
+
+impl<TPosition: Location> PriorityMap<TPosition> {
+    pub fn new(priorities: HashMap<TPosition, f64>) -> Self {
+        Self { priorities }
+    }
+
+    fn priority(&self, position: &TPosition) -> f64 {
+        self.priorities.get(position).copied().unwrap_or_default()
+    }
+}
+
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+> SelectionHeuristic<N, TState, TPosition, TDirection, T, TGrid> for PriorityMap<TPosition>
+{
+    fn select(&mut self, grid: &mut TGrid) -> Option<TPosition> {
+        // `max_by` would prefer the last of equal elements
+        uncollapsed(grid).reduce(|best, position| {
+            if self.priority(&position) > self.priority(&best) {
+                position
+            } else {
+                best
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        selection::tests::{gen_grid, selection_order},
+        utils::space::s2d::Location2D,
+    };
+
+    use super::*;
+
+    #[test]
+    fn highest_priority_first() {
+        let mut grid = gen_grid(2, 2);
+        let map = PriorityMap::new(HashMap::from([
+            (Location2D { x: 1, y: 1 }, 2.0),
+            (Location2D { x: 0, y: 1 }, 1.0),
+            (Location2D { x: 1, y: 0 }, -1.0),
+        ]));
+        assert_eq!(
+            selection_order(&mut grid, map),
+            vec![
+                Location2D { x: 1, y: 1 },
+                Location2D { x: 0, y: 1 },
+                Location2D { x: 0, y: 0 },
+                Location2D { x: 1, y: 0 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Minimum remaining values (MRV)
+
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::interface::TileInterface,
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::{SelectionHeuristic, uncollapsed};
+
+/// Picks the tile with the fewest possible states, ignoring the weights. Ties go to the tile
+/// listed first by the grid.
+#[derive(Debug, Clone, Copy, Default, Tsify, Serialize, Deserialize)]
+pub struct MinimumRemainingValues;
+
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+> SelectionHeuristic<N, TState, TPosition, TDirection, T, TGrid> for MinimumRemainingValues
+{
+    fn select(&mut self, grid: &mut TGrid) -> Option<TPosition> {
+        uncollapsed(grid).min_by_key(|position| {
+            grid.get_tile(*position)
+                .map(|tile| tile.possible_states_ref().count())
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{grid::GridInterface, selection::tests::gen_grid, utils::space::s2d::Location2D};
+
+    use super::*;
+
+    #[test]
+    fn fewest_states_first() {
+        let mut grid = gen_grid(3, 3);
+        assert_eq!(
+            MinimumRemainingValues.select(&mut grid),
+            Some(Location2D { x: 0, y: 0 })
+        );
+
+        let narrowed = Location2D { x: 2, y: 1 };
+        grid.with_tile(narrowed, |t, _| t.set_possible_states([0, 2]));
+        assert_eq!(MinimumRemainingValues.select(&mut grid), Some(narrowed));
+    }
+}
@@ -0,0 +1,142 @@
+//! Collapses the tiles in reading order
+
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::interface::TileInterface,
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::{Cursor, SelectionHeuristic};
+
+/// Picks the first uncollapsed tile in the order the grid lists them, row by row for the 2D
+/// grids. Contradicts a lot more than the other heuristics, but the output grows like a
+/// printout.
+#[derive(Debug, Tsify, Serialize, Deserialize)]
+pub struct Scanline<TPosition: Location> {
+    #[serde(skip)]
+    cursor: Cursor<TPosition>,
+}
+
+impl<TPosition: Location> Scanline<TPosition> {
+    pub fn new() -> Self {
+        Self {
+            cursor: Cursor::default(),
+        }
+    }
+}
+
+impl<TPosition: Location> Default for Scanline<TPosition> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+> SelectionHeuristic<N, TState, TPosition, TDirection, T, TGrid> for Scanline<TPosition>
+{
+    fn select(&mut self, grid: &mut TGrid) -> Option<TPosition> {
+        self.cursor.next(grid, |grid| grid.positions().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::{
+        backtracking::reset::BacktrackerByReset,
+        grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D},
+        selection::{
+            tests::{gen_grid, selection_order},
+            uncollapsed,
+        },
+        tile::{Tile, TileState},
+        utils::space::s2d::{Direction2D, Location2D},
+        value_selection::callback::Callback,
+        wave_function_collapse::interface::WaveFunctionCollapseInterruption,
+    };
+
+    use super::*;
+
+    #[test]
+    fn row_by_row() {
+        let mut grid = gen_grid(3, 2);
+        let order = selection_order(&mut grid, Scanline::new());
+        assert_eq!(
+            order,
+            vec![
+                Location2D { x: 0, y: 0 },
+                Location2D { x: 1, y: 0 },
+                Location2D { x: 2, y: 0 },
+                Location2D { x: 0, y: 1 },
+                Location2D { x: 1, y: 1 },
+                Location2D { x: 2, y: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn goes_back_to_uncollapsed_tiles() {
+        let mut grid = gen_grid(3, 2);
+        let mut scanline = Scanline::new();
+        for _ in 0..4 {
+            let position = scanline.select(&mut grid).unwrap();
+            grid.collapse(position, Some(0)).unwrap();
+        }
+        assert_eq!(scanline.select(&mut grid), Some(Location2D { x: 1, y: 1 }));
+
+        // like a backtracker would
+        grid.with_tile(Location2D { x: 1, y: 0 }, |tile, _| {
+            tile.set_possible_states([0, 1])
+        });
+        assert_eq!(scanline.select(&mut grid), Some(Location2D { x: 1, y: 0 }));
+
+        // a fresh grid of the same size starts from the beginning
+        let mut fresh = gen_grid(3, 2);
+        assert_eq!(scanline.select(&mut fresh), Some(Location2D { x: 0, y: 0 }));
+    }
+
+    /// Checks that every pick is the first uncollapsed tile
+    struct InOrder(Scanline<Location2D>);
+
+    impl SelectionHeuristic<4, u64, Location2D, Direction2D, Tile, DynamicSizeGrid2D<Tile>>
+        for InOrder
+    {
+        fn select(&mut self, grid: &mut DynamicSizeGrid2D<Tile>) -> Option<Location2D> {
+            let picked = self.0.select(grid);
+            assert_eq!(picked, uncollapsed(grid).next());
+            picked
+        }
+    }
+
+    #[test]
+    fn starts_over_after_resets() {
+        let mut grid = gen_grid(3, 2);
+        // contradicts once, halfway through the grid
+        let picks = Cell::new(0);
+        let values = Callback(|_, states: &[TileState], _: &mut _| {
+            picks.set(picks.get() + 1);
+            (picks.get() != 4).then_some(states[0])
+        });
+        let result = grid.run_with_heuristics(
+            100,
+            Some(BacktrackerByReset {}),
+            InOrder(Scanline::new()),
+            values,
+        );
+        assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+        assert_eq!(grid.get_reset_count(), 1);
+        assert_eq!(picks.get(), 4 + 6);
+    }
+}
@@ -0,0 +1,109 @@
+//! Grows the output outwards from the centre of a 2D grid
+
+use std::{cmp::Ordering, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::interface::TileInterface,
+    utils::space::{Direction, s2d::Location2D},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::{Cursor, SelectionHeuristic};
+
+/// Picks the first uncollapsed tile on a spiral starting from the centre of the grid, going
+/// around it counter-clockwise one ring at a time.
+#[derive(Debug, Default, Tsify, Serialize, Deserialize)]
+pub struct Spiral {
+    #[serde(skip)]
+    cursor: Cursor<Location2D>,
+}
+
+impl Spiral {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tiles are sorted by the ring they are on (chebyshev distance to the centre), and then by
+    /// their angle around the centre
+    fn calculate_order(dimensions: Location2D) -> Vec<Location2D> {
+        let centre_x = (dimensions.x.saturating_sub(1) / 2) as isize;
+        let centre_y = (dimensions.y.saturating_sub(1) / 2) as isize;
+        let key = |location: &Location2D| {
+            let dx = location.x as isize - centre_x;
+            let dy = location.y as isize - centre_y;
+            let ring = dx.abs().max(dy.abs());
+            // y grows downwards, flip it so the spiral turns counter-clockwise on screen
+            let angle = (-dy as f64)
+                .atan2(dx as f64)
+                .rem_euclid(std::f64::consts::TAU);
+            (ring, angle)
+        };
+
+        let mut order: Vec<_> = (0..dimensions.y)
+            .flat_map(|y| (0..dimensions.x).map(move |x| Location2D { x, y }))
+            .collect();
+        order.sort_by(|a, b| {
+            let (ring_a, angle_a) = key(a);
+            let (ring_b, angle_b) = key(b);
+            match ring_a.cmp(&ring_b) {
+                Ordering::Equal => angle_a.total_cmp(&angle_b),
+                ordering => ordering,
+            }
+        });
+        order
+    }
+}
+
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, Location2D, TDirection, T>,
+> SelectionHeuristic<N, TState, Location2D, TDirection, T, TGrid> for Spiral
+{
+    fn select(&mut self, grid: &mut TGrid) -> Option<Location2D> {
+        self.cursor
+            .next(grid, |grid| Self::calculate_order(grid.get_dimensions()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::selection::tests::{gen_grid, selection_order};
+
+    use super::*;
+
+    #[test]
+    fn starts_from_the_centre() {
+        let mut grid = gen_grid(3, 3);
+        let order = selection_order(&mut grid, Spiral::new());
+        assert_eq!(
+            order,
+            vec![
+                Location2D { x: 1, y: 1 },
+                Location2D { x: 2, y: 1 },
+                Location2D { x: 2, y: 0 },
+                Location2D { x: 1, y: 0 },
+                Location2D { x: 0, y: 0 },
+                Location2D { x: 0, y: 1 },
+                Location2D { x: 0, y: 2 },
+                Location2D { x: 1, y: 2 },
+                Location2D { x: 2, y: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_resizes() {
+        let mut spiral = Spiral::new();
+        let mut small = gen_grid(2, 2);
+        assert_eq!(spiral.select(&mut small), Some(Location2D { x: 0, y: 0 }));
+        let mut large = gen_grid(5, 5);
+        assert_eq!(spiral.select(&mut large), Some(Location2D { x: 2, y: 2 }));
+        assert_eq!(spiral.cursor.order.len(), 25);
+    }
+}
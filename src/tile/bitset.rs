@@ -0,0 +1,366 @@
+//! Bitset-backed tile implementation
+//!
+//! Each tile knows the sorted list of all states it could ever be in, and stores one bit per state.
+//! Grids create their tiles with `with_index`, so the list is the `StateIndex` of the compiled
+//! ruleset: it is shared between all tiles, cloning a tile only copies the bits and propagating
+//! with a bitmask from the ruleset is done a word at a time.
+//! The width is fixed when the tile is created, states outside of the list are ignored.
+
+use rand::{
+    Rng,
+    distr::{Distribution, weighted::WeightedIndex},
+};
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use super::{
+    TileState,
+    interface::{TileCollapseInstruction, TileInterface},
+};
+use crate::{
+    rules::compiled::{StateIndex, WORD_BITS, mask_has_index},
+    utils::entropy::Entropy,
+};
+
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi, large_number_types_as_bigints)]
+pub struct BitsetTile {
+    /// All states the tile knows about in ascending order, bit `i` corresponds to `states[i]`
+    #[tsify(type = "TileState[]")]
+    states: StateIndex,
+    /// One bit per known state, the width is fixed by the amount of known states
+    bits: Vec<u64>,
+    // can be calculated from bits, but we can spare some memory for better performance
+    count: usize,
+    /// Sum of the weights and sum of w * ln(w) over the possible states
+    weight_sums: Option<(f64, f64)>,
+    entropy: Option<Entropy>,
+}
+
+impl BitsetTile {
+    #[inline]
+    fn invalidate_cache(&mut self) {
+        self.count = self
+            .bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+        self.weight_sums = None;
+        self.entropy = None;
+    }
+
+    /// Returns the indices (into `states`) of all possible states in ascending order
+    #[inline]
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bits.iter().enumerate().flat_map(|(word_index, word)| {
+            let mut remaining = *word;
+            std::iter::from_fn(move || {
+                if remaining == 0 {
+                    return None;
+                }
+                let bit = remaining.trailing_zeros() as usize;
+                // clear the lowest set bit
+                remaining &= remaining - 1;
+                Some(word_index * WORD_BITS + bit)
+            })
+        })
+    }
+
+    /// Intersects the bits with `mask` a word at a time, the mask has to use the same state list
+    #[inline]
+    fn and_words(&mut self, mask: &[u64]) -> bool {
+        let mut was_modified = false;
+        for (word, mask_word) in self.bits.iter_mut().zip(mask) {
+            let intersection = *word & mask_word;
+            was_modified |= intersection != *word;
+            *word = intersection;
+        }
+        if was_modified {
+            self.invalidate_cache();
+        }
+        was_modified
+    }
+
+    /// Returns the sum of weights and the sum of w * ln(w) over the possible states
+    fn weight_sums(&mut self, weights: &std::collections::HashMap<TileState, usize>) -> (f64, f64) {
+        if let Some(cached) = self.weight_sums {
+            return cached;
+        }
+        let mut sum = 0.0;
+        let mut sum_log = 0.0;
+        for state in self.possible_states_ref() {
+            let w = weights.get(state).map(|&w| w as f64).unwrap_or(1.0);
+            sum += w;
+            if w > 0.0 {
+                sum_log += w * w.ln();
+            }
+        }
+        self.weight_sums = Some((sum, sum_log));
+        (sum, sum_log)
+    }
+}
+
+impl TileInterface<TileState> for BitsetTile {
+    /// Creates a tile with its own state list, holding exactly `possible`
+    fn new<I: IntoIterator<Item = TileState>>(possible: I) -> Self {
+        let index = StateIndex::new(possible);
+        let states = index.states().to_vec();
+        Self::with_index(&index, states)
+    }
+
+    /// States of `possible` that are missing from `index` are ignored
+    fn with_index<I: IntoIterator<Item = TileState>>(index: &StateIndex, possible: I) -> Self {
+        let mut new = Self {
+            states: index.clone(),
+            bits: vec![0; index.words()],
+            count: 0,
+            weight_sums: None,
+            entropy: None,
+        };
+        new.set_possible_states(possible);
+
+        new
+    }
+
+    #[inline]
+    fn possible_states_ref<'a>(&'a self) -> impl Iterator<Item = &'a TileState>
+    where
+        TileState: 'a,
+    {
+        self.indices().map(|index| &self.states.states()[index])
+    }
+
+    #[inline]
+    fn possible_states(&self) -> impl Iterator<Item = TileState> {
+        self.possible_states_ref().cloned()
+    }
+
+    #[inline]
+    fn has_collapsed(&self) -> bool {
+        self.count == 1
+    }
+
+    #[inline]
+    fn collapse<R: Rng>(
+        &mut self,
+        value: TileCollapseInstruction<TileState, R>,
+    ) -> Option<TileState> {
+        let chosen_state = match value {
+            // a state outside of the list can never be possible
+            TileCollapseInstruction::Predetermined(value) => {
+                self.states.index_of(value).map(|_| value)?
+            }
+            TileCollapseInstruction::Random(rng, weights) => {
+                let w: Vec<_> = self
+                    .possible_states_ref()
+                    .map(|s| weights.get(s).map(|&w| w as f64).unwrap_or(1.0))
+                    .collect();
+                let dist = WeightedIndex::new(w).unwrap();
+                let chosen_index = dist.sample(rng);
+                self.possible_states().nth(chosen_index)?
+            }
+        };
+
+        self.set_possible_states([chosen_state]);
+        Some(chosen_state)
+    }
+
+    /// States that are missing from the state list are ignored
+    #[inline]
+    fn set_possible_states<I: IntoIterator<Item = TileState>>(&mut self, states: I) {
+        self.bits.fill(0);
+        for state in states {
+            if let Some(index) = self.states.index_of(state) {
+                self.bits[index / WORD_BITS] |= 1 << (index % WORD_BITS);
+            }
+        }
+        self.invalidate_cache();
+    }
+
+    #[inline]
+    fn retain<F: FnMut(&TileState) -> bool>(&mut self, mut f: F) -> bool {
+        let mut was_modified = false;
+        for index in self.indices().collect::<Vec<_>>() {
+            if !f(&self.states.states()[index]) {
+                self.bits[index / WORD_BITS] &= !(1 << (index % WORD_BITS));
+                was_modified = true;
+            }
+        }
+        if was_modified {
+            self.invalidate_cache();
+        }
+        was_modified
+    }
+
+    #[inline]
+    fn intersect(&mut self, other: &Self) -> bool {
+        if self.states != other.states {
+            // the bits don't line up, fall back to checking states one by one
+            let other_states: std::collections::HashSet<_> = other.possible_states().collect();
+            return self.retain(|state| other_states.contains(state));
+        }
+
+        self.and_words(&other.bits)
+    }
+
+    #[inline]
+    fn possible_indices<'a>(&'a self, index: &'a StateIndex) -> impl Iterator<Item = usize> + 'a
+    where
+        TileState: 'a,
+    {
+        let shared = self.states == *index;
+        self.indices().filter_map(move |own| {
+            if shared {
+                Some(own)
+            } else {
+                index.index_of(self.states.state(own))
+            }
+        })
+    }
+
+    #[inline]
+    fn retain_mask(&mut self, index: &StateIndex, mask: &[u64]) -> bool {
+        if self.states != *index {
+            return self.retain(|state| {
+                index
+                    .index_of(*state)
+                    .is_some_and(|i| mask_has_index(mask, i))
+            });
+        }
+        self.and_words(mask)
+    }
+
+    #[inline]
+    fn calculate_entropy<R: Rng>(
+        &mut self,
+        weights: &std::collections::HashMap<TileState, usize>,
+        rng: &mut R,
+    ) -> Option<Entropy> {
+        if self.has_collapsed() {
+            return None;
+        }
+        if let Some(cached) = self.entropy {
+            return Some(cached);
+        }
+
+        let (sum, sum_log) = self.weight_sums(weights);
+        if sum == 0.0 {
+            // No valid states
+            return Some(Entropy(0.0));
+        }
+
+        let entropy = sum.ln() - sum_log / sum;
+        let noise = rng.random::<f64>() * f64::EPSILON;
+        let to_cache = Entropy(entropy + noise);
+        self.entropy = Some(to_cache);
+        Some(to_cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use super::*;
+    use crate::tile::Tile;
+
+    #[test]
+    fn bits_follow_states() {
+        // enough states to span multiple words
+        let mut tile = BitsetTile::new((0..150).map(|i| i * 3));
+        assert_eq!(tile.possible_states().count(), 150);
+        assert_eq!(tile.bits.len(), 3);
+
+        tile.set_possible_states([3, 300, 447]);
+        assert_eq!(
+            tile.possible_states().collect::<Vec<_>>(),
+            vec![3, 300, 447]
+        );
+        assert!(!tile.has_collapsed());
+
+        assert!(tile.retain(|state| *state != 300));
+        assert!(!tile.retain(|state| *state != 300));
+        assert_eq!(tile.possible_states().collect::<Vec<_>>(), vec![3, 447]);
+
+        // the width is fixed, unknown states are ignored
+        tile.set_possible_states([1, 447]);
+        assert_eq!(tile.possible_states().collect::<Vec<_>>(), vec![447]);
+        assert_eq!(tile.bits.len(), 3);
+        assert_eq!(
+            tile.collapse::<rand::rngs::ThreadRng>(TileCollapseInstruction::Predetermined(1)),
+            None
+        );
+
+        tile.set_possible_states([447]);
+        assert!(tile.has_collapsed());
+    }
+
+    #[test]
+    fn intersection() {
+        let all = BitsetTile::new(0..100);
+        let mut a = all.clone();
+        a.set_possible_states([1, 2, 3, 70, 99]);
+        let mut b = all.clone();
+        b.set_possible_states([2, 70, 80]);
+
+        assert!(a.intersect(&b));
+        assert_eq!(a.possible_states().collect::<Vec<_>>(), vec![2, 70]);
+        assert!(!a.intersect(&all));
+
+        // tiles that don't share their state list should give the same result
+        let mut c = BitsetTile::new([70, 1000]);
+        assert!(c.intersect(&a));
+        assert_eq!(c.possible_states().collect::<Vec<_>>(), vec![70]);
+    }
+
+    #[test]
+    fn mask_intersection() {
+        let index = StateIndex::new(0..100);
+        let mut shared = BitsetTile::with_index(&index, [1, 2, 70, 99, 1000]);
+        // states outside of the index are left out
+        assert_eq!(
+            shared.possible_indices(&index).collect::<Vec<_>>(),
+            vec![1, 2, 70, 99]
+        );
+
+        let mut mask = vec![0; index.words()];
+        for state in [2, 70, 80] {
+            mask[state / WORD_BITS] |= 1 << (state % WORD_BITS);
+        }
+        let mut own = BitsetTile::new([2, 3, 70]);
+        assert!(shared.retain_mask(&index, &mask));
+        assert!(!shared.retain_mask(&index, &mask));
+        assert_eq!(shared.possible_states().collect::<Vec<_>>(), vec![2, 70]);
+
+        // a tile with its own state list translates the indices
+        assert_eq!(
+            own.possible_indices(&index).collect::<Vec<_>>(),
+            vec![2, 3, 70]
+        );
+        assert!(own.retain_mask(&index, &mask));
+        assert_eq!(own.possible_states().collect::<Vec<_>>(), vec![2, 70]);
+    }
+
+    #[test]
+    fn entropy_matches_btreeset_tile() {
+        let weights = HashMap::from([(1, 10), (4, 1000), (7, 0)]);
+        for states in [
+            BTreeSet::from([]),
+            BTreeSet::from([1, 2]),
+            BTreeSet::from([1, 2, 3]),
+            BTreeSet::from([1, 2, 3, 4]),
+            BTreeSet::from([2, 7, 9]),
+        ] {
+            let mut reference = Tile::new(states.clone());
+            let mut bitset = BitsetTile::new(states);
+            assert_eq!(reference.has_collapsed(), bitset.has_collapsed());
+            let mut rng_a = rand::rng();
+            let reference_entropy = reference.calculate_entropy(&weights, &mut rng_a);
+            let bitset_entropy = bitset.calculate_entropy(&weights, &mut rng_a);
+            match (reference_entropy, bitset_entropy) {
+                (Some(a), Some(b)) => assert!((a.0 - b.0).abs() < 1e-9),
+                (a, b) => assert_eq!(a, b),
+            }
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 
 use rand::Rng;
 
-use crate::utils::entropy::Entropy;
+use crate::{
+    rules::compiled::{StateIndex, mask_has_index},
+    tile::TileState,
+    utils::entropy::Entropy,
+};
 
 pub enum TileCollapseInstruction<'a, State, R: Rng> {
     Predetermined(State),
@@ -14,6 +18,17 @@ pub enum TileCollapseInstruction<'a, State, R: Rng> {
 pub trait TileInterface<State: Hash + Eq + Copy> {
     fn new<I: IntoIterator<Item = State>>(possible: I) -> Self;
 
+    /// Like `new`, but the tile may share `index` to number its states. Tiles that don't use
+    /// indices ignore it.
+    // automatically implemented for all types that implement TileInterface
+    fn with_index<I: IntoIterator<Item = State>>(index: &StateIndex, possible: I) -> Self
+    where
+        Self: Sized,
+    {
+        let _ = index;
+        Self::new(possible)
+    }
+
     /// Returns an iterator over the possible states of the tile.
     /// No data is copied so the usage of this function should be quite efficient
     fn possible_states_ref<'a>(&'a self) -> impl Iterator<Item = &'a State>
@@ -40,4 +55,51 @@ pub trait TileInterface<State: Hash + Eq + Copy> {
     /// If no available states exist, `None` is returned
     fn collapse<R: Rng>(&mut self, value: TileCollapseInstruction<State, R>) -> Option<State>;
     fn set_possible_states<I: IntoIterator<Item = State>>(&mut self, states: I);
+
+    /// Removes all possible states for which `f` returns false.
+    /// Returns true if any states were removed
+    // automatically implemented for all types that implement TileInterface
+    fn retain<F: FnMut(&State) -> bool>(&mut self, mut f: F) -> bool {
+        let before = self.possible_states_ref().count();
+        let kept: Vec<_> = self.possible_states().filter(|state| f(state)).collect();
+        if kept.len() == before {
+            return false;
+        }
+        self.set_possible_states(kept);
+        true
+    }
+
+    /// Removes all possible states that aren't possible in `other`.
+    /// Returns true if any states were removed
+    // automatically implemented for all types that implement TileInterface
+    fn intersect(&mut self, other: &Self) -> bool {
+        let other_states: HashSet<_> = other.possible_states().collect();
+        self.retain(|state| other_states.contains(state))
+    }
+
+    /// Returns the positions of the possible states in `index` in ascending order, states
+    /// missing from it are skipped
+    // automatically implemented for all types that implement TileInterface
+    fn possible_indices<'a>(&'a self, index: &'a StateIndex) -> impl Iterator<Item = usize> + 'a
+    where
+        State: Into<TileState> + 'a,
+    {
+        // the possible states are listed in ascending order, and so are their indices
+        self.possible_states_ref()
+            .filter_map(|state| index.index_of((*state).into()))
+    }
+
+    /// Removes all possible states whose bit isn't set in `mask`, a bitmask over `index`.
+    /// Returns true if any states were removed
+    // automatically implemented for all types that implement TileInterface
+    fn retain_mask(&mut self, index: &StateIndex, mask: &[u64]) -> bool
+    where
+        State: Into<TileState>,
+    {
+        self.retain(|state| {
+            index
+                .index_of((*state).into())
+                .is_some_and(|i| mask_has_index(mask, i))
+        })
+    }
 }
@@ -1,7 +1,8 @@
 //! Simple tile implementation
 //!
-//! Possible states are stored in a BTreeSet
+//! Possible states are stored in a BTreeSet. See `bitset` for a more compact alternative.
 
+pub mod bitset;
 pub mod interface;
 
 use std::collections::BTreeSet;
@@ -99,6 +100,17 @@ impl TileInterface<TileState> for Tile {
         self.invalidate_cache();
     }
 
+    #[inline]
+    fn retain<F: FnMut(&TileState) -> bool>(&mut self, f: F) -> bool {
+        let before = self.possible_states.len();
+        self.possible_states.retain(f);
+        let was_modified = self.possible_states.len() != before;
+        if was_modified {
+            self.invalidate_cache();
+        }
+        was_modified
+    }
+
     #[inline]
     fn calculate_entropy<R: Rng>(
         &mut self,
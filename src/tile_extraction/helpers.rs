@@ -1,8 +1,15 @@
-use std::hash::{Hash, Hasher};
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
 
 use image::{DynamicImage, Rgba, RgbaImage};
 
-use crate::utils::space::s2d::{Delta2D, Direction2D};
+use crate::{
+    tile::TileState,
+    utils::space::s2d::{Delta2D, Direction2D},
+    wave_function_collapse::symmetry::StateTransforms,
+};
 
 pub fn pattern<F>(f: F, n: usize) -> Vec<u32>
 where
@@ -51,6 +58,37 @@ pub fn reflect(p: &[u32], n: usize) -> Vec<u32> {
     pattern(|x, y| p[n - 1 - x + y * n], n)
 }
 
+/// Pairs every pattern with its mirrored and rotated versions, as far as they are among
+/// `patterns`
+pub fn state_transforms(patterns: &[Vec<u32>], hashes: &[TileState], n: usize) -> StateTransforms {
+    let by_pattern: HashMap<&[u32], TileState> = patterns
+        .iter()
+        .map(Vec::as_slice)
+        .zip(hashes.iter().copied())
+        .collect();
+    let mut transforms = StateTransforms {
+        // patterns are drawn with their pixel at (n / 2, n / 2), see `img_to_repr`
+        offset: usize::from(n.is_multiple_of(2)),
+        ..Default::default()
+    };
+    for (p, state) in patterns.iter().zip(hashes) {
+        let mirrored_x = reflect(p, n);
+        // mirroring left to right and rotating by 180° mirrors top to bottom
+        let mirrored_y = rotate(&rotate(&mirrored_x, n), n);
+        let rotated = rotate(p, n);
+        for (map, variant) in [
+            (&mut transforms.mirror_x, mirrored_x),
+            (&mut transforms.mirror_y, mirrored_y),
+            (&mut transforms.rotation, rotated),
+        ] {
+            if let Some(image) = by_pattern.get(variant.as_slice()) {
+                map.insert(*state, *image);
+            }
+        }
+    }
+    transforms
+}
+
 pub fn hash(p: &[u32]) -> u64 {
     let mut hasher = std::collections::hash_map::DefaultHasher::new();
     for &val in p {
@@ -59,6 +97,22 @@ pub fn hash(p: &[u32]) -> u64 {
     hasher.finish()
 }
 
+/// Packs a pixel into `0xAARRGGBB`, the format of `RuleSet::state_representations`
+pub fn pixel_to_color(pixel: &Rgba<u8>) -> u32 {
+    let [r, g, b, a] = pixel.0;
+    ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
+}
+
+/// Unpacks a `0xAARRGGBB` colour into a pixel
+pub fn color_to_pixel(color: u32) -> Rgba<u8> {
+    Rgba([
+        (color >> 16) as u8,
+        (color >> 8) as u8,
+        color as u8,
+        (color >> 24) as u8,
+    ])
+}
+
 pub fn img_to_repr(image: DynamicImage, n: usize) -> u32 {
     // Convert to a concrete RGBA8 buffer
     let rgba = image.to_rgba8();
@@ -79,11 +133,7 @@ pub fn img_to_repr(image: DynamicImage, n: usize) -> u32 {
     // for odd n: this is the exact middle; for even n: this is the lower-right of the 4 central pixels
     let cx = n / 2;
     let cy = n / 2;
-    let pixel = rgba.get_pixel(cx as u32, cy as u32).0;
-    let (r, g, b, a) = (pixel[0], pixel[1], pixel[2], pixel[3]);
-
-    let repr: u32 = ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
-    return repr;
+    pixel_to_color(rgba.get_pixel(cx as u32, cy as u32))
 }
 
 pub fn pattern_to_image(pattern: &[u32], n: usize) -> DynamicImage {
@@ -91,14 +141,7 @@ pub fn pattern_to_image(pattern: &[u32], n: usize) -> DynamicImage {
 
     for y in 0..n {
         for x in 0..n {
-            let color = pattern[x + y * n];
-            let rgba = Rgba([
-                ((color >> 16) & 0xFF) as u8, // Red
-                ((color >> 8) & 0xFF) as u8,  // Green
-                (color & 0xFF) as u8,         // Blue
-                ((color >> 24) & 0xFF) as u8, // Alpha
-            ]);
-            img.put_pixel(x as u32, y as u32, rgba);
+            img.put_pixel(x as u32, y as u32, color_to_pixel(pattern[x + y * n]));
         }
     }
 
@@ -148,96 +191,53 @@ mod tests {
         assert_eq!(reflected, expected);
     }
 
-    // #[test]
+    #[test]
     fn edges_match_horizontal() {
         let n = 3;
-
-        // Make two patterns where the right edge of p1 matches the left edge of p2
-        // p1: right edge = 3, 6, 9
-        let p1 = pattern(
-            |x, y| {
-                if x == n - 1 {
-                    (y as u32 + 3) | 0xFF000000
-                } else {
-                    0
-                }
-            },
-            n,
-        );
+        let p1 = pattern(|x, y| (x + y * n) as u32 | 0xFF000000, n);
+        // p1 moved one pixel to the left, p2 overlaps p1 when placed on its right
         let p2 = pattern(
-            |x, y| {
-                if x == 0 {
-                    (y as u32 + 3) | 0xFF000000
-                } else {
-                    0
-                }
-            },
+            |x, y| p1[(x + 1).min(n - 1) + y * n] + (x == n - 1) as u32,
             n,
         );
 
         assert!(edges_match(&p1, &p2, Direction2D::RIGHT, n));
         assert!(edges_match(&p2, &p1, Direction2D::LEFT, n));
+        assert!(!edges_match(&p1, &p2, Direction2D::LEFT, n));
 
-        // Mismatch case
-        let p3 = pattern(
-            |x, y| {
-                if x == 0 {
-                    (y as u32 + 99) | 0xFF000000
-                } else {
-                    0
-                }
-            },
-            n,
-        );
+        // Mismatch case: one pixel of the overlap differs
+        let mut p3 = p2.clone();
+        p3[1 + n] = 0;
         assert!(!edges_match(&p1, &p3, Direction2D::RIGHT, n));
     }
 
-    // #[test]
+    #[test]
     fn edges_match_vertical() {
         let n = 3;
-
-        // Make two patterns where the bottom edge of p1 matches the top edge of p2
-        // bottom edge of p1 = 10, 11, 12
-        // top edge of p2    = 10, 11, 12
-        let p1 = pattern(
-            |x, y| {
-                if y == n - 1 {
-                    (x as u32 + 10) | 0xFF000000
-                } else {
-                    0
-                }
-            },
-            n,
-        );
-
+        let p1 = pattern(|x, y| (x + y * n) as u32 | 0xFF000000, n);
+        // p1 moved one pixel up, p2 overlaps p1 when placed below it
         let p2 = pattern(
-            |x, y| {
-                if y == 0 {
-                    (x as u32 + 10) | 0xFF000000
-                } else {
-                    0
-                }
-            },
+            |x, y| p1[x + (y + 1).min(n - 1) * n] + (y == n - 1) as u32,
             n,
         );
 
         assert!(edges_match(&p1, &p2, Direction2D::DOWN, n));
         assert!(edges_match(&p2, &p1, Direction2D::UP, n));
+        assert!(!edges_match(&p1, &p2, Direction2D::UP, n));
 
-        // Mismatch case: p3's top row doesn't match p1's bottom row
-        let p3 = pattern(
-            |x, y| {
-                if y == 0 {
-                    (x as u32 + 99) | 0xFF000000
-                } else {
-                    0
-                }
-            },
-            n,
-        );
+        // Mismatch case: one pixel of the overlap differs
+        let mut p3 = p2.clone();
+        p3[1] = 0;
         assert!(!edges_match(&p1, &p3, Direction2D::DOWN, n));
     }
 
+    #[test]
+    fn color_packing_round_trips() {
+        let pixel = Rgba([0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(pixel_to_color(&pixel), 0x78123456);
+        assert_eq!(color_to_pixel(0x78123456), pixel);
+    }
+
     #[test]
     fn hash_consistency() {
         let p = sample_pattern(3);
@@ -271,12 +271,4 @@ mod tests {
             }
         }
     }
-
-    // #[test]
-    // fn test_img_to_css_bg() {
-    //     let img = DynamicImage::new_rgba8(1, 1);
-    //     let css = img_to_repr(img, 1);
-    //     assert!(css.starts_with("url('data:image/png;base64,"));
-    //     assert!(css.ends_with("')"));
-    // }
 }
@@ -0,0 +1,218 @@
+//! Completing partially drawn images
+//!
+//! Every opaque pixel of the input restricts the matching tile of the output to the states that
+//! stand for its colour, transparent pixels (and optionally pixels of a chosen "blank" colour)
+//! are left for the algorithm to fill in. See `StateMasks` for how the restrictions are kept.
+//!
+//! Colours are stored as `0xAARRGGBB`, like in `RuleSet::state_representations`.
+
+use std::collections::{BTreeSet, HashMap};
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::{
+    backtracking::reset::BacktrackerByReset,
+    grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D, masks::StateMasks},
+    rules::RuleSet2D,
+    tile::{Tile, TileState, interface::TileInterface},
+    tile_extraction::helpers::{color_to_pixel, pixel_to_color},
+    utils::space::s2d::Location2D,
+    wave_function_collapse::interface::{WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+};
+
+/// Selects which states each colour stands for
+#[derive(Debug, Clone)]
+pub enum ColorMapping {
+    /// The states represented by the colour in the rules. For rules made by
+    /// `OverlappingBitmapExtractor` these are all patterns whose centre pixel has the colour.
+    Representations,
+    /// The states listed for the colour
+    Explicit(HashMap<u32, BTreeSet<TileState>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct InpaintingOptions {
+    pub mapping: ColorMapping,
+    /// Pixels of this colour are filled in as well, as if they were transparent
+    pub blank: Option<u32>,
+}
+
+/// Reasons an image can't be completed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InpaintingError {
+    #[error("no state stands for the colour {color:#010x} of the pixel at ({x}, {y})")]
+    UnknownColor { x: usize, y: usize, color: u32 },
+    #[error("the image can't be completed: {0:?}")]
+    Interrupted(WaveFunctionCollapseInterruption<Location2D>),
+}
+
+/// Restricts the tile of every opaque pixel to the states that stand for its colour
+pub fn masks_from_image(
+    image: &DynamicImage,
+    rules: &RuleSet2D,
+    options: &InpaintingOptions,
+) -> Result<StateMasks<Location2D>, InpaintingError> {
+    let by_color = match &options.mapping {
+        ColorMapping::Explicit(mapping) => mapping.clone(),
+        ColorMapping::Representations => {
+            let mut by_color: HashMap<u32, BTreeSet<TileState>> = HashMap::new();
+            for (state, color) in &rules.state_representations {
+                by_color.entry(*color).or_default().insert(*state);
+            }
+            by_color
+        }
+    };
+
+    let mut masks = StateMasks::new();
+    for (x, y, pixel) in image.to_rgba8().enumerate_pixels() {
+        let (x, y) = (x as usize, y as usize);
+        let color = pixel_to_color(pixel);
+        if pixel.0[3] == 0 || options.blank == Some(color) {
+            continue;
+        }
+        let states = by_color
+            .get(&color)
+            .filter(|states| !states.is_empty())
+            .ok_or(InpaintingError::UnknownColor { x, y, color })?;
+        masks.restrict(Location2D { x, y }, states.iter().copied());
+    }
+    Ok(masks)
+}
+
+/// Fills in the transparent and blank pixels of `image` so that the result follows `rules`.
+///
+/// Returns the solved grid, see `grid_to_image` for turning it back into an image.
+pub fn inpaint(
+    image: &DynamicImage,
+    rules: RuleSet2D,
+    options: &InpaintingOptions,
+    rng_seed: u64,
+) -> Result<DynamicSizeGrid2D<Tile>, InpaintingError> {
+    let masks = masks_from_image(image, &rules, options)?;
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut grid = DynamicSizeGrid2D::try_new_periodic(
+        width,
+        height,
+        rules,
+        rng_seed,
+        Default::default(),
+        Default::default(),
+    )
+    .map_err(InpaintingError::Interrupted)?;
+    grid.set_masks(masks)
+        .map_err(InpaintingError::Interrupted)?;
+    match grid.run(width * height * 10 + 1, Some(BacktrackerByReset {})) {
+        Ok(()) | Err(WaveFunctionCollapseInterruption::Finished) => Ok(grid),
+        Err(e) => Err(InpaintingError::Interrupted(e)),
+    }
+}
+
+/// Draws every tile with the representation of its state. Tiles that haven't collapsed, or
+/// whose state has no representation, are left transparent.
+pub fn grid_to_image(grid: &DynamicSizeGrid2D<Tile>) -> RgbaImage {
+    let mut image = RgbaImage::new(grid.width as u32, grid.height as u32);
+    for location in grid.positions() {
+        let color = grid
+            .get_tile(location)
+            .filter(|tile| tile.has_collapsed())
+            .and_then(|tile| tile.possible_states().next())
+            .and_then(|state| grid.rules.represent_tile(state));
+        if let Some(color) = color {
+            image.put_pixel(location.x as u32, location.y as u32, color_to_pixel(color));
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::tile_extraction::{
+        TileExtractor,
+        overlapping_bitmap::{OverlappingBitmapExtractor, OverlappingBitmapExtractorOptions},
+    };
+
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    fn image(width: u32, height: u32, color: impl Fn(u32, u32) -> [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| Rgba(color(x, y))))
+    }
+
+    fn checkers(x: u32, y: u32) -> [u8; 4] {
+        if (x + y).is_multiple_of(2) {
+            RED
+        } else {
+            GREEN
+        }
+    }
+
+    #[test]
+    fn opaque_pixels_become_masks() {
+        let red = pixel_to_color(&Rgba(RED));
+        let green = pixel_to_color(&Rgba(GREEN));
+        let options = InpaintingOptions {
+            mapping: ColorMapping::Explicit(HashMap::from([(red, BTreeSet::from([0, 1]))])),
+            blank: Some(green),
+        };
+        let rules = crate::rules::samples::checkers::rules();
+        let input = image(3, 1, |x, _| [RED, GREEN, CLEAR][x as usize]);
+        let masks = masks_from_image(&input, &rules, &options).unwrap();
+        assert_eq!(masks.iter().count(), 1);
+        assert_eq!(
+            masks.get(Location2D { x: 0, y: 0 }),
+            Some(&BTreeSet::from([0, 1]))
+        );
+
+        // green is no longer blank, and nothing stands for it
+        let options = InpaintingOptions {
+            blank: None,
+            ..options
+        };
+        assert_eq!(
+            masks_from_image(&input, &rules, &options),
+            Err(InpaintingError::UnknownColor {
+                x: 1,
+                y: 0,
+                color: green
+            })
+        );
+    }
+
+    #[test]
+    fn completes_checkers() {
+        let extractor = OverlappingBitmapExtractor::new(
+            image(4, 4, checkers),
+            OverlappingBitmapExtractorOptions {
+                n: 2,
+                symmetry: 1,
+                periodic_input: true,
+            },
+        );
+        let options = InpaintingOptions {
+            mapping: ColorMapping::Representations,
+            blank: None,
+        };
+        // a single green pixel decides the phase of the whole board
+        let input = image(6, 5, |x, y| if (x, y) == (2, 1) { GREEN } else { CLEAR });
+        for seed in 0..5 {
+            let grid = inpaint(&input, extractor.get_rules().clone(), &options, seed).unwrap();
+            let output = grid_to_image(&grid);
+            assert_eq!(output, image(6, 5, checkers).to_rgba8(), "seed {seed}");
+        }
+
+        // no phase fits both pixels
+        let input = image(6, 5, |x, y| match (x, y) {
+            (0, 0) | (1, 0) => RED,
+            _ => CLEAR,
+        });
+        assert!(matches!(
+            inpaint(&input, extractor.get_rules().clone(), &options, 0),
+            Err(InpaintingError::Interrupted(_))
+        ));
+    }
+}
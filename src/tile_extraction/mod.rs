@@ -1,8 +1,11 @@
 use crate::{rules::RuleSet, utils::space::Direction};
 
 mod helpers;
+pub mod inpainting;
 pub mod overlapping_bitmap;
-// pub mod overlapping_text;
+pub mod overlapping_text;
+pub mod simple_tiled;
+pub mod tile_map;
 
 pub trait TileExtractor<
     const NEIGHBOURS_PER_TILE: usize,
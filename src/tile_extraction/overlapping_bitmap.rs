@@ -16,7 +16,7 @@ use crate::{
 
 use super::{
     TileExtractor,
-    helpers::{edges_match, img_to_repr, pattern_to_image},
+    helpers::{edges_match, img_to_repr, pattern_to_image, pixel_to_color, state_transforms},
 };
 
 #[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
@@ -48,10 +48,7 @@ impl OverlappingBitmapExtractor {
 
         let buffer = rgba_image
             .pixels()
-            .map(|pixel| {
-                let [r, g, b, a] = pixel.0;
-                ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
-            })
+            .map(pixel_to_color)
             .collect::<Vec<u32>>();
 
         let (patterns, weights) = Self::extract_patterns(
@@ -83,16 +80,19 @@ impl OverlappingBitmapExtractor {
             .collect();
 
         let allowed = Self::build_adjacency_set(&patterns, &tile_states, options.n);
-
-        Self {
-            ruleset: RuleSet2D::new(
-                BTreeSet::from_iter(tile_states),
-                allowed,
-                tilestate_to_weight,
-                repr,
-                BTreeMap::new(),
-            ),
-        }
+        // with `symmetry` 1 the input only has to contain the variants for them to be paired,
+        // with `symmetry` 8 all of them exist
+        let transforms = state_transforms(&patterns, &tile_states, options.n);
+
+        let mut ruleset = RuleSet2D::new(
+            BTreeSet::from_iter(tile_states),
+            allowed,
+            tilestate_to_weight,
+            repr,
+            BTreeMap::new(),
+        );
+        ruleset.transforms = transforms;
+        Self { ruleset }
     }
 
     fn build_adjacency_set(
@@ -255,6 +255,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn transforms_pair_variants() {
+        // a single red pixel in the top left corner
+        let img = simple_image(3, |x, y| {
+            if (x, y) == (0, 0) {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        });
+        let options = OverlappingBitmapExtractorOptions {
+            n: 2,
+            symmetry: 8,
+            periodic_input: true,
+        };
+        let rules = OverlappingBitmapExtractor::new(img, options).ruleset;
+        let transforms = &rules.transforms;
+        for state in &rules.possible {
+            let mx = transforms.mirror_x[state];
+            let my = transforms.mirror_y[state];
+            let r = transforms.rotation[state];
+            assert_eq!(transforms.mirror_x[&mx], *state);
+            assert_eq!(transforms.mirror_y[&my], *state);
+            let r4 = (0..3).fold(r, |s, _| transforms.rotation[&s]);
+            assert_eq!(r4, *state);
+            // two rotations equal mirroring along both axes
+            assert_eq!(transforms.rotation[&r], transforms.mirror_y[&mx]);
+        }
+        // only the patterns without any red are symmetric
+        assert!(rules.possible.iter().any(|s| transforms.mirror_x[s] != *s));
+    }
+
     // #[test]
     // fn test_css_representation_format() {
     //     let img = simple_image(2, |x, y| {
@@ -1,25 +1,58 @@
+//! Extracts n-gram rules from a text corpus, generated text is produced with a 1D grid
+//!
+//! Each tile is a pattern of `n` consecutive characters, neighbouring patterns have to overlap by
+//! `n - 1` characters. The generated text is made up of the first character of each tile followed
+//! by the rest of the last tile.
+
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
-    hash::{DefaultHasher, Hash, Hasher},
+    hash::{Hash, Hasher},
 };
 
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
 use crate::{
+    backtracking::reset::BacktrackerByReset,
+    grid::{GridInterface, dynamic_1d::DynamicSizeGrid1D},
     rules::RuleSet1D,
-    tile::TileState,
-    utils::space::s1d::{Direction1D, NEIGHBOUR_COUNT_1D},
+    tile::{TileState, interface::TileInterface},
+    utils::space::s1d::{Direction1D, Location1D, NEIGHBOUR_COUNT_1D, Vector1D},
+    wave_function_collapse::interface::{WaveFunctionCollapse, WaveFunctionCollapseInterruption},
 };
 
 use super::TileExtractor;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct OverlappingTextExtractorOptions {
     /// Extracted tokens are n characters long
     pub n: usize,
+    /// If set, the corpus is prefixed with this character and generated text always starts the
+    /// same way as the corpus. Should not appear anywhere in the corpus.
+    pub start_token: Option<char>,
+    /// If set, the corpus is suffixed with this character and generated text always ends the
+    /// same way as the corpus. Should not appear anywhere in the corpus.
+    pub end_token: Option<char>,
+}
+
+/// Reasons text can't be generated
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TextGenerationError {
+    #[error("at least 2 tiles are needed to generate text, got {0}")]
+    TooShort(usize),
+    #[error("the text can't be generated: {0:?}")]
+    Interrupted(WaveFunctionCollapseInterruption<Location1D>),
+    #[error("some tiles didn't collapse into an extracted state")]
+    Uncollapsed,
 }
 
 #[derive(Debug)]
 pub struct OverlappingTextExtractor {
     ruleset: RuleSet1D,
+    options: OverlappingTextExtractorOptions,
+    /// The characters each extracted tile state stands for
+    patterns: HashMap<TileState, Vec<char>>,
 }
 
 impl TileExtractor<NEIGHBOUR_COUNT_1D, Direction1D> for OverlappingTextExtractor {
@@ -30,11 +63,14 @@ impl TileExtractor<NEIGHBOUR_COUNT_1D, Direction1D> for OverlappingTextExtractor
 
 impl OverlappingTextExtractor {
     pub fn new(source: String, options: OverlappingTextExtractorOptions) -> Self {
-        let width = source.len();
-
-        let buffer = source.chars().collect::<Vec<char>>();
+        let buffer: Vec<char> = options
+            .start_token
+            .into_iter()
+            .chain(source.chars())
+            .chain(options.end_token)
+            .collect();
 
-        let (patterns, weights) = Self::extract_patterns(buffer, width, options.n);
+        let (patterns, weights) = Self::extract_patterns(&buffer, options.n);
 
         let mut repr = HashMap::new();
         let mut tilestate_to_pattern = HashMap::new();
@@ -43,11 +79,10 @@ impl OverlappingTextExtractor {
             .iter()
             .enumerate()
             .map(|(i, pattern)| {
-                let mut hasher = DefaultHasher::new();
-                pattern.hash(&mut hasher);
-                let hash = hasher.finish();
+                let hash = hash(pattern);
                 tilestate_to_pattern.insert(hash, pattern.clone());
-                repr.insert(hash, pattern.iter().collect());
+                // the character this tile contributes to the output
+                repr.insert(hash, pattern[0] as u32);
                 tilestate_to_weight.insert(hash, weights[i]);
                 hash
             })
@@ -55,14 +90,29 @@ impl OverlappingTextExtractor {
 
         let allowed = Self::build_adjacency_set(&patterns, &tile_states, options.n);
 
+        // the first and last patterns are the only ones containing the tokens
+        let mut initialize_edges = BTreeMap::new();
+        if options.start_token.is_some()
+            && let Some(first) = patterns.first()
+        {
+            initialize_edges.insert(Direction1D::LEFT, hash(first));
+        }
+        if options.end_token.is_some()
+            && let Some(last) = buffer.len().checked_sub(options.n)
+        {
+            initialize_edges.insert(Direction1D::RIGHT, hash(&buffer[last..]));
+        }
+
         Self {
             ruleset: RuleSet1D::new(
                 BTreeSet::from_iter(tile_states),
                 allowed,
                 tilestate_to_weight,
                 repr,
-                BTreeMap::new(),
+                initialize_edges,
             ),
+            options,
+            patterns: tilestate_to_pattern,
         }
     }
 
@@ -87,49 +137,93 @@ impl OverlappingTextExtractor {
         adjacency
     }
 
-    fn extract_patterns(bitmap: Vec<char>, width: usize, n: usize) -> (Vec<Vec<char>>, Vec<usize>) {
-        use std::collections::HashMap;
-
-        let xmax = width - n + 1;
-
+    fn extract_patterns(text: &[char], n: usize) -> (Vec<Vec<char>>, Vec<usize>) {
         let mut patterns: Vec<Vec<char>> = Vec::new();
         let mut weights: Vec<usize> = Vec::new();
         let mut pattern_indices: HashMap<u64, usize> = HashMap::new();
 
-        for x in 0..xmax {
-            // Base pattern
-            let p = pattern(
-                |dx| {
-                    let sx = (x + dx) % width;
-                    bitmap[sx]
-                },
-                n,
-            );
-
-            let h = hash(&p);
+        if n == 0 {
+            return (patterns, weights);
+        }
+
+        for p in text.windows(n) {
+            let h = hash(p);
             if let Some(&index) = pattern_indices.get(&h) {
                 weights[index] += 1;
             } else {
                 let index = weights.len();
                 pattern_indices.insert(h, index);
-                patterns.push(p.clone());
+                patterns.push(p.to_vec());
                 weights.push(1);
             }
         }
 
         (patterns, weights)
     }
-}
 
-pub fn pattern<F>(f: F, n: usize) -> Vec<char>
-where
-    F: Fn(usize) -> char,
-{
-    let mut result = vec![' '; n];
-    for x in 0..n {
-        result[x] = f(x);
+    /// Returns the characters that the tile state stands for
+    pub fn get_pattern(&self, state: TileState) -> Option<&[char]> {
+        self.patterns.get(&state).map(|pattern| pattern.as_slice())
+    }
+
+    /// Turns a sequence of collapsed tile states back into text, leaving out the start and end
+    /// tokens
+    ///
+    /// Returns None if a state wasn't extracted by this extractor
+    pub fn states_to_text<I: IntoIterator<Item = TileState>>(&self, states: I) -> Option<String> {
+        let mut text = Vec::new();
+        let mut last = None;
+        for state in states {
+            let pattern = self.get_pattern(state)?;
+            text.push(pattern[0]);
+            last = Some(pattern);
+        }
+        if let Some(last) = last {
+            text.extend_from_slice(&last[1..]);
+        }
+
+        let is_token =
+            |c: &char| Some(*c) == self.options.start_token || Some(*c) == self.options.end_token;
+        Some(text.into_iter().filter(|c| !is_token(c)).collect())
+    }
+
+    /// Turns a fully collapsed grid back into text, see `states_to_text`
+    pub fn grid_to_text(&self, grid: &DynamicSizeGrid1D) -> Option<String> {
+        let states: Option<Vec<_>> = (0..grid.width)
+            .map(|x| {
+                let tile = grid.get_tile(Location1D { x })?;
+                if !tile.has_collapsed() {
+                    return None;
+                }
+                tile.possible_states().next()
+            })
+            .collect();
+        self.states_to_text(states?)
+    }
+
+    /// Generates new text with the extracted rules.
+    ///
+    /// `length` is the amount of tiles in the grid, so the text will be `length + n - 1`
+    /// characters long, not counting the start and end tokens. Fails if the start and end
+    /// tokens can't be connected in `length` tiles.
+    pub fn generate(&self, length: usize, rng_seed: u64) -> Result<String, TextGenerationError> {
+        if length < 2 {
+            return Err(TextGenerationError::TooShort(length));
+        }
+        let mut grid = DynamicSizeGrid1D::try_new_periodic(
+            length,
+            self.ruleset.clone(),
+            rng_seed,
+            Vector1D::default(),
+        )
+        .map_err(TextGenerationError::Interrupted)?;
+        match grid.run(length * 10 + 1, Some(BacktrackerByReset {})) {
+            Ok(()) | Err(WaveFunctionCollapseInterruption::Finished) => {}
+            Err(e) => return Err(TextGenerationError::Interrupted(e)),
+        }
+        self.grid_to_text(&grid)
+            .ok_or(TextGenerationError::Uncollapsed)
     }
-    result
 }
 
 pub fn hash(p: &[char]) -> u64 {
@@ -140,10 +234,11 @@ pub fn hash(p: &[char]) -> u64 {
     hasher.finish()
 }
 
+/// Checks if `p2` can be placed in `direction` of `p1`, overlapping by `n - 1` characters
 pub fn edges_match(p1: &[char], p2: &[char], direction: Direction1D, n: usize) -> bool {
     match direction {
-        Direction1D::RIGHT => p1[p1.len() - n..] == p2[..n],
-        Direction1D::LEFT => p1[..n] == p2[p2.len() - n..],
+        Direction1D::RIGHT => p1[1..] == p2[..n - 1],
+        Direction1D::LEFT => p1[..n - 1] == p2[1..],
     }
 }
 
@@ -151,13 +246,17 @@ pub fn edges_match(p1: &[char], p2: &[char], direction: Direction1D, n: usize) -
 mod tests {
     use super::*;
 
+    const SOURCE: &str = "Let's go fishing tomorrow, I know a good spot the rascals haven't found yet. At least I hope so...";
+
     #[test]
     fn test_extractor_basic() {
-        let source = "Let's go fishing tomorrow, I know a good spot the rascals haven't found yet. At least I hope so...".to_owned();
+        let options = OverlappingTextExtractorOptions {
+            n: 3,
+            start_token: None,
+            end_token: None,
+        };
 
-        let options = OverlappingTextExtractorOptions { n: 3 };
-
-        let extractor = OverlappingTextExtractor::new(source, options);
+        let extractor = OverlappingTextExtractor::new(SOURCE.to_owned(), options);
 
         let ruleset = extractor.get_rules();
         let tile_count = ruleset.possible.len();
@@ -172,4 +271,67 @@ mod tests {
             "Adjacency rules must refer to known tile states"
         );
     }
+
+    #[test]
+    fn source_round_trip() {
+        let options = OverlappingTextExtractorOptions {
+            n: 4,
+            start_token: Some('\u{2}'),
+            end_token: Some('\u{3}'),
+        };
+        let extractor = OverlappingTextExtractor::new(SOURCE.to_owned(), options);
+
+        let wrapped: Vec<char> = "\u{2}"
+            .chars()
+            .chain(SOURCE.chars())
+            .chain("\u{3}".chars())
+            .collect();
+        let states = wrapped.windows(4).map(hash);
+        assert_eq!(extractor.states_to_text(states).unwrap(), SOURCE);
+        assert_eq!(extractor.states_to_text([12345]), None);
+    }
+
+    #[test]
+    fn generate_with_tokens() {
+        let options = OverlappingTextExtractorOptions {
+            n: 3,
+            start_token: Some('^'),
+            end_token: Some('$'),
+        };
+        let extractor = OverlappingTextExtractor::new(SOURCE.to_owned(), options);
+
+        for seed in 0..8 {
+            let text = extractor.generate(40, seed).expect("generating text");
+            // 40 tiles cover 42 characters, two of which are the tokens
+            assert_eq!(text.chars().count(), 40);
+            assert!(text.starts_with("Le"), "{text}");
+            assert!(text.ends_with(".."), "{text}");
+
+            // every 3 character window has to appear in the corpus
+            let chars: Vec<char> = text.chars().collect();
+            for window in chars.windows(3) {
+                let window: String = window.iter().collect();
+                assert!(SOURCE.contains(&window), "{window:?} in {text:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn generate_reports_errors() {
+        let options = OverlappingTextExtractorOptions {
+            n: 3,
+            start_token: Some('^'),
+            end_token: Some('$'),
+        };
+        let extractor = OverlappingTextExtractor::new(SOURCE.to_owned(), options);
+        assert_eq!(
+            extractor.generate(1, 0),
+            Err(TextGenerationError::TooShort(1))
+        );
+        // the shortest path from the start to the end token takes more than 2 tiles
+        assert!(matches!(
+            extractor.generate(2, 0),
+            Err(TextGenerationError::Interrupted(_))
+        ));
+    }
 }
@@ -0,0 +1,366 @@
+//! The simple tiled model, with rules written by hand instead of learned from a sample
+//!
+//! A `Tileset` lists named tiles with a symmetry class and the pairs of tiles that may be next
+//! to each other horizontally. Every tile is expanded into its distinct rotations and
+//! reflections, each becoming a state of the rules, and every neighbour pair is rotated and
+//! reflected along with them.
+//!
+//! Follows the tileset format of Maxim Gumin's SimpleTiledModel, written as JSON.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    rules::{RuleSet2D, compose::namespaced_state},
+    tile::TileState,
+    utils::space::s2d::{Direction2D, NEIGHBOUR_COUNT_2D},
+    wave_function_collapse::symmetry::StateTransforms,
+};
+
+use super::TileExtractor;
+
+/// Which rotations and reflections of a tile look the same, named after letters of the same
+/// shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+pub enum TileSymmetry {
+    /// Looks the same in every orientation, 1 state
+    X,
+    /// Mirror symmetric along one axis, 4 states
+    T,
+    /// Mirror symmetric along both axes, 2 states
+    I,
+    /// Mirror symmetric along a diagonal, 4 states
+    L,
+    /// Symmetric when rotated by 180°, 2 states
+    #[serde(rename = "\\")]
+    Backslash,
+    /// No symmetry, 8 states
+    F,
+}
+
+impl TileSymmetry {
+    /// Amount of distinct orientations
+    pub fn cardinality(self) -> usize {
+        match self {
+            TileSymmetry::X => 1,
+            TileSymmetry::I | TileSymmetry::Backslash => 2,
+            TileSymmetry::T | TileSymmetry::L => 4,
+            TileSymmetry::F => 8,
+        }
+    }
+
+    /// The orientation `i` turns into when rotated 90° counterclockwise
+    fn rotate(self, i: usize) -> usize {
+        match self {
+            TileSymmetry::X => i,
+            TileSymmetry::I | TileSymmetry::Backslash => 1 - i,
+            TileSymmetry::T | TileSymmetry::L => (i + 1) % 4,
+            TileSymmetry::F if i < 4 => (i + 1) % 4,
+            TileSymmetry::F => 4 + (i + 3) % 4,
+        }
+    }
+
+    /// The orientation `i` turns into when mirrored left to right
+    fn reflect(self, i: usize) -> usize {
+        match self {
+            TileSymmetry::X | TileSymmetry::I => i,
+            TileSymmetry::Backslash => 1 - i,
+            TileSymmetry::T if i.is_multiple_of(2) => i,
+            TileSymmetry::T => 4 - i,
+            TileSymmetry::L if i.is_multiple_of(2) => i + 1,
+            TileSymmetry::L => i - 1,
+            TileSymmetry::F if i < 4 => i + 4,
+            TileSymmetry::F => i - 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+pub struct TilesetTile {
+    pub name: String,
+    pub symmetry: TileSymmetry,
+    /// Weight of each orientation of the tile
+    #[serde(default = "default_weight")]
+    pub weight: usize,
+}
+
+fn default_weight() -> usize {
+    1
+}
+
+/// `right` may be on the right side of `left`. Both are tile names, optionally followed by a
+/// space and an orientation, like "corner 1".
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+pub struct TilesetNeighbour {
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Tileset {
+    pub tiles: Vec<TilesetTile>,
+    pub neighbours: Vec<TilesetNeighbour>,
+}
+
+impl Tileset {
+    pub fn from_json(json: &str) -> Result<Self, TilesetError> {
+        serde_json::from_str(json).map_err(|e| TilesetError::Parse(e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TilesetError {
+    #[error("invalid tileset: {0}")]
+    Parse(String),
+    #[error("tile {0:?} is defined more than once")]
+    DuplicateTile(String),
+    #[error("neighbour {0:?} refers to an unknown tile")]
+    UnknownTile(String),
+    #[error("neighbour {0:?} has an invalid orientation")]
+    InvalidOrientation(String),
+}
+
+/// How to draw a state: the sprite of `tile`, rotated `rotation` quarter turns counterclockwise
+/// and then mirrored left to right if `reflected` is set
+#[derive(Debug, Clone, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+pub struct TileOrientation {
+    pub tile: String,
+    pub rotation: usize,
+    pub reflected: bool,
+}
+
+#[derive(Debug)]
+pub struct SimpleTiledModel {
+    ruleset: RuleSet2D,
+    orientations: BTreeMap<TileState, TileOrientation>,
+}
+
+impl TileExtractor<NEIGHBOUR_COUNT_2D, Direction2D> for SimpleTiledModel {
+    fn get_rules(&self) -> &RuleSet2D {
+        &self.ruleset
+    }
+}
+
+impl SimpleTiledModel {
+    pub fn new(tileset: &Tileset) -> Result<Self, TilesetError> {
+        let mut symmetries = HashMap::new();
+        for tile in &tileset.tiles {
+            if symmetries
+                .insert(tile.name.as_str(), tile.symmetry)
+                .is_some()
+            {
+                return Err(TilesetError::DuplicateTile(tile.name.clone()));
+            }
+        }
+        // the state of every orientation stays the same as long as the tile keeps its name
+        let state = |name: &str, orientation: usize| namespaced_state(name, orientation as u64);
+
+        let mut possible = BTreeSet::new();
+        let mut weights = HashMap::new();
+        let mut orientations = BTreeMap::new();
+        let mut transforms = StateTransforms::default();
+        for tile in &tileset.tiles {
+            let symmetry = tile.symmetry;
+            for i in 0..symmetry.cardinality() {
+                let s = state(&tile.name, i);
+                possible.insert(s);
+                weights.insert(s, tile.weight);
+                orientations.insert(
+                    s,
+                    TileOrientation {
+                        tile: tile.name.clone(),
+                        rotation: i % 4,
+                        reflected: i >= 4,
+                    },
+                );
+                let (rotated, reflected) = (symmetry.rotate(i), symmetry.reflect(i));
+                // reflecting and rotating by 180° mirrors top to bottom
+                let flipped = symmetry.rotate(symmetry.rotate(reflected));
+                transforms.rotation.insert(s, state(&tile.name, rotated));
+                transforms.mirror_x.insert(s, state(&tile.name, reflected));
+                transforms.mirror_y.insert(s, state(&tile.name, flipped));
+            }
+        }
+
+        let parse = |reference: &str| -> Result<TileState, TilesetError> {
+            let mut parts = reference.split_whitespace();
+            let name = parts.next().unwrap_or_default();
+            let symmetry = symmetries
+                .get(name)
+                .ok_or_else(|| TilesetError::UnknownTile(reference.to_string()))?;
+            let orientation = match parts.next() {
+                None => 0,
+                Some(orientation) => orientation
+                    .parse()
+                    .ok()
+                    .filter(|o| *o < symmetry.cardinality() && parts.next().is_none())
+                    .ok_or_else(|| TilesetError::InvalidOrientation(reference.to_string()))?,
+            };
+            Ok(state(name, orientation))
+        };
+
+        let mut allowed = HashSet::new();
+        for neighbour in &tileset.neighbours {
+            let left = parse(&neighbour.left)?;
+            let right = parse(&neighbour.right)?;
+            let rotate = |s: TileState| transforms.rotation[&s];
+            let mirror_x = |s: TileState| transforms.mirror_x[&s];
+            let mirror_y = |s: TileState| transforms.mirror_y[&s];
+
+            // mirroring top to bottom keeps the sides, mirroring left to right and rotating by
+            // 180° swaps them
+            allowed.insert((left, Direction2D::RIGHT, right));
+            allowed.insert((mirror_y(left), Direction2D::RIGHT, mirror_y(right)));
+            allowed.insert((mirror_x(right), Direction2D::RIGHT, mirror_x(left)));
+            let (left_180, right_180) = (rotate(rotate(left)), rotate(rotate(right)));
+            allowed.insert((right_180, Direction2D::RIGHT, left_180));
+
+            // rotating counterclockwise turns the pair vertical, with the right tile on top
+            let (down, up) = (rotate(left), rotate(right));
+            allowed.insert((up, Direction2D::DOWN, down));
+            allowed.insert((mirror_x(up), Direction2D::DOWN, mirror_x(down)));
+            allowed.insert((mirror_y(down), Direction2D::DOWN, mirror_y(up)));
+            let (down_180, up_180) = (rotate(rotate(down)), rotate(rotate(up)));
+            allowed.insert((down_180, Direction2D::DOWN, up_180));
+        }
+
+        let mut ruleset =
+            RuleSet2D::new(possible, allowed, weights, HashMap::new(), BTreeMap::new());
+        ruleset.transforms = transforms;
+        Ok(Self {
+            ruleset,
+            orientations,
+        })
+    }
+
+    /// How to draw `state`, see `TileOrientation`
+    pub fn orientation(&self, state: TileState) -> Option<&TileOrientation> {
+        self.orientations.get(&state)
+    }
+
+    pub fn orientations(&self) -> &BTreeMap<TileState, TileOrientation> {
+        &self.orientations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backtracking::reset::BacktrackerByReset,
+        grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D},
+        tile::interface::TileInterface,
+        utils::space::s2d::Direction2D::*,
+        wave_function_collapse::interface::{
+            WaveFunctionCollapse, WaveFunctionCollapseInterruption,
+        },
+    };
+
+    use super::*;
+
+    /// Pipes: straight pieces, corners and empty tiles. Orientation 0 of the straight piece is
+    /// vertical, orientation 0 of the corner connects the top and right sides.
+    const PIPES: &str = r#"{
+        "tiles": [
+            { "name": "empty", "symmetry": "X", "weight": 4 },
+            { "name": "line", "symmetry": "I" },
+            { "name": "corner", "symmetry": "L" }
+        ],
+        "neighbours": [
+            { "left": "empty", "right": "empty" },
+            { "left": "empty", "right": "line" },
+            { "left": "line 1", "right": "line 1" },
+            { "left": "empty", "right": "corner" },
+            { "left": "corner", "right": "line 1" },
+            { "left": "corner", "right": "corner 1" },
+            { "left": "line", "right": "empty" },
+            { "left": "corner 1", "right": "empty" }
+        ]
+    }"#;
+
+    fn pipes() -> SimpleTiledModel {
+        SimpleTiledModel::new(&Tileset::from_json(PIPES).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn orientations_become_states() {
+        let model = pipes();
+        let rules = model.get_rules();
+        assert_eq!(rules.possible.len(), 1 + 2 + 4);
+        let line = |i| namespaced_state("line", i);
+        let corner = |i| namespaced_state("corner", i);
+        assert_eq!(
+            model.orientation(corner(3)),
+            Some(&TileOrientation {
+                tile: "corner".to_string(),
+                rotation: 3,
+                reflected: false,
+            })
+        );
+        // horizontal lines connect sideways, vertical lines upwards
+        assert!(rules.allowed.contains(&(line(1), RIGHT, line(1))));
+        assert!(rules.allowed.contains(&(line(0), DOWN, line(0))));
+        assert!(!rules.allowed.contains(&(line(0), RIGHT, line(0))));
+        // a corner connecting the top and right sides is mirrored into one connecting the top
+        // and left sides, which is the same as rotating it counterclockwise
+        assert_eq!(rules.transforms.mirror_x[&corner(0)], corner(1));
+        assert_eq!(rules.transforms.rotation[&corner(0)], corner(1));
+        assert!(rules.analyze().is_ok(), "{:?}", rules.analyze().issues);
+    }
+
+    #[test]
+    fn pipes_can_be_generated() {
+        let rules = pipes().get_rules().clone();
+        let mut grid = DynamicSizeGrid2D::new(12, 12, rules.clone(), 0);
+        assert_eq!(
+            grid.run(12 * 12 * 10, Some(BacktrackerByReset {})),
+            Err(WaveFunctionCollapseInterruption::Finished)
+        );
+        for location in grid.positions() {
+            let state = grid
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .next()
+                .unwrap();
+            for (direction, neighbour) in grid.get_neighbour_tiles(location) {
+                let Some(neighbour) = neighbour else { continue };
+                let neighbour = neighbour.possible_states().next().unwrap();
+                assert!(
+                    rules.allowed.contains(&(state, direction, neighbour)),
+                    "{location:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let tileset = |neighbour: &str| Tileset {
+            tiles: vec![TilesetTile {
+                name: "a".to_string(),
+                symmetry: TileSymmetry::I,
+                weight: 1,
+            }],
+            neighbours: vec![TilesetNeighbour {
+                left: "a".to_string(),
+                right: neighbour.to_string(),
+            }],
+        };
+        assert!(SimpleTiledModel::new(&tileset("a 1")).is_ok());
+        assert_eq!(
+            SimpleTiledModel::new(&tileset("a 2")).unwrap_err(),
+            TilesetError::InvalidOrientation("a 2".to_string())
+        );
+        assert_eq!(
+            SimpleTiledModel::new(&tileset("b")).unwrap_err(),
+            TilesetError::UnknownTile("b".to_string())
+        );
+        assert!(matches!(
+            Tileset::from_json(r#"{ "tiles": [{ "name": "a", "symmetry": "Y" }] }"#),
+            Err(TilesetError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,291 @@
+//! Rules from tile maps, images made of a grid of k ✖ k sprites
+//!
+//! Unlike `OverlappingBitmapExtractor`, the cells don't overlap: every distinct sprite becomes
+//! a state, and two states may only be next to each other if their sprites are next to each
+//! other somewhere in the map.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use image::{DynamicImage, GenericImage};
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    rules::RuleSet2D,
+    tile::TileState,
+    utils::space::s2d::{Delta2D, Direction2D, Location2D, NEIGHBOUR_COUNT_2D},
+};
+
+use super::{
+    TileExtractor,
+    helpers::{hash, img_to_repr, pattern, pattern_to_image, pixel_to_color, state_transforms},
+};
+
+#[derive(Debug, Clone, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct TileMapExtractorOptions {
+    /// Sprites are k ✖ k pixels
+    pub k: usize,
+    /// Cells on opposite sides of the map are neighbours
+    pub periodic_input: bool,
+}
+
+/// Reasons a tile map can't be split into sprites
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TileMapError {
+    #[error("a {width}×{height} image can't be split into {k}×{k} sprites")]
+    SizeNotMultipleOfK {
+        width: usize,
+        height: usize,
+        k: usize,
+    },
+}
+
+#[derive(Debug)]
+pub struct TileMapExtractor {
+    ruleset: RuleSet2D,
+    k: usize,
+    sprites: HashMap<TileState, Vec<u32>>,
+}
+
+impl TileExtractor<NEIGHBOUR_COUNT_2D, Direction2D> for TileMapExtractor {
+    fn get_rules(&self) -> &RuleSet2D {
+        &self.ruleset
+    }
+}
+
+impl TileMapExtractor {
+    /// Fails if the size of `image` isn't a multiple of `k`
+    pub fn new(
+        image: DynamicImage,
+        options: TileMapExtractorOptions,
+    ) -> Result<Self, TileMapError> {
+        let k = options.k;
+        let rgba_image = image.to_rgba8();
+        let (width, height) = rgba_image.dimensions();
+        let (width, height) = (width as usize, height as usize);
+        if k == 0 || !width.is_multiple_of(k) || !height.is_multiple_of(k) {
+            return Err(TileMapError::SizeNotMultipleOfK { width, height, k });
+        }
+        let (columns, rows) = (width / k, height / k);
+
+        let buffer = rgba_image
+            .pixels()
+            .map(pixel_to_color)
+            .collect::<Vec<u32>>();
+
+        let mut sprites = HashMap::new();
+        let mut weights = HashMap::new();
+        // the state of each cell, row by row
+        let cells: Vec<TileState> = (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let sprite = pattern(|dx, dy| buffer[x * k + dx + (y * k + dy) * width], k);
+                let state = hash(&sprite);
+                *weights.entry(state).or_insert(0) += 1;
+                sprites.entry(state).or_insert(sprite);
+                state
+            })
+            .collect();
+
+        let mut allowed = HashSet::new();
+        for y in 0..rows {
+            for x in 0..columns {
+                let state = cells[x + y * columns];
+                // the other directions are added by mirroring
+                for direction in [Direction2D::RIGHT, Direction2D::DOWN] {
+                    let Delta2D { x: dx, y: dy } = Delta2D::from(direction);
+                    let (nx, ny) = (x + dx as usize, y + dy as usize);
+                    let (nx, ny) = if options.periodic_input {
+                        (nx % columns, ny % rows)
+                    } else if nx < columns && ny < rows {
+                        (nx, ny)
+                    } else {
+                        continue;
+                    };
+                    allowed.insert((state, direction, cells[nx + ny * columns]));
+                }
+            }
+        }
+
+        let repr = sprites
+            .iter()
+            .map(|(state, sprite)| (*state, img_to_repr(pattern_to_image(sprite, k), k)))
+            .collect();
+        let (states, patterns): (Vec<TileState>, Vec<Vec<u32>>) = sprites
+            .iter()
+            .map(|(state, sprite)| (*state, sprite.clone()))
+            .unzip();
+
+        let mut ruleset = RuleSet2D::new(
+            BTreeSet::from_iter(states.iter().copied()),
+            allowed,
+            weights,
+            repr,
+            BTreeMap::new(),
+        );
+        ruleset.transforms = state_transforms(&patterns, &states, k);
+        Ok(Self {
+            ruleset,
+            k,
+            sprites,
+        })
+    }
+
+    /// Size of the sprites in pixels
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The full sprite of `state`, as `state_representations` only holds its centre pixel
+    pub fn sprite(&self, state: TileState) -> Option<DynamicImage> {
+        self.sprites
+            .get(&state)
+            .map(|sprite| pattern_to_image(sprite, self.k))
+    }
+
+    /// Draws the sprites of a `width` by `height` grid of tiles. Missing tiles and unknown
+    /// states are left transparent.
+    pub fn render<I: IntoIterator<Item = (Location2D, TileState)>>(
+        &self,
+        width: usize,
+        height: usize,
+        states: I,
+    ) -> DynamicImage {
+        let k = self.k as u32;
+        let mut image = DynamicImage::new_rgba8(width as u32 * k, height as u32 * k);
+        for (location, state) in states {
+            if let Some(sprite) = self.sprite(state) {
+                // out of bounds locations are skipped
+                let _ = image.copy_from(&sprite, location.x as u32 * k, location.y as u32 * k);
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use crate::utils::space::{Direction, s2d::Direction2D::*};
+
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    /// A map of 2 ✖ 2 sprites, 3 columns and 2 rows: a red sprite, a blue sprite and a sprite
+    /// with a red top half
+    ///
+    /// R B R
+    /// H H B
+    fn tile_map() -> DynamicImage {
+        let image = RgbaImage::from_fn(6, 4, |x, y| {
+            let (column, row) = (x / 2, y / 2);
+            let colour = match (column, row) {
+                (0, 0) | (2, 0) => RED,
+                (0, 1) | (1, 1) if y.is_multiple_of(2) => RED,
+                _ => BLUE,
+            };
+            Rgba(colour)
+        });
+        DynamicImage::ImageRgba8(image)
+    }
+
+    fn extract(periodic_input: bool) -> TileMapExtractor {
+        let options = TileMapExtractorOptions {
+            k: 2,
+            periodic_input,
+        };
+        TileMapExtractor::new(tile_map(), options).unwrap()
+    }
+
+    #[test]
+    fn sprites_become_states() {
+        let extractor = extract(false);
+        let rules = extractor.get_rules();
+        assert_eq!(rules.possible.len(), 3);
+        let red = hash(&[0xffff0000; 4]);
+        let blue = hash(&[0xff0000ff; 4]);
+        let half = hash(&[0xffff0000, 0xffff0000, 0xff0000ff, 0xff0000ff]);
+        assert_eq!(rules.weights[&red], 2);
+        assert_eq!(rules.weights[&blue], 2);
+        assert_eq!(rules.weights[&half], 2);
+
+        // only neighbours from the map are allowed
+        let expected = HashSet::from([
+            (red, RIGHT, blue),
+            (blue, RIGHT, red),
+            (half, RIGHT, half),
+            (half, RIGHT, blue),
+            (red, DOWN, half),
+            (blue, DOWN, half),
+            (red, DOWN, blue),
+        ]);
+        let mirrored = expected.iter().map(|(a, d, b)| (*b, d.mirror(), *a));
+        assert_eq!(
+            rules.allowed,
+            expected.iter().copied().chain(mirrored).collect()
+        );
+        assert!(!rules.allowed.contains(&(red, RIGHT, red)));
+
+        // the whole sprite is kept
+        let sprite = extractor.sprite(half).unwrap().to_rgba8();
+        assert_eq!(sprite.get_pixel(1, 0).0, RED);
+        assert_eq!(sprite.get_pixel(1, 1).0, BLUE);
+        assert_eq!(rules.represent_tile(half), Some(0xff0000ff));
+        // flipping the half sprite vertically gives a sprite that isn't in the map
+        assert_eq!(rules.transforms.mirror_x[&half], half);
+        assert!(!rules.transforms.mirror_y.contains_key(&half));
+    }
+
+    #[test]
+    fn periodic_maps_wrap() {
+        let rules = extract(true).get_rules().clone();
+        let red = hash(&[0xffff0000; 4]);
+        let blue = hash(&[0xff0000ff; 4]);
+        let half = hash(&[0xffff0000, 0xffff0000, 0xff0000ff, 0xff0000ff]);
+        assert!(rules.allowed.contains(&(red, RIGHT, red)));
+        assert!(rules.allowed.contains(&(blue, RIGHT, half)));
+        assert!(rules.allowed.contains(&(half, DOWN, red)));
+    }
+
+    #[test]
+    fn map_can_be_rendered() {
+        let extractor = extract(false);
+        let cells = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)];
+        let states = [0xffff0000u32, 0xff0000ff, 0xffff0000]
+            .map(|colour| hash(&[colour; 4]))
+            .into_iter()
+            .chain([
+                hash(&[0xffff0000, 0xffff0000, 0xff0000ff, 0xff0000ff]),
+                hash(&[0xffff0000, 0xffff0000, 0xff0000ff, 0xff0000ff]),
+                hash(&[0xff0000ff; 4]),
+            ]);
+        let located = cells
+            .into_iter()
+            .map(|(x, y)| Location2D { x, y })
+            .zip(states);
+        let rendered = extractor.render(3, 2, located);
+        assert_eq!(rendered.to_rgba8(), tile_map().to_rgba8());
+    }
+
+    #[test]
+    fn size_has_to_be_a_multiple_of_k() {
+        for k in [0, 4] {
+            let options = TileMapExtractorOptions {
+                k,
+                periodic_input: false,
+            };
+            assert_eq!(
+                TileMapExtractor::new(tile_map(), options).unwrap_err(),
+                TileMapError::SizeNotMultipleOfK {
+                    width: 6,
+                    height: 4,
+                    k
+                }
+            );
+        }
+    }
+}
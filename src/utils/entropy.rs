@@ -13,6 +13,7 @@ use super::space::s1d::Location1D;
 // praise the IEEC
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Tsify, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
+#[allow(clippy::derive_ord_xor_partial_ord)] // `Ord` is intentionally reversed, see below
 pub struct Entropy(pub f64);
 
 impl Eq for Entropy {} // Safe because we guarantee consistent Ord
@@ -33,7 +34,7 @@ pub struct EntropyHeapEntry {
 
 impl PartialOrd for EntropyHeapEntry {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.entropy.cmp(&other.entropy))
+        Some(self.cmp(other))
     }
 }
 
@@ -53,7 +54,7 @@ pub struct EntropyHeapEntry1D {
 
 impl PartialOrd for EntropyHeapEntry1D {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.entropy.cmp(&other.entropy))
+        Some(self.cmp(other))
     }
 }
 
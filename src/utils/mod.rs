@@ -1,4 +1,5 @@
 pub mod entropy;
+pub mod noise;
 #[cfg(not(tarpaulin_include))] // we won't unit test the representation layer
 pub mod render;
 pub mod space;
@@ -0,0 +1,144 @@
+//! Seeded 2D noise, used for example to vary state weights over the output
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+pub enum NoiseKind {
+    /// Random values at the corners of a lattice, smoothly interpolated
+    Value,
+    /// Random gradients at the corners of a lattice, smoother and less blocky than value noise
+    Perlin,
+}
+
+/// Fractal noise with values between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq, Tsify, Serialize, Deserialize)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    pub seed: u64,
+    /// Size of the largest features, in tiles
+    pub scale: f64,
+    /// Amount of layers of detail, each half the size and strength of the previous one
+    pub octaves: usize,
+}
+
+impl Noise {
+    pub fn new(kind: NoiseKind, seed: u64, scale: f64) -> Self {
+        Self {
+            kind,
+            seed,
+            scale,
+            octaves: 1,
+        }
+    }
+
+    pub fn with_octaves(mut self, octaves: usize) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    /// Samples the noise at (`x`, `y`), the result is between 0 and 1
+    pub fn sample(&self, x: f64, y: f64) -> f64 {
+        let (mut sum, mut total) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (1.0 / self.scale, 1.0);
+        for octave in 0..self.octaves.max(1) {
+            let seed = self.seed.wrapping_add(octave as u64);
+            let (x, y) = (x * frequency, y * frequency);
+            let value = match self.kind {
+                NoiseKind::Value => value_noise(seed, x, y),
+                // perlin noise stays within ±√0.5
+                NoiseKind::Perlin => perlin_noise(seed, x, y) / std::f64::consts::SQRT_2 + 0.5,
+            };
+            sum += value * amplitude;
+            total += amplitude;
+            frequency *= 2.0;
+            amplitude /= 2.0;
+        }
+        (sum / total).clamp(0.0, 1.0)
+    }
+}
+
+/// SplitMix64 over the lattice point, so no tables have to be kept around
+fn hash(seed: u64, x: i64, y: i64) -> u64 {
+    let mut z = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Smoothstep with zero first and second derivatives at the ends
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn value_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let corner = |dx, dy| unit(hash(seed, x0 + dx, y0 + dy));
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), tx),
+        lerp(corner(0, 1), corner(1, 1), tx),
+        ty,
+    )
+}
+
+fn perlin_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let corner = |dx: i64, dy: i64| {
+        let angle = unit(hash(seed, x0 + dx, y0 + dy)) * std::f64::consts::TAU;
+        angle.cos() * (fx - dx as f64) + angle.sin() * (fy - dy as f64)
+    };
+    let (tx, ty) = (fade(fx), fade(fy));
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), tx),
+        lerp(corner(0, 1), corner(1, 1), tx),
+        ty,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_seeded_and_bounded() {
+        for kind in [NoiseKind::Value, NoiseKind::Perlin] {
+            let noise = Noise::new(kind, 7, 8.0).with_octaves(3);
+            let other = Noise { seed: 8, ..noise };
+            let mut differs = false;
+            for i in 0..200 {
+                let (x, y) = (i as f64 * 0.73, i as f64 * 1.31);
+                let value = noise.sample(x, y);
+                assert!((0.0..=1.0).contains(&value), "{kind:?} {value}");
+                assert_eq!(value, noise.sample(x, y));
+                differs |= value != other.sample(x, y);
+            }
+            assert!(differs, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn noise_is_smooth() {
+        for kind in [NoiseKind::Value, NoiseKind::Perlin] {
+            let noise = Noise::new(kind, 1, 16.0);
+            for i in 0..100 {
+                let x = i as f64 * 0.5;
+                let step = (noise.sample(x, 3.0) - noise.sample(x + 0.5, 3.0)).abs();
+                assert!(step < 0.2, "{kind:?} {step}");
+            }
+        }
+    }
+}
@@ -1,13 +1,62 @@
 use palette::{FromColor, IntoColor, Oklab, Srgb, Srgba};
 
 use crate::{
-    grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D},
+    grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D, dynamic_hex::DynamicSizeHexGrid},
+    rules::RuleSet,
     tile::{TileState, interface::TileInterface},
-    utils::space::s2d::Location2D,
+    utils::space::{
+        Direction,
+        s2d::Location2D,
+        s2d_hex::{DirectionHex, LocationHex, NEIGHBOUR_COUNT_HEX},
+    },
 };
 
 use super::space::s2d::{Direction2D, NEIGHBOUR_COUNT_2D};
 
+/// Averages the colors of the possible states of a tile in the Oklab color space, returns None
+/// if none of them has a representation
+fn fill_color<const N: usize, TDirection: Direction<N>, T: TileInterface<TileState>>(
+    rules: &RuleSet<N, TDirection>,
+    tile: &T,
+) -> Option<String> {
+    let mut lab_sum = Oklab::new(0.0, 0.0, 0.0);
+    let mut alpha_sum = 0.0;
+    let mut count = 0.0;
+
+    for state in tile.possible_states_ref() {
+        if let Some(color) = rules.represent_tile(*state) {
+            let a = ((color >> 24) & 0xFF) as f32 / 255.0;
+            let r = ((color >> 16) & 0xFF) as f32 / 255.0;
+            let g = ((color >> 8) & 0xFF) as f32 / 255.0;
+            let b = (color & 0xFF) as f32 / 255.0;
+
+            let srgba = Srgba::new(r, g, b, a);
+            let lab: Oklab = srgba.into_color();
+
+            lab_sum.l += lab.l;
+            lab_sum.a += lab.a;
+            lab_sum.b += lab.b;
+            alpha_sum += a;
+            count += 1.0;
+        }
+    }
+
+    if count == 0.0 {
+        return None;
+    }
+    let avg_lab = Oklab::new(lab_sum.l / count, lab_sum.a / count, lab_sum.b / count);
+    let avg_alpha = alpha_sum / count;
+
+    let rgb: Srgb<f32> = Srgb::from_color(avg_lab).into_format();
+
+    let r = (rgb.red * 255.0).round() as u8;
+    let g = (rgb.green * 255.0).round() as u8;
+    let b = (rgb.blue * 255.0).round() as u8;
+    let a = avg_alpha;
+
+    Some(format!("rgba({r},{g},{b},{a:.2})"))
+}
+
 pub trait CanvasRenderable<T: TileInterface<TileState> + Clone>:
     GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, T>
 {
@@ -34,50 +83,10 @@ pub trait CanvasRenderable<T: TileInterface<TileState> + Clone>:
                 } else {
                     self.get_tile(Location2D { x, y })
                 };
-                if let Some(tile) = tile_opt {
-                    let states: Vec<_> = tile.possible_states_ref().collect();
-                    if !states.is_empty() {
-                        let mut lab_sum = Oklab::new(0.0, 0.0, 0.0);
-                        let mut alpha_sum = 0.0;
-                        let mut count = 0.0;
-
-                        for state in states {
-                            if let Some(color) = self.get_rules().represent_tile(*state) {
-                                let a = ((color >> 24) & 0xFF) as f32 / 255.0;
-                                let r = ((color >> 16) & 0xFF) as f32 / 255.0;
-                                let g = ((color >> 8) & 0xFF) as f32 / 255.0;
-                                let b = (color & 0xFF) as f32 / 255.0;
-
-                                let srgba = Srgba::new(r, g, b, a);
-                                let lab: Oklab = srgba.into_color();
-
-                                lab_sum.l += lab.l;
-                                lab_sum.a += lab.a;
-                                lab_sum.b += lab.b;
-                                alpha_sum += a;
-                                count += 1.0;
-                            }
-                        }
-
-                        if count > 0.0 {
-                            let avg_lab =
-                                Oklab::new(lab_sum.l / count, lab_sum.a / count, lab_sum.b / count);
-                            let avg_alpha = alpha_sum / count;
-
-                            let rgb: Srgb<f32> = Srgb::from_color(avg_lab).into_format();
-
-                            let r = (rgb.red * 255.0).round() as u8;
-                            let g = (rgb.green * 255.0).round() as u8;
-                            let b = (rgb.blue * 255.0).round() as u8;
-                            let a = avg_alpha;
-
-                            let fill = format!("rgba({r},{g},{b},{a:.2})");
-
-                            out.push_str(&format!(
+                if let Some(fill) = tile_opt.and_then(|tile| fill_color(self.get_rules(), tile)) {
+                    out.push_str(&format!(
                         r#"<rect x="{css_x}" y="{css_y}" width="{cell_w}" height="{cell_h}" fill="{fill}" />"#,
                     ));
-                        }
-                    }
                 }
             }
         }
@@ -87,7 +96,64 @@ pub trait CanvasRenderable<T: TileInterface<TileState> + Clone>:
     }
 }
 
-impl<T: TileInterface<TileState> + Clone> CanvasRenderable<T> for DynamicSizeGrid2D where
-    DynamicSizeGrid2D: GridInterface<4, TileState, Location2D, Direction2D, T>
+impl<T: TileInterface<TileState> + Clone> CanvasRenderable<T> for DynamicSizeGrid2D<T> where
+    DynamicSizeGrid2D<T>: GridInterface<4, TileState, Location2D, Direction2D, T>
+{
+}
+
+/// Renders hex grids as SVG, like `CanvasRenderable` does for square grids.
+///
+/// The grid is drawn with pointy-top hexagons, scaled to fit inside `total_w` x `total_h`
+/// without distorting them.
+pub trait HexCanvasRenderable<T: TileInterface<TileState> + Clone>:
+    GridInterface<NEIGHBOUR_COUNT_HEX, TileState, LocationHex, DirectionHex, T>
+{
+    fn render(&self, total_w: usize, total_h: usize, time: Option<usize>) -> String {
+        let LocationHex {
+            q: columns,
+            r: rows,
+        } = self.get_dimensions();
+        let tiles_at_t = time.map(|t| self.get_tiles_at_time(t));
+
+        // every odd row is shifted by half a hexagon, and the rows overlap by a quarter
+        let sqrt_3 = 3.0_f64.sqrt();
+        let size_by_width = total_w as f64 / ((columns as f64 + 0.5) * sqrt_3);
+        let size_by_height = total_h as f64 / (rows as f64 * 1.5 + 0.5);
+        let size = size_by_width.min(size_by_height);
+
+        let mut out = format!(r#"<svg width="{total_w}" height="{total_h}">"#);
+
+        for location in self.positions() {
+            let tile_opt = if let Some(ref tiles_at_t) = tiles_at_t {
+                tiles_at_t.get(&location)
+            } else {
+                self.get_tile(location)
+            };
+            let Some(fill) = tile_opt.and_then(|tile| fill_color(self.get_rules(), tile)) else {
+                continue;
+            };
+
+            let (column, row) = location.to_offset();
+            let center_x = sqrt_3 * size * (column as f64 + 0.5 + 0.5 * (row & 1) as f64);
+            let center_y = size * (1.0 + 1.5 * row as f64);
+            let points: Vec<_> = (0..6)
+                .map(|corner| {
+                    let angle = (60.0 * corner as f64 - 30.0).to_radians();
+                    let x = center_x + size * angle.cos();
+                    let y = center_y + size * angle.sin();
+                    format!("{x:.3},{y:.3}")
+                })
+                .collect();
+            let points = points.join(" ");
+            out.push_str(&format!(r#"<polygon points="{points}" fill="{fill}" />"#));
+        }
+
+        out.push_str("</svg>");
+        out
+    }
+}
+
+impl<T: TileInterface<TileState> + Clone + PartialEq> HexCanvasRenderable<T>
+    for DynamicSizeHexGrid<T>
 {
 }
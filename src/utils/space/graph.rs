@@ -0,0 +1,21 @@
+//! Space made out of the nodes of an arbitrary graph
+//!
+//! Unlike the lattices, a graph has no coordinates, so a location is just the index of a node.
+//! The directions are provided by the user, see `GraphGrid`.
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use super::Location;
+
+/// Index of a node in a `GraphGrid`
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Hash, Eq, PartialOrd, Ord, Tsify, Serialize, Deserialize,
+)]
+pub struct GraphNode(pub usize);
+
+impl Location for GraphNode {
+    fn length(&self) -> usize {
+        self.0
+    }
+}
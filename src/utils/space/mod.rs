@@ -3,7 +3,9 @@
 use std::fmt::Debug;
 use std::hash::Hash;
 
+pub mod graph;
 pub mod s2d;
+pub mod s2d_hex;
 // 1d or 3d versions are not a part of the core algorithm
 // as such, they won't be unit tested
 #[cfg(not(tarpaulin_include))]
@@ -20,6 +22,36 @@ pub trait Location: Debug + Hash + Eq + Copy + Ord {
 /// Dimension-agnostic direction that can be mirrored
 ///
 /// used for finding neighbours of tiles
-pub trait Direction<const COUNT: usize>: Hash + Eq + Ord {
+pub trait Direction<const COUNT: usize>: Hash + Eq + Ord + Copy {
     fn mirror(self) -> Self;
 }
+
+/// Moves `value` by `delta` along an axis with `size` positions.
+///
+/// Returns None if the result falls outside the axis, unless the axis is periodic, in which case
+/// the result wraps around to the other side.
+pub fn apply_on_axis(value: usize, delta: isize, size: usize, periodic: bool) -> Option<usize> {
+    if periodic && size > 0 {
+        let size = size as isize;
+        let wrapped = (value as isize + delta).rem_euclid(size);
+        return Some(wrapped as usize);
+    }
+    value
+        .checked_add_signed(delta)
+        .filter(|moved| *moved < size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply_on_axis;
+
+    #[test]
+    fn axis_wrapping() {
+        assert_eq!(apply_on_axis(0, -1, 3, false), None);
+        assert_eq!(apply_on_axis(2, 1, 3, false), None);
+        assert_eq!(apply_on_axis(1, 1, 3, false), Some(2));
+        assert_eq!(apply_on_axis(0, -1, 3, true), Some(2));
+        assert_eq!(apply_on_axis(2, 1, 3, true), Some(0));
+        assert_eq!(apply_on_axis(0, 1, 1, true), Some(0));
+    }
+}
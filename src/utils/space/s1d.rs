@@ -16,7 +16,7 @@ pub const AXIS_1D: usize = 1;
 pub const NEIGHBOUR_COUNT_1D: usize = 2 * AXIS_1D;
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord, Tsify, Serialize, Deserialize,
+    Debug, Clone, Copy, Default, PartialEq, Hash, Eq, PartialOrd, Ord, Tsify, Serialize, Deserialize,
 )]
 pub struct Vector1D<T: Copy> {
     pub x: T,
@@ -0,0 +1,206 @@
+//! Two dimensional space made out of pointy-top hexagons
+//!
+//! Locations use axial coordinates: `q` grows towards the east and `r` towards the south-east,
+//! the implicit third cube coordinate is `-q - r`. Each hexagon has six neighbours, two along
+//! each of the three axes.
+
+use std::{
+    hash::Hash,
+    ops::{Add, Sub},
+};
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+use tsify_next::declare;
+
+use super::{Direction, Location};
+
+pub const AXIS_HEX: usize = 3;
+pub const NEIGHBOUR_COUNT_HEX: usize = 2 * AXIS_HEX;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Hash, Eq, PartialOrd, Ord, Tsify, Serialize, Deserialize,
+)]
+pub struct VectorHex<T: Copy> {
+    pub q: T,
+    pub r: T,
+}
+
+/// Axial coordinates can be negative, so locations and deltas share the same type
+#[declare]
+pub type LocationHex = VectorHex<isize>;
+pub type DeltaHex = VectorHex<isize>;
+
+impl LocationHex {
+    pub fn apply(self, delta: DeltaHex) -> Self {
+        self + delta
+    }
+
+    pub fn delta(self, other: Self) -> DeltaHex {
+        other - self
+    }
+
+    /// Amount of steps needed to get from `self` to `other`
+    pub fn distance(self, other: Self) -> usize {
+        let delta = self.delta(other);
+        (delta.q.unsigned_abs() + delta.r.unsigned_abs() + (delta.q + delta.r).unsigned_abs()) / 2
+    }
+
+    /// Converts the location to "odd-r" offset coordinates (column, row), where every odd row is
+    /// shifted half a hexagon to the east. Rectangular hex maps are easiest to store this way.
+    pub fn to_offset(self) -> (isize, isize) {
+        let column = self.q + (self.r - (self.r & 1)) / 2;
+        (column, self.r)
+    }
+
+    /// Inverse of `to_offset`
+    pub fn from_offset(column: isize, row: isize) -> Self {
+        Self {
+            q: column - (row - (row & 1)) / 2,
+            r: row,
+        }
+    }
+}
+
+impl Location for LocationHex {
+    fn length(&self) -> usize {
+        LocationHex::default().distance(*self)
+    }
+}
+
+impl Add<DeltaHex> for DeltaHex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::Output {
+            q: self.q + rhs.q,
+            r: self.r + rhs.r,
+        }
+    }
+}
+impl Sub<DeltaHex> for DeltaHex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::Output {
+            q: self.q - rhs.q,
+            r: self.r - rhs.r,
+        }
+    }
+}
+
+/// The six sides of a pointy-top hexagon, in counter-clockwise order starting from the east.
+///
+/// Opposite directions are always three steps apart.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Tsify, Serialize, Deserialize,
+)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum DirectionHex {
+    EAST = 0,
+    NORTHEAST = 1,
+    NORTHWEST = 2,
+    WEST = 3,
+    SOUTHWEST = 4,
+    SOUTHEAST = 5,
+}
+
+impl TryFrom<usize> for DirectionHex {
+    type Error = ();
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::EAST),
+            1 => Ok(Self::NORTHEAST),
+            2 => Ok(Self::NORTHWEST),
+            3 => Ok(Self::WEST),
+            4 => Ok(Self::SOUTHWEST),
+            5 => Ok(Self::SOUTHEAST),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<DirectionHex> for DeltaHex {
+    fn from(value: DirectionHex) -> Self {
+        match value {
+            DirectionHex::EAST => DeltaHex { q: 1, r: 0 },
+            DirectionHex::NORTHEAST => DeltaHex { q: 1, r: -1 },
+            DirectionHex::NORTHWEST => DeltaHex { q: 0, r: -1 },
+            DirectionHex::WEST => DeltaHex { q: -1, r: 0 },
+            DirectionHex::SOUTHWEST => DeltaHex { q: -1, r: 1 },
+            DirectionHex::SOUTHEAST => DeltaHex { q: 0, r: 1 },
+        }
+    }
+}
+
+impl TryFrom<DeltaHex> for DirectionHex {
+    type Error = ();
+
+    fn try_from(value: DeltaHex) -> Result<Self, Self::Error> {
+        match value {
+            DeltaHex { q: 1, r: 0 } => Ok(DirectionHex::EAST),
+            DeltaHex { q: 1, r: -1 } => Ok(DirectionHex::NORTHEAST),
+            DeltaHex { q: 0, r: -1 } => Ok(DirectionHex::NORTHWEST),
+            DeltaHex { q: -1, r: 0 } => Ok(DirectionHex::WEST),
+            DeltaHex { q: -1, r: 1 } => Ok(DirectionHex::SOUTHWEST),
+            DeltaHex { q: 0, r: 1 } => Ok(DirectionHex::SOUTHEAST),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Direction<NEIGHBOUR_COUNT_HEX> for DirectionHex {
+    fn mirror(self) -> Self {
+        match self {
+            DirectionHex::EAST => DirectionHex::WEST,
+            DirectionHex::NORTHEAST => DirectionHex::SOUTHWEST,
+            DirectionHex::NORTHWEST => DirectionHex::SOUTHEAST,
+            DirectionHex::WEST => DirectionHex::EAST,
+            DirectionHex::SOUTHWEST => DirectionHex::NORTHEAST,
+            DirectionHex::SOUTHEAST => DirectionHex::NORTHWEST,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn mirror_cancels_delta() {
+        for index in 0..NEIGHBOUR_COUNT_HEX {
+            let direction = DirectionHex::try_from(index).unwrap();
+            let mirrored = direction.mirror();
+            assert_ne!(direction, mirrored);
+            assert_eq!(mirrored.mirror(), direction);
+            assert_eq!(
+                DeltaHex::from(direction) + DeltaHex::from(mirrored),
+                DeltaHex::default()
+            );
+            assert_eq!(
+                DirectionHex::try_from(DeltaHex::from(direction)),
+                Ok(direction)
+            );
+        }
+        assert!(DirectionHex::try_from(NEIGHBOUR_COUNT_HEX).is_err());
+    }
+
+    #[test]
+    fn distances_and_offsets() {
+        let origin = LocationHex::default();
+        for index in 0..NEIGHBOUR_COUNT_HEX {
+            let neighbour = origin.apply(DirectionHex::try_from(index).unwrap().into());
+            assert_eq!(origin.distance(neighbour), 1);
+        }
+        assert_eq!(origin.distance(LocationHex { q: 2, r: -1 }), 2);
+        assert_eq!(LocationHex { q: -3, r: 3 }.length(), 3);
+
+        for row in -3..3 {
+            for column in -3..3 {
+                let location = LocationHex::from_offset(column, row);
+                assert_eq!(location.to_offset(), (column, row));
+            }
+        }
+    }
+}
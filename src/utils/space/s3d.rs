@@ -16,7 +16,7 @@ pub const AXIS_3D: usize = 3;
 pub const NEIGHBOUR_COUNT_3D: usize = 2 * AXIS_3D;
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord, Tsify, Serialize, Deserialize,
+    Debug, Clone, Copy, Default, PartialEq, Hash, Eq, PartialOrd, Ord, Tsify, Serialize, Deserialize,
 )]
 pub struct Vector3D<T: Copy> {
     pub x: T,
@@ -0,0 +1,77 @@
+//! Always the heaviest state
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::ValueSelector;
+
+/// Picks the possible state with the most weight in the rules. Ties go to the state listed first
+/// by the tile. Doesn't use any randomness, so the output only depends on the order in which the
+/// tiles are collapsed.
+#[derive(Debug, Clone, Copy, Default, Tsify, Serialize, Deserialize)]
+pub struct ArgmaxWeight;
+
+/// The state with the largest weight, the first one wins ties
+pub(super) fn heaviest(states: &[TileState], weights: &[f64]) -> Option<TileState> {
+    states
+        .iter()
+        .zip(weights)
+        .reduce(|best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+        .map(|(state, _)| *state)
+}
+
+impl<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState>,
+    TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+> ValueSelector<N, TileState, TPosition, TDirection, T, TGrid> for ArgmaxWeight
+{
+    fn select_value(&mut self, grid: &mut TGrid, position: TPosition) -> Option<TileState> {
+        let states: Vec<_> = grid.get_tile(position)?.possible_states().collect();
+        let weights = grid.get_weights(position);
+        let weights: Vec<_> = states
+            .iter()
+            .map(|state| weights.get(state).map(|&w| w as f64).unwrap_or(1.0))
+            .collect();
+        heaviest(&states, &weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::GridInterface,
+        value_selection::tests::{ORIGIN, gen_grid},
+    };
+
+    use super::*;
+
+    #[test]
+    fn picks_heaviest() {
+        let mut grid = gen_grid(2, 2);
+        assert_eq!(ArgmaxWeight.select_value(&mut grid, ORIGIN), Some(2));
+        grid.with_tile(ORIGIN, |t, _| t.set_possible_states([0, 1]));
+        assert_eq!(ArgmaxWeight.select_value(&mut grid, ORIGIN), Some(1));
+        grid.with_tile(ORIGIN, |t, _| t.set_possible_states([]));
+        assert_eq!(ArgmaxWeight.select_value(&mut grid, ORIGIN), None);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_state() {
+        assert_eq!(heaviest(&[4, 5, 6], &[1.0, 3.0, 3.0]), Some(5));
+    }
+}
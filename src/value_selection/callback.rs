@@ -0,0 +1,98 @@
+//! Lets the user decide the state
+
+use std::hash::Hash;
+
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    tile::interface::TileInterface,
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::ValueSelector;
+
+/// Calls a function with the position of the tile, its possible states and the random number
+/// generator of the grid. Using the given generator for any randomness keeps the output
+/// deterministic.
+///
+/// Returning None, or a state the tile can't be in, is treated as a contradiction.
+pub struct Callback<F>(pub F);
+
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+    F: Fn(TPosition, &[TState], &mut ChaCha8Rng) -> Option<TState>,
+> ValueSelector<N, TState, TPosition, TDirection, T, TGrid> for Callback<F>
+{
+    fn select_value(&mut self, grid: &mut TGrid, position: TPosition) -> Option<TState> {
+        let states: Vec<_> = grid.get_tile(position)?.possible_states().collect();
+        let chosen = grid
+            .with_tile(position, |_, rng| (self.0)(position, &states, rng))
+            .flatten()?;
+        states.contains(&chosen).then_some(chosen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{
+        grid::GridInterface,
+        utils::space::s2d::Location2D,
+        value_selection::tests::{ORIGIN, gen_grid},
+        wave_function_collapse::interface::WaveFunctionCollapseInterruption,
+    };
+
+    use super::*;
+
+    #[test]
+    fn calls_back() {
+        let mut grid = gen_grid(2, 1);
+        let mut lowest = Callback(|_, states: &[u64], _: &mut ChaCha8Rng| states.first().copied());
+        assert_eq!(lowest.select_value(&mut grid, ORIGIN), Some(0));
+
+        // a state that isn't possible
+        let mut invalid = Callback(|_, _: &[u64], _: &mut ChaCha8Rng| Some(7));
+        assert_eq!(invalid.select_value(&mut grid, ORIGIN), None);
+        assert_eq!(
+            grid.collapse_with(ORIGIN, &mut invalid),
+            Err(WaveFunctionCollapseInterruption::Contradiction(ORIGIN))
+        );
+    }
+
+    #[test]
+    fn seeded_randomness() {
+        let pick = |position: Location2D, states: &[u64], rng: &mut ChaCha8Rng| {
+            // odd columns get random states
+            if position.x % 2 == 1 {
+                Some(states[rng.random_range(0..states.len())])
+            } else {
+                states.last().copied()
+            }
+        };
+        let mut a = gen_grid(8, 8);
+        let mut b = gen_grid(8, 8);
+        for grid in [&mut a, &mut b] {
+            let result = grid.run_with_heuristics(
+                100,
+                None::<crate::backtracking::reset::BacktrackerByReset>,
+                crate::selection::scanline::Scanline::new(),
+                Callback(pick),
+            );
+            assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+        }
+        assert_eq!(a.image(), b.image());
+        assert!(
+            a.image()
+                .iter()
+                .filter(|(position, _)| position.x % 2 == 0)
+                .all(|(_, tile)| tile.possible_states().eq([2]))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Least constraining value (LCV)
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::ValueSelector;
+
+/// Picks the state that removes the fewest possible states from the neighbouring tiles, which
+/// keeps as many options open as possible and makes contradictions less likely.
+///
+/// Ties go to the state with the most weight, and then to the state listed first by the tile.
+/// Doesn't use any randomness.
+#[derive(Debug, Clone, Copy, Default, Tsify, Serialize, Deserialize)]
+pub struct LeastConstraining;
+
+impl<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState>,
+    TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+> ValueSelector<N, TileState, TPosition, TDirection, T, TGrid> for LeastConstraining
+{
+    fn select_value(&mut self, grid: &mut TGrid, position: TPosition) -> Option<TileState> {
+        let rules = grid.get_compiled_rules();
+        let weights = grid.get_weights(position);
+
+        // the directions in which each neighbour sees this tile
+        let mut neighbours = Vec::new();
+        for (_, neighbour) in grid.get_neighbours(position) {
+            let Some(neighbour) = neighbour else {
+                continue;
+            };
+            let Some(tile) = grid.get_tile(neighbour) else {
+                continue;
+            };
+            for (direction, candidate) in grid.get_neighbours(neighbour) {
+                if candidate == Some(position) {
+                    neighbours.push((direction, tile));
+                }
+            }
+        }
+
+        let mut best: Option<(TileState, usize, usize)> = None;
+        for state in grid.get_tile(position)?.possible_states() {
+            let removed: usize = neighbours
+                .iter()
+                .map(|(direction, tile)| {
+                    let allowed = rules
+                        .index_of(state)
+                        .and_then(|index| rules.compatible_mask(*direction, index));
+                    tile.possible_states_ref()
+                        .filter(|neighbour_state| {
+                            !allowed.is_some_and(|allowed| {
+                                rules.mask_contains(allowed, **neighbour_state)
+                            })
+                        })
+                        .count()
+                })
+                .sum();
+            let weight = weights.get(&state).copied().unwrap_or(1);
+            let better = match best {
+                None => true,
+                Some((_, best_removed, best_weight)) => {
+                    removed < best_removed || (removed == best_removed && weight > best_weight)
+                }
+            };
+            if better {
+                best = Some((state, removed, weight));
+            }
+        }
+        best.map(|(state, _, _)| state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    use crate::{
+        grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D},
+        rules::RuleSet2D,
+        tile::Tile,
+        utils::space::s2d::{Direction2D, Location2D},
+        value_selection::tests::{ORIGIN, gen_grid},
+    };
+
+    use super::*;
+
+    #[test]
+    fn keeps_neighbours_open() {
+        // 0 only allows 0 next to it, 1 allows anything
+        let mut allowed = HashSet::new();
+        for direction in [Direction2D::RIGHT, Direction2D::DOWN] {
+            allowed.insert((0, direction, 0));
+            for other in 0..2 {
+                allowed.insert((1, direction, other));
+            }
+        }
+        let rules = RuleSet2D::new(
+            BTreeSet::from([0, 1]),
+            allowed,
+            HashMap::from([(0, 100)]),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(2, 2, rules, 0);
+        assert_eq!(LeastConstraining.select_value(&mut grid, ORIGIN), Some(1));
+
+        // with 1 gone from the neighbours, 0 removes nothing and wins with its weight
+        for location in [Location2D { x: 1, y: 0 }, Location2D { x: 0, y: 1 }] {
+            grid.with_tile(location, |t, _| t.set_possible_states([0]));
+        }
+        assert_eq!(LeastConstraining.select_value(&mut grid, ORIGIN), Some(0));
+    }
+
+    #[test]
+    fn unconstrained_falls_back_to_weight() {
+        let mut grid = gen_grid(3, 3);
+        assert_eq!(
+            LeastConstraining.select_value(&mut grid, Location2D { x: 1, y: 1 }),
+            Some(2)
+        );
+    }
+}
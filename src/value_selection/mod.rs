@@ -0,0 +1,72 @@
+//! Strategies for picking the state a tile collapses into
+//!
+//! By default a state is sampled randomly using the weights of the rules, see `WeightedRandom`.
+//! `WaveFunctionCollapse::collapse_with` and `WaveFunctionCollapse::run_with_heuristics` take any
+//! of the selectors here instead.
+//!
+//! Selectors that need randomness use the random number generator of the grid, so the output
+//! stays deterministic for a given seed.
+
+use std::hash::Hash;
+
+use crate::{
+    tile::interface::TileInterface,
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+pub mod argmax;
+pub mod callback;
+pub mod least_constraining;
+pub mod weighted;
+
+pub trait ValueSelector<
+    const NEIGHBOURS_PER_TILE: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<{ NEIGHBOURS_PER_TILE }>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T>,
+>
+{
+    /// Picks one of the possible states of the tile at `position`, or None if it has no states
+    /// left to pick from
+    fn select_value(&mut self, grid: &mut TGrid, position: TPosition) -> Option<TState>;
+}
+
+/// Allows reusing the same selector, along with its state, across multiple runs
+impl<
+    const N: usize,
+    TState: Hash + Eq + Copy,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TState>,
+    TGrid: WaveFunctionCollapse<N, TState, TPosition, TDirection, T>,
+    V: ValueSelector<N, TState, TPosition, TDirection, T, TGrid>,
+> ValueSelector<N, TState, TPosition, TDirection, T, TGrid> for &mut V
+{
+    fn select_value(&mut self, grid: &mut TGrid, position: TPosition) -> Option<TState> {
+        (**self).select_value(grid, position)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        grid::{dynamic_2d::DynamicSizeGrid2D, tests::any_combination_rules},
+        tile::Tile,
+        utils::space::s2d::Location2D,
+    };
+
+    /// A grid where any of the three states is allowed next to any other state, with the weights
+    /// 1, 2 and 3
+    pub fn gen_grid(width: usize, height: usize) -> DynamicSizeGrid2D<Tile> {
+        let mut rules = any_combination_rules(0..3);
+        rules.weights = HashMap::from([(0, 1), (1, 2), (2, 3)]);
+        DynamicSizeGrid2D::new(width, height, rules, 0)
+    }
+
+    pub const ORIGIN: Location2D = Location2D { x: 0, y: 0 };
+}
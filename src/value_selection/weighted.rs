@@ -0,0 +1,129 @@
+//! Weighted random sampling, with an optional temperature
+
+use rand::distr::{Distribution, weighted::WeightedIndex};
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+    wave_function_collapse::interface::WaveFunctionCollapse,
+};
+
+use super::ValueSelector;
+
+/// Samples a state randomly, states with more weight in the rules are more likely to be picked.
+///
+/// Each weight is raised to the power of `1 / temperature` before sampling. The default
+/// temperature of 1 keeps the weights as they are, lower temperatures favour the heavy states
+/// even more and higher ones flatten the distribution towards uniform. A temperature of zero or
+/// less always picks the heaviest state, like `ArgmaxWeight`.
+#[derive(Debug, Clone, Copy, Tsify, Serialize, Deserialize)]
+pub struct WeightedRandom {
+    pub temperature: f64,
+}
+
+impl WeightedRandom {
+    pub fn with_temperature(temperature: f64) -> Self {
+        Self { temperature }
+    }
+}
+
+impl Default for WeightedRandom {
+    fn default() -> Self {
+        Self { temperature: 1.0 }
+    }
+}
+
+impl<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState>,
+    TGrid: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+> ValueSelector<N, TileState, TPosition, TDirection, T, TGrid> for WeightedRandom
+{
+    fn select_value(&mut self, grid: &mut TGrid, position: TPosition) -> Option<TileState> {
+        let tile = grid.get_tile(position)?;
+        let states: Vec<_> = tile.possible_states().collect();
+        let weights = grid.get_weights(position);
+        let weights: Vec<_> = states
+            .iter()
+            .map(|state| weights.get(state).map(|&w| w as f64).unwrap_or(1.0))
+            .collect();
+
+        if self.temperature <= 0.0 {
+            return super::argmax::heaviest(&states, &weights);
+        }
+        let exponent = 1.0 / self.temperature;
+        // relative to the heaviest state, so large weights don't overflow at low temperatures
+        let heaviest = weights.iter().copied().fold(0.0, f64::max);
+        let weights: Vec<_> = weights
+            .into_iter()
+            .map(|w| (w / heaviest).powf(exponent))
+            .collect();
+        let dist = WeightedIndex::new(weights).ok()?;
+        // uses the same random number generator as the grid, the same way `Tile::collapse` does
+        let chosen_index = grid.with_tile(position, |_, rng| dist.sample(rng))?;
+        states.get(chosen_index).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        grid::GridInterface,
+        value_selection::tests::{ORIGIN, gen_grid},
+        wave_function_collapse::interface::WaveFunctionCollapseInterruption,
+    };
+
+    use super::*;
+
+    #[test]
+    fn default_matches_collapse() {
+        let mut collapsed = gen_grid(6, 6);
+        let mut selected = gen_grid(6, 6);
+        while let Some(position) = collapsed.get_lowest_entropy_position() {
+            collapsed.collapse(position, None).unwrap();
+            assert_eq!(selected.get_lowest_entropy_position(), Some(position));
+            selected
+                .collapse_with(position, &mut WeightedRandom::default())
+                .unwrap();
+        }
+        assert_eq!(collapsed.image(), selected.image());
+        assert_eq!(
+            selected.run::<crate::backtracking::reset::BacktrackerByReset>(1, None),
+            Err(WaveFunctionCollapseInterruption::Finished)
+        );
+    }
+
+    #[test]
+    fn temperature_shapes_the_distribution() {
+        fn count_heaviest(temperature: f64) -> usize {
+            let mut grid = gen_grid(1, 1);
+            let mut selector = WeightedRandom::with_temperature(temperature);
+            (0..1000)
+                .filter(|_| selector.select_value(&mut grid, ORIGIN) == Some(2))
+                .count()
+        }
+
+        // the heaviest state has half of the weight
+        let normal = count_heaviest(1.0);
+        assert!((400..600).contains(&normal), "{normal}");
+        assert!(count_heaviest(0.25) > normal + 200);
+        assert!(count_heaviest(100.0) < normal - 100);
+        assert_eq!(count_heaviest(0.0), 1000);
+    }
+
+    #[test]
+    fn large_weights_survive_low_temperatures() {
+        let mut grid = gen_grid(1, 1);
+        grid.rules.weights = HashMap::from([(0, 1 << 20), (1, 1 << 30), (2, 1 << 40)]);
+        let mut selector = WeightedRandom::with_temperature(0.001);
+        for _ in 0..100 {
+            assert_eq!(selector.select_value(&mut grid, ORIGIN), Some(2));
+        }
+    }
+}
@@ -13,11 +13,17 @@ use wasm_bindgen::prelude::*;
 
 use crate::{
     backtracking::{
-        Backtracker, gradual_reset::BacktrackerByGradualReset, reset::BacktrackerByReset,
+        Backtracker, backjumping::BacktrackerByBackjumping,
+        gradual_reset::BacktrackerByGradualReset, reset::BacktrackerByReset,
+        undo::BacktrackingByUndo,
     },
     grid::dynamic_2d::DynamicSizeGrid2D,
     rules::RuleSet2D,
-    tile::{Tile, TileState, interface::TileInterface},
+    selection::{
+        SelectionHeuristic, entropy::MinimumEntropy, priority::PriorityMap, random::UniformRandom,
+        remaining_values::MinimumRemainingValues, scanline::Scanline, spiral::Spiral,
+    },
+    tile::{Tile, TileState, bitset::BitsetTile, interface::TileInterface},
     tile_extraction::{
         TileExtractor,
         overlapping_bitmap::{OverlappingBitmapExtractor, OverlappingBitmapExtractorOptions},
@@ -26,11 +32,17 @@ use crate::{
         render::CanvasRenderable,
         space::{
             Direction,
-            s2d::{Direction2D, Location2D},
+            s2d::{Direction2D, Location2D, Vector2D},
         },
     },
-    wave_function_collapse::interface::{
-        TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption,
+    value_selection::{
+        ValueSelector, argmax::ArgmaxWeight, least_constraining::LeastConstraining,
+        weighted::WeightedRandom,
+    },
+    wave_function_collapse::{
+        interface::{TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+        propagation::PropagationStrategy,
+        symmetry::Symmetry2D,
     },
 };
 
@@ -197,8 +209,37 @@ impl Rules {
     }
 }
 
+/// Selects how the possible states of each tile are stored, see `Tile` and `BitsetTile`
+#[wasm_bindgen]
+pub enum TileVariant {
+    BTreeSet,
+    Bitset,
+}
+
+enum GridInner {
+    BTreeSet(DynamicSizeGrid2D<Tile>),
+    Bitset(DynamicSizeGrid2D<BitsetTile>),
+}
+
+/// Runs the same expression on the inner grid, whichever tile type it uses
+macro_rules! with_inner {
+    ($grid:expr, $inner:ident => $body:expr) => {
+        match $grid {
+            GridInner::BTreeSet($inner) => $body,
+            GridInner::Bitset($inner) => $body,
+        }
+    };
+}
+
+/// The grid, the heuristics used for picking which of its tiles to collapse next, and into
+/// which state, and the backtracker `tick` uses
 #[wasm_bindgen]
-pub struct Grid(DynamicSizeGrid2D);
+pub struct Grid(
+    GridInner,
+    Selection2D,
+    ValueSelection2D,
+    Option<Backtracker2D>,
+);
 
 #[derive(Tsify, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -207,41 +248,101 @@ pub struct Dimensions {
     pub height: usize,
 }
 
+/// Axes along which the grid wraps around
+#[derive(Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PeriodicAxes {
+    pub x: bool,
+    pub y: bool,
+}
+
 #[wasm_bindgen]
 impl Grid {
     #[wasm_bindgen(constructor)]
-    pub fn new(rng_seed: u64, rules: Rules, width: usize, height: usize) -> Self {
+    pub fn new(
+        rng_seed: u64,
+        rules: Rules,
+        width: usize,
+        height: usize,
+        propagation: Option<PropagationStrategy>,
+        tile_variant: Option<TileVariant>,
+        periodic: Option<PeriodicAxes>,
+    ) -> Self {
         console_error_panic_hook::set_once();
-        let inner = DynamicSizeGrid2D::new(width, height, rules.0, rng_seed);
-        Self(inner)
+        let propagation = propagation.unwrap_or_default();
+        let periodic = periodic
+            .map(|axes| Vector2D {
+                x: axes.x,
+                y: axes.y,
+            })
+            .unwrap_or_default();
+        let inner = match tile_variant.unwrap_or(TileVariant::BTreeSet) {
+            TileVariant::BTreeSet => GridInner::BTreeSet(DynamicSizeGrid2D::new_periodic(
+                width,
+                height,
+                rules.0,
+                rng_seed,
+                propagation,
+                periodic,
+            )),
+            TileVariant::Bitset => GridInner::Bitset(DynamicSizeGrid2D::new_periodic(
+                width,
+                height,
+                rules.0,
+                rng_seed,
+                propagation,
+                periodic,
+            )),
+        };
+        Self(
+            inner,
+            Selection2D::default(),
+            ValueSelection2D::default(),
+            None,
+        )
+    }
+
+    /// Changes how the next tile to collapse is picked, used by both `tick` and `run`
+    pub fn set_selection(&mut self, selection: Selection2D) {
+        self.1 = selection;
+    }
+
+    /// Changes how the state of each collapsed tile is picked, used by both `tick` and `run`
+    pub fn set_value_selection(&mut self, values: ValueSelection2D) {
+        self.2 = values;
+    }
+
+    /// Changes how contradictions are handled by `tick`. The backtracker is kept between ticks,
+    /// so it remembers the decisions made in earlier ones.
+    pub fn set_backtracker(&mut self, variant: Option<BacktrackerVariant>) {
+        self.3 = variant.map(new_backtracker);
     }
 
     pub fn get_dimensions(&self) -> Dimensions {
-        Dimensions {
-            width: self.0.width,
-            height: self.0.height,
-        }
+        with_inner!(&self.0, grid => Dimensions {
+            width: grid.width,
+            height: grid.height,
+        })
     }
 
     pub fn render(&self, w: usize, h: usize, time: Option<usize>) -> String {
-        self.0.render(w, h, time)
+        with_inner!(&self.0, grid => grid.render(w, h, time))
     }
 
     pub fn get_history_len(&self) -> usize {
-        self.0.update_log.len()
+        with_inner!(&self.0, grid => grid.update_log.len())
     }
 
     pub fn is_finished(&self) -> bool {
-        let uncollapsed_tile_exists = self
-            .0
+        let uncollapsed_tile_exists = with_inner!(&self.0, grid => grid
             .tiles_ref()
             .iter()
-            .any(|t| t.possible_states_ref().count() != 1);
+            .any(|t| t.possible_states_ref().count() != 1));
         !uncollapsed_tile_exists
     }
 
     pub fn collapse(&mut self, x: usize, y: usize, value: Option<TileState>) -> Option<bool> {
-        let result = self.0.collapse(Location2D { x, y }, value);
+        let result = with_inner!(&mut self.0, grid => grid.collapse(Location2D { x, y }, value));
         let done = match result {
             Err(WaveFunctionCollapseInterruption::Finished) => true,
             Err(_) => return None,
@@ -250,8 +351,29 @@ impl Grid {
         Some(done)
     }
 
-    pub fn tick(&mut self, backtracker: Option<Backtracker2D>) -> Option<bool> {
-        let result = self.0.run(1, backtracker);
+    /// Pins the tile to `value` for the rest of the run, see `DynamicSizeGrid2D::pin`. Returns
+    /// the reason as an error message if the tile can't be pinned.
+    pub fn pin(&mut self, x: usize, y: usize, value: TileState) -> Result<(), String> {
+        with_inner!(&mut self.0, grid => grid.pin(Location2D { x, y }, value))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Makes the output symmetric, see `DynamicSizeGrid2D::set_symmetry`. Returns false if the
+    /// current tiles break the symmetry, or if a 90° rotation is asked of a grid that isn't
+    /// square.
+    pub fn set_symmetry(&mut self, symmetry: Option<Symmetry2D>) -> bool {
+        with_inner!(&mut self.0, grid => grid.set_symmetry(symmetry)).is_ok()
+    }
+
+    /// Collapses one tile, handling contradictions with the backtracker from `set_backtracker`
+    pub fn tick(&mut self) -> Option<bool> {
+        let (selection, values, backtracker) = (&mut self.1, &mut self.2, &mut self.3);
+        let result = with_inner!(&mut self.0, grid => grid.run_with_heuristics(
+            1,
+            backtracker.as_mut(),
+            &mut *selection,
+            &mut *values,
+        ));
         let done = match result {
             Err(WaveFunctionCollapseInterruption::Finished) => true,
             Err(WaveFunctionCollapseInterruption::MaxIterationsReached) => false,
@@ -267,7 +389,13 @@ impl Grid {
         backtracker_variant: Option<BacktrackerVariant>,
     ) -> Option<bool> {
         let b = backtracker_variant.map(new_backtracker);
-        let result = self.0.run(max_iter, b);
+        let (selection, values) = (&mut self.1, &mut self.2);
+        let result = with_inner!(&mut self.0, grid => grid.run_with_heuristics(
+            max_iter,
+            b,
+            &mut *selection,
+            &mut *values,
+        ));
         let done = match result {
             Err(WaveFunctionCollapseInterruption::Finished) => true,
             Err(_) => return None,
@@ -281,6 +409,8 @@ impl Grid {
 pub enum BacktrackerVariant {
     Reset,
     GradualReset,
+    Undo,
+    Backjumping,
 }
 
 #[wasm_bindgen]
@@ -290,6 +420,10 @@ pub fn new_backtracker(variant: BacktrackerVariant) -> Backtracker2D {
         BacktrackerVariant::GradualReset => {
             Backtracker2D::GradualReset(BacktrackerByGradualReset::new(1))
         }
+        BacktrackerVariant::Undo => Backtracker2D::Undo(BacktrackingByUndo::new()),
+        BacktrackerVariant::Backjumping => {
+            Backtracker2D::Backjumping(BacktrackerByBackjumping::new())
+        }
     }
 }
 
@@ -298,15 +432,34 @@ pub fn new_backtracker(variant: BacktrackerVariant) -> Backtracker2D {
 pub enum Backtracker2D {
     Reset(BacktrackerByReset),
     GradualReset(BacktrackerByGradualReset<Location2D>),
+    Undo(BacktrackingByUndo<Location2D>),
+    Backjumping(BacktrackerByBackjumping<Location2D>),
 }
 
 impl<
     const N: usize,
     TDirection: Direction<N>,
-    T: TileInterface<TileState>,
+    T: TileInterface<TileState> + Clone,
     TGrid: WaveFunctionCollapse<N, TileState, Location2D, TDirection, T>,
 > Backtracker<N, TileState, Location2D, TDirection, T, TGrid> for Backtracker2D
 {
+    fn tick(
+        &mut self,
+        grid: &mut TGrid,
+        position: Location2D,
+        value: TileState,
+    ) -> TickResult<Location2D> {
+        match self {
+            Backtracker2D::Undo(backtracking_by_undo) => {
+                backtracking_by_undo.tick(grid, position, value)
+            }
+            Backtracker2D::Backjumping(backtracker_by_backjumping) => {
+                backtracker_by_backjumping.tick(grid, position, value)
+            }
+            _ => grid.collapse(position, Some(value)),
+        }
+    }
+
     fn contradiction_handler(
         &mut self,
         grid: &mut TGrid,
@@ -319,6 +472,124 @@ impl<
             Backtracker2D::GradualReset(backtracker_by_gradual_reset) => {
                 backtracker_by_gradual_reset.contradiction_handler(grid, contradiction_location)
             }
+            Backtracker2D::Undo(backtracking_by_undo) => {
+                backtracking_by_undo.contradiction_handler(grid, contradiction_location)
+            }
+            Backtracker2D::Backjumping(backtracker_by_backjumping) => {
+                backtracker_by_backjumping.contradiction_handler(grid, contradiction_location)
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub enum SelectionVariant {
+    MinimumEntropy,
+    MinimumRemainingValues,
+    Scanline,
+    Spiral,
+    Random,
+}
+
+/// `seed` is only used by `SelectionVariant::Random`
+#[wasm_bindgen]
+pub fn new_selection(variant: SelectionVariant, seed: u64) -> Selection2D {
+    match variant {
+        SelectionVariant::MinimumEntropy => Selection2D::MinimumEntropy(MinimumEntropy),
+        SelectionVariant::MinimumRemainingValues => {
+            Selection2D::MinimumRemainingValues(MinimumRemainingValues)
+        }
+        SelectionVariant::Scanline => Selection2D::Scanline(Scanline::new()),
+        SelectionVariant::Spiral => Selection2D::Spiral(Spiral::new()),
+        SelectionVariant::Random => Selection2D::Random(Box::new(UniformRandom::new(seed))),
+    }
+}
+
+/// Collapses the tiles with the highest priority first, `priorities` is indexed row by row like
+/// the pixels of an image. Missing values count as zero. Returns an error message if `width` is
+/// zero.
+#[wasm_bindgen]
+pub fn new_priority_selection(width: usize, priorities: Vec<f64>) -> Result<Selection2D, String> {
+    if width == 0 {
+        return Err("the width of the priorities has to be positive".to_string());
+    }
+    let priorities = priorities
+        .into_iter()
+        .enumerate()
+        .map(|(i, priority)| {
+            let location = Location2D {
+                x: i % width,
+                y: i / width,
+            };
+            (location, priority)
+        })
+        .collect();
+    Ok(Selection2D::Priority(PriorityMap::new(priorities)))
+}
+
+#[derive(Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Selection2D {
+    MinimumEntropy(MinimumEntropy),
+    MinimumRemainingValues(MinimumRemainingValues),
+    Scanline(Scanline<Location2D>),
+    Spiral(Spiral),
+    Random(Box<UniformRandom>),
+    Priority(PriorityMap<Location2D>),
+}
+
+impl Default for Selection2D {
+    fn default() -> Self {
+        Self::MinimumEntropy(MinimumEntropy)
+    }
+}
+
+impl<
+    const N: usize,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState>,
+    TGrid: WaveFunctionCollapse<N, TileState, Location2D, TDirection, T>,
+> SelectionHeuristic<N, TileState, Location2D, TDirection, T, TGrid> for Selection2D
+{
+    fn select(&mut self, grid: &mut TGrid) -> Option<Location2D> {
+        match self {
+            Selection2D::MinimumEntropy(heuristic) => heuristic.select(grid),
+            Selection2D::MinimumRemainingValues(heuristic) => heuristic.select(grid),
+            Selection2D::Scanline(heuristic) => heuristic.select(grid),
+            Selection2D::Spiral(heuristic) => heuristic.select(grid),
+            Selection2D::Random(heuristic) => heuristic.select(grid),
+            Selection2D::Priority(heuristic) => heuristic.select(grid),
+        }
+    }
+}
+
+/// Picks the state each tile collapses into, see `crate::value_selection`
+#[derive(Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ValueSelection2D {
+    Weighted(WeightedRandom),
+    Argmax(ArgmaxWeight),
+    LeastConstraining(LeastConstraining),
+}
+
+impl Default for ValueSelection2D {
+    fn default() -> Self {
+        Self::Weighted(WeightedRandom::default())
+    }
+}
+
+impl<
+    const N: usize,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState>,
+    TGrid: WaveFunctionCollapse<N, TileState, Location2D, TDirection, T>,
+> ValueSelector<N, TileState, Location2D, TDirection, T, TGrid> for ValueSelection2D
+{
+    fn select_value(&mut self, grid: &mut TGrid, position: Location2D) -> Option<TileState> {
+        match self {
+            ValueSelection2D::Weighted(selector) => selector.select_value(grid, position),
+            ValueSelection2D::Argmax(selector) => selector.select_value(grid, position),
+            ValueSelection2D::LeastConstraining(selector) => selector.select_value(grid, position),
         }
     }
 }
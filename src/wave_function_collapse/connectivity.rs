@@ -0,0 +1,318 @@
+//! Keeping the walkable parts of the output connected
+//!
+//! A `ConnectivityConstraint` marks some states as walkable and requires all tiles collapsed
+//! into them to form a single connected region, optionally with a path between two given
+//! tiles.
+//!
+//! After every propagation the tiles that could still be walkable are treated as a graph:
+//! - tiles that have to be walkable, but can't reach each other anymore, are a contradiction
+//! - tiles that can't be connected to the tiles that have to be walkable lose their walkable
+//!   states
+//! - tiles that are the only remaining link between tiles that have to be walkable (articulation
+//!   points) lose their other states
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+};
+
+use super::{
+    interface::{PropagateQueueEntry, WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+    propagate_from_tile,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectivityConstraint<TPosition> {
+    /// States that can be walked through
+    pub walkable: BTreeSet<TileState>,
+    /// If set, both tiles have to be walkable and connected to each other
+    pub path: Option<(TPosition, TPosition)>,
+}
+
+impl<TPosition> ConnectivityConstraint<TPosition> {
+    pub fn new<I: IntoIterator<Item = TileState>>(walkable: I) -> Self {
+        Self {
+            walkable: walkable.into_iter().collect(),
+            path: None,
+        }
+    }
+
+    /// Also requires a walkable path between `from` and `to`
+    pub fn with_path(mut self, from: TPosition, to: TPosition) -> Self {
+        self.path = Some((from, to));
+        self
+    }
+}
+
+/// Marks the tiles that have to be walkable for the required tiles to stay connected.
+///
+/// `graph` is an adjacency list, only the tiles connected to `root` are searched. Uses an
+/// iterative version of Tarjan's articulation point search, where a point only counts if
+/// removing it separates required tiles from each other.
+fn essential_tiles(graph: &[Vec<usize>], required: &[bool], root: usize) -> Vec<bool> {
+    const UNVISITED: usize = usize::MAX;
+    let total_required = required.iter().filter(|r| **r).count();
+    let mut discovered = vec![UNVISITED; graph.len()];
+    let mut low = vec![UNVISITED; graph.len()];
+    let mut parent = vec![None; graph.len()];
+    // required tiles in the search tree below each tile, the tile itself included
+    let mut required_below = vec![0; graph.len()];
+    let mut essential = vec![false; graph.len()];
+
+    let mut time = 0;
+    discovered[root] = time;
+    low[root] = time;
+    required_below[root] = required[root] as usize;
+    let mut stack = vec![(root, 0)];
+    while let Some((tile, next_edge)) = stack.last_mut() {
+        let tile = *tile;
+        if let Some(&neighbour) = graph[tile].get(*next_edge) {
+            *next_edge += 1;
+            if discovered[neighbour] == UNVISITED {
+                time += 1;
+                discovered[neighbour] = time;
+                low[neighbour] = time;
+                parent[neighbour] = Some(tile);
+                required_below[neighbour] = required[neighbour] as usize;
+                stack.push((neighbour, 0));
+            } else {
+                low[tile] = low[tile].min(discovered[neighbour]);
+            }
+            continue;
+        }
+
+        stack.pop();
+        if let Some(parent) = parent[tile] {
+            low[parent] = low[parent].min(low[tile]);
+            let cut_off = required_below[tile];
+            // the subtree can only reach the rest of the graph through the parent
+            if low[tile] >= discovered[parent] && cut_off > 0 && cut_off < total_required {
+                essential[parent] = true;
+            }
+            required_below[parent] += cut_off;
+        }
+    }
+    essential
+}
+
+/// Checks the connectivity constraint of the grid, removing states where needed.
+///
+/// Returns the propagation caused by the changes, or None if nothing had to be changed.
+pub(crate) fn enforce<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState> + Clone,
+    G: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+>(
+    grid: &mut G,
+) -> Result<
+    Option<VecDeque<PropagateQueueEntry<TPosition>>>,
+    WaveFunctionCollapseInterruption<TPosition>,
+> {
+    let Some(constraint) = grid.get_connectivity() else {
+        return Ok(None);
+    };
+    let walkable = &constraint.walkable;
+    let endpoints: Vec<_> = constraint.path.iter().flat_map(|(a, b)| [*a, *b]).collect();
+
+    // tiles that could still be walkable become the nodes of the graph
+    let mut positions = Vec::new();
+    let mut index = HashMap::new();
+    let mut required = Vec::new();
+    let mut undecided = Vec::new();
+    for position in grid.positions() {
+        let Some(tile) = grid.get_tile(position) else {
+            continue;
+        };
+        let walkable_states = tile
+            .possible_states_ref()
+            .filter(|state| walkable.contains(state))
+            .count();
+        if walkable_states == 0 {
+            if endpoints.contains(&position) {
+                return Err(WaveFunctionCollapseInterruption::Contradiction(position));
+            }
+            continue;
+        }
+        let always_walkable = walkable_states == tile.possible_states_ref().count();
+        index.insert(position, positions.len());
+        positions.push(position);
+        required.push(always_walkable || endpoints.contains(&position));
+        undecided.push(!always_walkable);
+    }
+    let Some(root) = required.iter().position(|r| *r) else {
+        // nothing has to be connected yet
+        return Ok(None);
+    };
+
+    let graph: Vec<Vec<usize>> = positions
+        .iter()
+        .map(|position| {
+            grid.get_neighbours(*position)
+                .into_iter()
+                .filter_map(|(_, neighbour)| index.get(&neighbour?).copied())
+                .collect()
+        })
+        .collect();
+
+    let mut reachable = vec![false; positions.len()];
+    reachable[root] = true;
+    let mut queue = VecDeque::from([root]);
+    while let Some(tile) = queue.pop_front() {
+        for &neighbour in &graph[tile] {
+            if !reachable[neighbour] {
+                reachable[neighbour] = true;
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    if let Some(separated) = (0..positions.len()).find(|&i| required[i] && !reachable[i]) {
+        return Err(WaveFunctionCollapseInterruption::Contradiction(
+            positions[separated],
+        ));
+    }
+
+    // the search only sees the tiles reachable from the root
+    let essential = essential_tiles(&graph, &required, root);
+
+    let walkable = walkable.clone();
+    let mut changes = Vec::new();
+    for (i, position) in positions.iter().enumerate() {
+        if !reachable[i] {
+            // can't be connected to the tiles that have to be walkable
+            changes.push((*position, false));
+        } else if undecided[i] && (required[i] || essential[i]) {
+            changes.push((*position, true));
+        }
+    }
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    let mut queue = VecDeque::new();
+    for (position, keep_walkable) in changes {
+        grid.with_tile(position, |tile, _| {
+            tile.retain(|state| walkable.contains(state) == keep_walkable);
+        });
+        queue.extend(propagate_from_tile(grid, position));
+    }
+    Ok(Some(queue))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D, tests::any_combination_grid},
+        tile::Tile,
+        utils::space::s2d::Location2D,
+    };
+
+    use super::*;
+
+    const FLOOR: TileState = 0;
+    const WALL: TileState = 1;
+
+    fn row(grid: &DynamicSizeGrid2D<Tile>) -> Vec<Vec<TileState>> {
+        (0..grid.width)
+            .map(|x| {
+                grid.get_tile(Location2D { x, y: 0 })
+                    .unwrap()
+                    .possible_states()
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn path_is_carved() {
+        let mut grid = any_combination_grid(4, 1, [FLOOR, WALL]);
+        let constraint = ConnectivityConstraint::new([FLOOR])
+            .with_path(Location2D { x: 0, y: 0 }, Location2D { x: 3, y: 0 });
+        grid.set_connectivity(Some(constraint)).unwrap();
+        assert_eq!(row(&grid), vec![vec![FLOOR]; 4]);
+
+        // the path survives resets
+        grid.reset();
+        assert_eq!(row(&grid), vec![vec![FLOOR]; 4]);
+    }
+
+    #[test]
+    fn separated_regions_are_removed() {
+        let mut grid = any_combination_grid(5, 1, [FLOOR, WALL]);
+        grid.set_connectivity(Some(ConnectivityConstraint::new([FLOOR])))
+            .unwrap();
+        grid.collapse(Location2D { x: 3, y: 0 }, Some(FLOOR))
+            .unwrap();
+        grid.collapse(Location2D { x: 1, y: 0 }, Some(WALL))
+            .unwrap();
+        assert_eq!(
+            row(&grid),
+            vec![
+                vec![WALL],
+                vec![WALL],
+                vec![FLOOR, WALL],
+                vec![FLOOR],
+                vec![FLOOR, WALL]
+            ]
+        );
+    }
+
+    #[test]
+    fn last_link_is_kept() {
+        let mut grid = any_combination_grid(3, 3, [FLOOR, WALL]);
+        grid.set_connectivity(Some(ConnectivityConstraint::new([FLOOR])))
+            .unwrap();
+        grid.collapse(Location2D { x: 0, y: 0 }, Some(FLOOR))
+            .unwrap();
+        grid.collapse(Location2D { x: 2, y: 0 }, Some(FLOOR))
+            .unwrap();
+        grid.collapse(Location2D { x: 1, y: 1 }, Some(WALL))
+            .unwrap();
+        // the top middle tile is the only way around the wall
+        assert!(
+            grid.get_tile(Location2D { x: 1, y: 0 })
+                .unwrap()
+                .possible_states()
+                .eq([FLOOR, WALL])
+        );
+        grid.collapse(Location2D { x: 0, y: 1 }, Some(WALL))
+            .unwrap();
+        assert!(
+            grid.get_tile(Location2D { x: 1, y: 0 })
+                .unwrap()
+                .possible_states()
+                .eq([FLOOR])
+        );
+    }
+
+    #[test]
+    fn disconnection_is_a_contradiction() {
+        let mut grid = any_combination_grid(3, 1, [FLOOR, WALL]);
+        grid.set_connectivity(Some(ConnectivityConstraint::new([FLOOR])))
+            .unwrap();
+        grid.collapse(Location2D { x: 0, y: 0 }, Some(FLOOR))
+            .unwrap();
+        grid.collapse(Location2D { x: 2, y: 0 }, Some(FLOOR))
+            .unwrap();
+        // the middle tile is the only link between the two, the tile that got separated is
+        // reported
+        assert_eq!(
+            grid.collapse(Location2D { x: 1, y: 0 }, Some(WALL)),
+            Err(WaveFunctionCollapseInterruption::Contradiction(
+                Location2D { x: 2, y: 0 }
+            ))
+        );
+
+        let mut grid = any_combination_grid(3, 1, [FLOOR, WALL]);
+        grid.with_tile(Location2D { x: 1, y: 0 }, |t, _| {
+            t.set_possible_states([WALL])
+        });
+        let constraint = ConnectivityConstraint::new([FLOOR])
+            .with_path(Location2D { x: 0, y: 0 }, Location2D { x: 2, y: 0 });
+        assert!(grid.set_connectivity(Some(constraint)).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+//! Global constraints on how many tiles may end up in a state
+//!
+//! Adjacency rules only ever look at neighbouring tiles, so they can't express things like
+//! "exactly one exit" or "at least 20% water". A `CountConstraint` limits the amount of tiles
+//! collapsed into a state across the whole grid.
+//!
+//! Grids keep per-state counters of collapsed and still-possible tiles in `StateCounts`, which
+//! are checked after every propagation:
+//! - once the maximum is reached, the state is banned from every other tile
+//! - once the minimum can only be met by the remaining candidates, all of them are forced into
+//!   the state
+//! - if a limit can no longer be met, the propagation ends in a contradiction, which the
+//!   backtrackers handle like any other
+//!
+//! Limits that can't be met on the grid at all, like a minimum above the amount of tiles, are
+//! rejected with a `CountConstraintError` when the grid is created.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    grid::GridInterface,
+    rules::RuleSet,
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+};
+
+use super::{
+    interface::{PropagateQueueEntry, WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+    propagate_from_tile,
+};
+
+/// An amount of tiles, either absolute or relative to the size of the grid
+#[derive(Debug, Clone, Copy, PartialEq, Tsify, Serialize, Deserialize)]
+pub enum CountLimit {
+    Tiles(usize),
+    /// Between 0 and 1, a minimum is rounded up and a maximum down
+    Fraction(f64),
+}
+
+/// Limits the amount of tiles collapsed into `state`
+#[derive(Debug, Clone, Copy, PartialEq, Tsify, Serialize, Deserialize)]
+pub struct CountConstraint {
+    pub state: TileState,
+    pub min: Option<CountLimit>,
+    pub max: Option<CountLimit>,
+}
+
+impl CountConstraint {
+    /// Exactly `count` tiles in `state`
+    pub fn exactly(state: TileState, count: usize) -> Self {
+        Self {
+            state,
+            min: Some(CountLimit::Tiles(count)),
+            max: Some(CountLimit::Tiles(count)),
+        }
+    }
+
+    pub fn at_least(state: TileState, limit: CountLimit) -> Self {
+        Self {
+            state,
+            min: Some(limit),
+            max: None,
+        }
+    }
+
+    pub fn at_most(state: TileState, limit: CountLimit) -> Self {
+        Self {
+            state,
+            min: None,
+            max: Some(limit),
+        }
+    }
+
+    /// The minimum and maximum amount of tiles in a grid of `tile_count` tiles, or an error if
+    /// they can't be met
+    pub fn resolve(&self, tile_count: usize) -> Result<(usize, usize), CountConstraintError> {
+        let min = match self.min {
+            None => 0,
+            Some(CountLimit::Tiles(count)) => count,
+            Some(CountLimit::Fraction(fraction)) => (fraction * tile_count as f64).ceil() as usize,
+        };
+        let max = match self.max {
+            None => tile_count,
+            Some(CountLimit::Tiles(count)) => count,
+            Some(CountLimit::Fraction(fraction)) => (fraction * tile_count as f64).floor() as usize,
+        };
+        check_limits(self.state, min, max, tile_count)?;
+        Ok((min, max))
+    }
+}
+
+/// A count constraint that can't be met on a grid of the given size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error, Serialize, Deserialize)]
+pub enum CountConstraintError {
+    #[error("state {state} needs at least {min} tiles, but at most {max} are allowed")]
+    MinAboveMax {
+        state: TileState,
+        min: usize,
+        max: usize,
+    },
+    #[error("state {state} needs at least {min} tiles, but the grid only has {tile_count}")]
+    MinAboveTileCount {
+        state: TileState,
+        min: usize,
+        tile_count: usize,
+    },
+}
+
+fn check_limits(
+    state: TileState,
+    min: usize,
+    max: usize,
+    tile_count: usize,
+) -> Result<(), CountConstraintError> {
+    if min > tile_count {
+        return Err(CountConstraintError::MinAboveTileCount {
+            state,
+            min,
+            tile_count,
+        });
+    }
+    if min > max {
+        return Err(CountConstraintError::MinAboveMax { state, min, max });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Counter {
+    min: usize,
+    max: usize,
+    /// Tiles that can still be in the state, collapsed ones included
+    possible: usize,
+    /// Tiles that have collapsed into the state
+    collapsed: usize,
+}
+
+/// Per-state counters for the states mentioned by `RuleSet::count_constraints`
+///
+/// The counters have to be kept up to date by calling `tile_updated` every time the possible
+/// states of a tile change, grids do this automatically if their rules have count constraints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateCounts {
+    // a BTreeMap keeps the order in which constraints are enforced deterministic
+    counters: BTreeMap<TileState, Counter>,
+}
+
+impl StateCounts {
+    /// Creates counters that match the current possible states of all tiles in the grid, or None
+    /// if the rules of the grid don't have any count constraints. Returns an error if the
+    /// constraints can't be met on a grid of its size.
+    pub fn from_grid<
+        const N: usize,
+        TPosition: Location,
+        TDirection: Direction<N>,
+        T: TileInterface<TileState>,
+        G: GridInterface<N, TileState, TPosition, TDirection, T>,
+    >(
+        grid: &G,
+    ) -> Result<Option<Self>, CountConstraintError> {
+        let rules = grid.get_rules();
+        if rules.count_constraints.is_empty() {
+            return Ok(None);
+        }
+        let mut new = Self::new(rules, grid.positions().count())?;
+        for position in grid.positions() {
+            if let Some(tile) = grid.get_tile(position) {
+                new.add(tile);
+            }
+        }
+        Ok(Some(new))
+    }
+
+    /// Creates counters for a grid of `tile_count` tiles, with no tiles counted yet
+    fn new<const N: usize, TDirection: Direction<N>>(
+        rules: &RuleSet<N, TDirection>,
+        tile_count: usize,
+    ) -> Result<Self, CountConstraintError> {
+        let mut counters: BTreeMap<TileState, Counter> = BTreeMap::new();
+        for constraint in &rules.count_constraints {
+            let (min, max) = constraint.resolve(tile_count)?;
+            // multiple constraints on the same state are combined
+            let counter = counters.entry(constraint.state).or_insert(Counter {
+                min: 0,
+                max: tile_count,
+                ..Default::default()
+            });
+            counter.min = counter.min.max(min);
+            counter.max = counter.max.min(max);
+        }
+        for (&state, counter) in &counters {
+            check_limits(state, counter.min, counter.max, tile_count)?;
+        }
+        Ok(Self { counters })
+    }
+
+    fn add<T: TileInterface<TileState>>(&mut self, tile: &T) {
+        let collapsed = tile.has_collapsed();
+        for state in tile.possible_states_ref() {
+            if let Some(counter) = self.counters.get_mut(state) {
+                counter.possible += 1;
+                counter.collapsed += collapsed as usize;
+            }
+        }
+    }
+
+    fn remove<T: TileInterface<TileState>>(&mut self, tile: &T) {
+        let collapsed = tile.has_collapsed();
+        for state in tile.possible_states_ref() {
+            if let Some(counter) = self.counters.get_mut(state) {
+                counter.possible -= 1;
+                counter.collapsed -= collapsed as usize;
+            }
+        }
+    }
+
+    /// Updates the counters after a tile changed from `old` to `new`
+    pub fn tile_updated<T: TileInterface<TileState>>(&mut self, old: &T, new: &T) {
+        self.remove(old);
+        self.add(new);
+    }
+
+    /// The amount of tiles that can still be in `state`, or None if the state isn't constrained
+    pub fn possible(&self, state: TileState) -> Option<usize> {
+        self.counters.get(&state).map(|counter| counter.possible)
+    }
+
+    /// The amount of tiles collapsed into `state`, or None if the state isn't constrained
+    pub fn collapsed(&self, state: TileState) -> Option<usize> {
+        self.counters.get(&state).map(|counter| counter.collapsed)
+    }
+}
+
+/// What has to be done to keep a constraint satisfiable
+enum Enforcement {
+    Ban(TileState),
+    Force(TileState),
+    /// More tiles have collapsed into the state than allowed
+    TooMany(TileState),
+    /// Not enough tiles can be in the state anymore
+    TooFew(TileState),
+}
+
+/// Checks the count constraints of the grid, banning or forcing states where needed.
+///
+/// Returns the propagation caused by the changes, or None if nothing had to be changed. Only the
+/// first constraint that needs attention is handled, as the propagation may affect the others.
+pub(crate) fn enforce<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState> + Clone,
+    G: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+>(
+    grid: &mut G,
+) -> Result<
+    Option<VecDeque<PropagateQueueEntry<TPosition>>>,
+    WaveFunctionCollapseInterruption<TPosition>,
+> {
+    let Some(counts) = grid.get_state_counts() else {
+        return Ok(None);
+    };
+    let Some(enforcement) = counts.counters.iter().find_map(|(&state, counter)| {
+        if counter.collapsed > counter.max {
+            Some(Enforcement::TooMany(state))
+        } else if counter.possible < counter.min {
+            Some(Enforcement::TooFew(state))
+        } else if counter.collapsed == counter.max && counter.possible > counter.collapsed {
+            Some(Enforcement::Ban(state))
+        } else if counter.possible == counter.min && counter.collapsed < counter.min {
+            Some(Enforcement::Force(state))
+        } else {
+            None
+        }
+    }) else {
+        return Ok(None);
+    };
+
+    let (state, force) = match enforcement {
+        Enforcement::TooMany(state) | Enforcement::TooFew(state) => {
+            let too_many = matches!(enforcement, Enforcement::TooMany(_));
+            // point at a tile that contributes to the violation
+            let position = grid.positions().find(|&position| {
+                grid.get_tile(position).is_some_and(|tile| {
+                    let contains = tile.possible_states_ref().any(|s| *s == state);
+                    if too_many {
+                        tile.has_collapsed() && contains
+                    } else {
+                        !contains
+                    }
+                })
+            });
+            // the limits are checked when the counters are created, but any tile will do
+            let Some(position) = position.or_else(|| grid.positions().next()) else {
+                return Ok(None);
+            };
+            return Err(WaveFunctionCollapseInterruption::Contradiction(position));
+        }
+        Enforcement::Ban(state) => (state, false),
+        Enforcement::Force(state) => (state, true),
+    };
+
+    let candidates: Vec<_> = grid
+        .positions()
+        .filter(|&position| {
+            grid.get_tile(position).is_some_and(|tile| {
+                !tile.has_collapsed() && tile.possible_states_ref().any(|s| *s == state)
+            })
+        })
+        .collect();
+    let mut queue = VecDeque::new();
+    for position in candidates {
+        grid.with_tile(position, |tile, _| {
+            if force {
+                tile.set_possible_states([state]);
+            } else {
+                tile.retain(|s| *s != state);
+            }
+        });
+        queue.extend(propagate_from_tile(grid, position));
+    }
+    Ok(Some(queue))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::{dynamic_2d::DynamicSizeGrid2D, tests::any_combination_rules},
+        tile::Tile,
+        utils::space::s2d::Location2D,
+    };
+
+    use super::*;
+
+    /// Three states that are allowed next to each other in any combination
+    fn gen_grid(
+        width: usize,
+        height: usize,
+        constraints: Vec<CountConstraint>,
+    ) -> DynamicSizeGrid2D<Tile> {
+        let rules = any_combination_rules(0..3).with_count_constraints(constraints);
+        DynamicSizeGrid2D::new(width, height, rules, 0)
+    }
+
+    fn count(grid: &DynamicSizeGrid2D<Tile>, state: TileState) -> usize {
+        grid.image()
+            .values()
+            .filter(|tile| tile.possible_states().eq([state]))
+            .count()
+    }
+
+    #[test]
+    fn resolves_limits() {
+        let constraint = CountConstraint {
+            state: 0,
+            min: Some(CountLimit::Fraction(0.25)),
+            max: Some(CountLimit::Fraction(0.25)),
+        };
+        assert_eq!(constraint.resolve(8), Ok((2, 2)));
+        // a quarter of 10 tiles can't be met exactly
+        assert_eq!(
+            constraint.resolve(10),
+            Err(CountConstraintError::MinAboveMax {
+                state: 0,
+                min: 3,
+                max: 2
+            })
+        );
+        assert_eq!(
+            CountConstraint::at_least(1, CountLimit::Tiles(3)).resolve(8),
+            Ok((3, 8))
+        );
+        assert_eq!(
+            CountConstraint::at_most(1, CountLimit::Tiles(3)).resolve(8),
+            Ok((0, 3))
+        );
+        assert_eq!(
+            CountConstraint::at_least(1, CountLimit::Tiles(9)).resolve(8),
+            Err(CountConstraintError::MinAboveTileCount {
+                state: 1,
+                min: 9,
+                tile_count: 8
+            })
+        );
+    }
+
+    #[test]
+    fn grids_reject_unmeetable_limits() {
+        let create = |constraints: Vec<CountConstraint>| {
+            let rules = any_combination_rules(0..3).with_count_constraints(constraints);
+            DynamicSizeGrid2D::<Tile>::try_new_periodic(
+                2,
+                2,
+                rules,
+                0,
+                Default::default(),
+                Default::default(),
+            )
+            .err()
+        };
+        assert_eq!(
+            create(vec![CountConstraint::at_least(0, CountLimit::Tiles(100))]),
+            Some(WaveFunctionCollapseInterruption::InvalidCountConstraint(
+                CountConstraintError::MinAboveTileCount {
+                    state: 0,
+                    min: 100,
+                    tile_count: 4
+                }
+            ))
+        );
+        // each constraint can be met on its own, but not together
+        assert_eq!(
+            create(vec![
+                CountConstraint::at_least(0, CountLimit::Tiles(3)),
+                CountConstraint::at_most(0, CountLimit::Tiles(2)),
+            ]),
+            Some(WaveFunctionCollapseInterruption::InvalidCountConstraint(
+                CountConstraintError::MinAboveMax {
+                    state: 0,
+                    min: 3,
+                    max: 2
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn counts_follow_tiles() {
+        let mut grid = gen_grid(
+            2,
+            2,
+            vec![CountConstraint::at_most(0, CountLimit::Tiles(4))],
+        );
+        let counts = grid.get_state_counts().unwrap();
+        assert_eq!(
+            (counts.possible(0), counts.collapsed(0)),
+            (Some(4), Some(0))
+        );
+        assert_eq!(counts.possible(1), None);
+
+        grid.with_tile(Location2D { x: 0, y: 0 }, |t, _| t.set_possible_states([0]));
+        grid.with_tile(Location2D { x: 1, y: 0 }, |t, _| {
+            t.set_possible_states([1, 2])
+        });
+        let counts = grid.get_state_counts().unwrap();
+        assert_eq!(
+            (counts.possible(0), counts.collapsed(0)),
+            (Some(3), Some(1))
+        );
+
+        grid.reset();
+        let counts = grid.get_state_counts().unwrap();
+        assert_eq!(
+            (counts.possible(0), counts.collapsed(0)),
+            (Some(4), Some(0))
+        );
+    }
+
+    #[test]
+    fn maximum_bans_the_state() {
+        let mut grid = gen_grid(3, 3, vec![CountConstraint::exactly(0, 1)]);
+        grid.collapse(Location2D { x: 1, y: 1 }, Some(0)).unwrap();
+        for (position, tile) in grid.image() {
+            if position != (Location2D { x: 1, y: 1 }) {
+                assert!(tile.possible_states().eq([1, 2]), "{position:?}");
+            }
+        }
+        assert_eq!(
+            grid.get_state_counts().unwrap().possible(0),
+            Some(1),
+            "the state should only remain where it was collapsed"
+        );
+    }
+
+    #[test]
+    fn minimum_forces_the_candidates() {
+        let mut grid = gen_grid(
+            2,
+            2,
+            vec![CountConstraint::at_least(0, CountLimit::Tiles(3))],
+        );
+        grid.collapse(Location2D { x: 0, y: 0 }, Some(1)).unwrap();
+        assert_eq!(count(&grid, 0), 3);
+        assert_eq!(count(&grid, 1), 1);
+    }
+
+    #[test]
+    fn violations_are_contradictions() {
+        let mut grid = gen_grid(
+            2,
+            2,
+            vec![CountConstraint::at_least(0, CountLimit::Tiles(3))],
+        );
+        // the constraint is checked before the next change is propagated
+        grid.with_tile(Location2D { x: 0, y: 0 }, |t, _| t.set_possible_states([1]));
+        grid.with_tile(Location2D { x: 1, y: 0 }, |t, _| t.set_possible_states([1]));
+        assert!(matches!(
+            grid.collapse(Location2D { x: 1, y: 1 }, Some(2)),
+            Err(WaveFunctionCollapseInterruption::Contradiction(_))
+        ));
+    }
+}
@@ -1,11 +1,34 @@
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::{
-    backtracking::{gradual_reset::BacktrackerByGradualReset, reset::BacktrackerByReset},
-    grid::{GridInterface, constant_2d::ConstantSizeGrid2D, tests::assert_tile_state},
-    tile::interface::TileInterface,
-    utils::space::s2d::Location2D,
-    wave_function_collapse::interface::{WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+    backtracking::{
+        backjumping::BacktrackerByBackjumping, gradual_reset::BacktrackerByGradualReset,
+        reset::BacktrackerByReset, undo::BacktrackingByUndo,
+    },
+    grid::{
+        GridInterface,
+        constant_2d::ConstantSizeGrid2D,
+        dynamic_1d::DynamicSizeGrid1D,
+        dynamic_2d::DynamicSizeGrid2D,
+        dynamic_3d::DynamicSizeGrid3D,
+        dynamic_hex::DynamicSizeHexGrid,
+        graph::{GraphDescription, GraphGrid},
+        tests::assert_tile_state,
+    },
+    rules::{RuleSet1D, RuleSet2D, RuleSet3D},
+    tile::{Tile, bitset::BitsetTile, interface::TileInterface},
+    utils::space::{
+        graph::GraphNode,
+        s1d::Vector1D,
+        s1d::{Direction1D, Location1D},
+        s2d::{Direction2D, Location2D, Vector2D},
+        s3d::{Location3D, Vector3D},
+    },
+    value_selection::weighted::WeightedRandom,
+    wave_function_collapse::{
+        interface::{WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+        propagation::PropagationStrategy,
+    },
 };
 
 fn debug_print<const W: usize, const H: usize>(grid: &ConstantSizeGrid2D<W, H>) {
@@ -306,3 +329,1130 @@ fn flowers_blatant_rule_violations_gradual_reset() {
         }
     });
 }
+
+/// Runs the same grid with both propagation strategies, the results should be identical
+fn assert_propagation_strategies_match(rules: RuleSet2D, w: usize, h: usize, seeds: u64) {
+    (0..seeds).into_par_iter().for_each(|seed| {
+        let mut pairwise = DynamicSizeGrid2D::<Tile>::new_with_propagation(
+            w,
+            h,
+            rules.clone(),
+            seed,
+            PropagationStrategy::Pairwise,
+        );
+        let mut support_count = DynamicSizeGrid2D::<Tile>::new_with_propagation(
+            w,
+            h,
+            rules.clone(),
+            seed,
+            PropagationStrategy::SupportCount,
+        );
+        let pairwise_result = pairwise.run(w * h * 10, Some(BacktrackerByReset {}));
+        let support_count_result = support_count.run(w * h * 10, Some(BacktrackerByReset {}));
+
+        assert_eq!(pairwise_result, support_count_result, "seed {seed}");
+        assert_eq!(pairwise.image(), support_count.image(), "seed {seed}");
+        assert_eq!(pairwise.update_log, support_count.update_log, "seed {seed}");
+    });
+}
+
+#[test]
+fn propagation_strategies_match_terrain() {
+    assert_propagation_strategies_match(crate::rules::samples::terrain::rules(), 12, 12, 8);
+}
+
+#[test]
+fn propagation_strategies_match_flowers() {
+    assert_propagation_strategies_match(
+        crate::rules::samples::flowers_singlepixel::rules(),
+        10,
+        10,
+        8,
+    );
+}
+
+#[test]
+fn propagation_strategies_match_bubblewrap() {
+    assert_propagation_strategies_match(crate::rules::samples::bubble_wrap::rules(5), 8, 8, 4);
+}
+
+#[test]
+fn propagation_strategies_match_village() {
+    let rules: RuleSet2D = serde_json::from_str(include_str!("../../samples/rules/village.json"))
+        .expect("failed to parse prebuilt rules.json");
+    assert_propagation_strategies_match(rules, 8, 8, 2);
+}
+
+#[test]
+fn propagation_strategies_match_constant_size() {
+    const W: usize = 9;
+    const H: usize = 9;
+
+    let rules = crate::rules::samples::flowers_singlepixel::rules();
+    for seed in 0..4 {
+        let mut pairwise = ConstantSizeGrid2D::<W, H>::new_with_propagation(
+            rules.clone(),
+            seed,
+            PropagationStrategy::Pairwise,
+        );
+        let mut support_count = ConstantSizeGrid2D::<W, H>::new_with_propagation(
+            rules.clone(),
+            seed,
+            PropagationStrategy::SupportCount,
+        );
+        let pairwise_result = pairwise.run(500, Some(BacktrackerByReset {}));
+        let support_count_result = support_count.run(500, Some(BacktrackerByReset {}));
+        debug_print(&support_count);
+
+        assert_eq!(pairwise_result, support_count_result, "seed {seed}");
+        assert_eq!(pairwise.image(), support_count.image(), "seed {seed}");
+    }
+}
+
+/// Runs the same grid with both tile implementations, the results should be identical
+fn assert_tile_implementations_match(
+    rules: RuleSet2D,
+    w: usize,
+    h: usize,
+    seeds: u64,
+    propagation: PropagationStrategy,
+) {
+    (0..seeds).into_par_iter().for_each(|seed| {
+        let mut btreeset =
+            DynamicSizeGrid2D::<Tile>::new_with_propagation(w, h, rules.clone(), seed, propagation);
+        let mut bitset = DynamicSizeGrid2D::<BitsetTile>::new_with_propagation(
+            w,
+            h,
+            rules.clone(),
+            seed,
+            propagation,
+        );
+        let btreeset_result = btreeset.run(w * h * 10, Some(BacktrackerByReset {}));
+        let bitset_result = bitset.run(w * h * 10, Some(BacktrackerByReset {}));
+
+        assert_eq!(btreeset_result, bitset_result, "seed {seed}");
+        for location in btreeset.positions() {
+            let expected: Vec<_> = btreeset
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .collect();
+            let actual: Vec<_> = bitset
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .collect();
+            assert_eq!(expected, actual, "seed {seed}, {location:?}");
+        }
+        assert_eq!(
+            btreeset.update_log.len(),
+            bitset.update_log.len(),
+            "seed {seed}"
+        );
+    });
+}
+
+#[test]
+fn tile_implementations_match_terrain() {
+    assert_tile_implementations_match(
+        crate::rules::samples::terrain::rules(),
+        12,
+        12,
+        8,
+        PropagationStrategy::Pairwise,
+    );
+}
+
+#[test]
+fn tile_implementations_match_flowers() {
+    assert_tile_implementations_match(
+        crate::rules::samples::flowers_singlepixel::rules(),
+        10,
+        10,
+        8,
+        PropagationStrategy::SupportCount,
+    );
+}
+
+#[test]
+fn tile_implementations_match_village() {
+    let rules: RuleSet2D = serde_json::from_str(include_str!("../../samples/rules/village.json"))
+        .expect("failed to parse prebuilt rules.json");
+    assert_tile_implementations_match(rules, 8, 8, 2, PropagationStrategy::SupportCount);
+}
+
+#[test]
+fn checkers_bitset() {
+    use crate::rules::samples::checkers::{STATE_BLACK, STATE_WHITE};
+    let rules = crate::rules::samples::checkers::rules();
+    let mut grid = ConstantSizeGrid2D::<6, 6, BitsetTile>::new_with_propagation(
+        rules,
+        0,
+        PropagationStrategy::default(),
+    );
+    grid.collapse(Location2D { x: 0, y: 0 }, Some(STATE_BLACK))
+        .expect("collapsing the first tile shouldn't fail");
+    for location in grid.positions() {
+        let expected = if (location.x + location.y) % 2 == 0 {
+            STATE_BLACK
+        } else {
+            STATE_WHITE
+        };
+        assert_tile_state(&grid.get_tile(location).unwrap(), expected);
+    }
+}
+
+/// Checks that every tile has collapsed and that all neighbouring tiles, including the ones
+/// across a periodic edge, are allowed by the rules
+fn assert_valid_2d<T: TileInterface<u64> + Clone + PartialEq>(
+    grid: &DynamicSizeGrid2D<T>,
+    rules: &RuleSet2D,
+) {
+    for location in grid.positions() {
+        let tile = grid.get_tile(location).unwrap();
+        assert!(tile.has_collapsed(), "{location:?} didn't collapse");
+        let state = tile.possible_states().next().unwrap();
+        for (direction, neighbour) in grid.get_neighbour_tiles(location) {
+            if let Some(neighbour) = neighbour {
+                let neighbour_state = neighbour.possible_states().next().unwrap();
+                assert!(
+                    rules.allowed.contains(&(state, direction, neighbour_state)),
+                    "{state} at {location:?} doesn't allow {neighbour_state} in {direction:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn periodic_terrain_is_seamless() {
+    let rules = crate::rules::samples::terrain::rules();
+    (0..8).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid2D::<Tile>::new_periodic(
+            12,
+            10,
+            rules.clone(),
+            seed,
+            PropagationStrategy::Pairwise,
+            Vector2D { x: true, y: true },
+        );
+        assert_eq!(
+            grid.run(12 * 10 * 10, Some(BacktrackerByReset {})),
+            Err(WaveFunctionCollapseInterruption::Finished),
+            "seed {seed}"
+        );
+        assert_valid_2d(&grid, &rules);
+    });
+}
+
+#[test]
+fn periodic_propagation_strategies_match() {
+    let rules = crate::rules::samples::flowers_singlepixel::rules();
+    (0..4).into_par_iter().for_each(|seed| {
+        let periodic = Vector2D { x: true, y: false };
+        let mut pairwise = DynamicSizeGrid2D::<Tile>::new_periodic(
+            10,
+            10,
+            rules.clone(),
+            seed,
+            PropagationStrategy::Pairwise,
+            periodic,
+        );
+        let mut support_count = DynamicSizeGrid2D::<BitsetTile>::new_periodic(
+            10,
+            10,
+            rules.clone(),
+            seed,
+            PropagationStrategy::SupportCount,
+            periodic,
+        );
+        let pairwise_result = pairwise.run(1000, Some(BacktrackerByReset {}));
+        let support_count_result = support_count.run(1000, Some(BacktrackerByReset {}));
+
+        assert_eq!(pairwise_result, support_count_result, "seed {seed}");
+        for location in pairwise.positions() {
+            let expected: Vec<_> = pairwise
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .collect();
+            let actual: Vec<_> = support_count
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .collect();
+            assert_eq!(expected, actual, "seed {seed}, {location:?}");
+        }
+    });
+}
+
+#[test]
+fn periodic_checkers_need_even_sides() {
+    use crate::rules::samples::checkers::STATE_BLACK;
+    let rules = crate::rules::samples::checkers::rules();
+
+    let mut even = DynamicSizeGrid2D::<Tile>::new_periodic(
+        4,
+        2,
+        rules.clone(),
+        0,
+        PropagationStrategy::Pairwise,
+        Vector2D { x: true, y: true },
+    );
+    even.collapse(Location2D { x: 0, y: 0 }, Some(STATE_BLACK))
+        .expect("an even periodic grid can be filled with checkers");
+    assert_valid_2d(&even, &rules);
+
+    // the wrapped neighbours of an odd row can't alternate
+    for propagation in [
+        PropagationStrategy::Pairwise,
+        PropagationStrategy::SupportCount,
+    ] {
+        let mut odd = DynamicSizeGrid2D::<Tile>::new_periodic(
+            3,
+            2,
+            rules.clone(),
+            0,
+            propagation,
+            Vector2D { x: true, y: false },
+        );
+        assert!(matches!(
+            odd.collapse(Location2D { x: 0, y: 0 }, Some(STATE_BLACK)),
+            Err(WaveFunctionCollapseInterruption::Contradiction(_))
+        ));
+    }
+}
+
+#[test]
+fn one_dimensional_alternating() {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    const STATE_A: u64 = 0;
+    const STATE_B: u64 = 1;
+    let rules = RuleSet1D::new(
+        BTreeSet::from([STATE_A, STATE_B]),
+        HashSet::from([
+            (STATE_A, Direction1D::RIGHT, STATE_B),
+            (STATE_B, Direction1D::RIGHT, STATE_A),
+        ]),
+        HashMap::new(),
+        HashMap::new(),
+        BTreeMap::new(),
+    );
+    let mut grid = DynamicSizeGrid1D::new(9, rules, 0);
+    grid.collapse(Location1D { x: 4 }, Some(STATE_A))
+        .expect("collapsing the middle tile shouldn't fail");
+    for x in 0..9 {
+        let expected = if x % 2 == 0 { STATE_A } else { STATE_B };
+        assert_tile_state(&grid.get_tile(Location1D { x }).unwrap(), expected);
+    }
+}
+
+#[test]
+fn one_dimensional_periodic() {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    const STATE_A: u64 = 0;
+    const STATE_B: u64 = 1;
+    let rules = RuleSet1D::new(
+        BTreeSet::from([STATE_A, STATE_B]),
+        HashSet::from([
+            (STATE_A, Direction1D::RIGHT, STATE_B),
+            (STATE_B, Direction1D::RIGHT, STATE_A),
+        ]),
+        HashMap::new(),
+        HashMap::new(),
+        BTreeMap::new(),
+    );
+    let periodic = Vector1D { x: true };
+
+    // both neighbours of the first tile are the same tile
+    let mut grid = DynamicSizeGrid1D::new_periodic(2, rules.clone(), 0, periodic);
+    assert_eq!(
+        grid.get_neighbours(Location1D { x: 0 }),
+        [
+            (Direction1D::RIGHT, Some(Location1D { x: 1 })),
+            (Direction1D::LEFT, Some(Location1D { x: 1 })),
+        ]
+    );
+    grid.collapse(Location1D { x: 0 }, Some(STATE_A))
+        .expect("two tiles can alternate around the loop");
+    assert_tile_state(&grid.get_tile(Location1D { x: 1 }).unwrap(), STATE_B);
+
+    // a single tile is its own neighbour, and no state allows itself
+    let mut grid = DynamicSizeGrid1D::new_periodic(1, rules.clone(), 0, periodic);
+    assert_eq!(
+        grid.collapse(Location1D { x: 0 }, Some(STATE_A)),
+        Err(WaveFunctionCollapseInterruption::Contradiction(
+            Location1D { x: 0 }
+        ))
+    );
+
+    let mut grid = DynamicSizeGrid1D::new_periodic(5, rules, 0, periodic);
+    assert!(matches!(
+        grid.collapse(Location1D { x: 2 }, Some(STATE_A)),
+        Err(WaveFunctionCollapseInterruption::Contradiction(_))
+    ));
+}
+
+#[test]
+fn one_dimensional_run() {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    const STATE_LOW: u64 = 0;
+    const STATE_MID: u64 = 1;
+    const STATE_HIGH: u64 = 2;
+    // a "staircase" that can only go up or down by one step at a time
+    let rules = RuleSet1D::new(
+        BTreeSet::from([STATE_LOW, STATE_MID, STATE_HIGH]),
+        HashSet::from([
+            (STATE_LOW, Direction1D::RIGHT, STATE_LOW),
+            (STATE_LOW, Direction1D::RIGHT, STATE_MID),
+            (STATE_MID, Direction1D::RIGHT, STATE_MID),
+            (STATE_MID, Direction1D::RIGHT, STATE_HIGH),
+            (STATE_HIGH, Direction1D::RIGHT, STATE_HIGH),
+        ]),
+        HashMap::new(),
+        HashMap::new(),
+        BTreeMap::new(),
+    );
+    (0..16).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid1D::new(20, rules.clone(), seed);
+        assert_eq!(
+            grid.run(100, None::<BacktrackerByReset>),
+            Err(WaveFunctionCollapseInterruption::Finished),
+            "seed {seed}"
+        );
+        let states: Vec<_> = (0..20)
+            .map(|x| {
+                let tile = grid.get_tile(Location1D { x }).unwrap();
+                assert!(tile.has_collapsed());
+                tile.possible_states().next().unwrap()
+            })
+            .collect();
+        for pair in states.windows(2) {
+            assert!(
+                rules
+                    .allowed
+                    .contains(&(pair[0], Direction1D::RIGHT, pair[1])),
+                "seed {seed}: {states:?}"
+            );
+        }
+    });
+}
+
+/// Checks that every voxel has collapsed and that all neighbouring voxels are allowed by the rules
+fn assert_valid_3d<T: TileInterface<u64> + Clone + PartialEq>(
+    grid: &DynamicSizeGrid3D<T>,
+    rules: &RuleSet3D,
+) {
+    for location in grid.positions() {
+        let tile = grid.get_tile(location).unwrap();
+        assert!(tile.has_collapsed(), "{location:?} didn't collapse");
+        let state = tile.possible_states().next().unwrap();
+        for (direction, neighbour) in grid.get_neighbour_tiles(location) {
+            if let Some(neighbour) = neighbour {
+                let neighbour_state = neighbour.possible_states().next().unwrap();
+                assert!(
+                    rules.allowed.contains(&(state, direction, neighbour_state)),
+                    "{state} at {location:?} doesn't allow {neighbour_state} in {direction:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn checkers_3d() {
+    use crate::rules::samples::checkers_3d::{STATE_BLACK, STATE_WHITE};
+    let rules = crate::rules::samples::checkers_3d::rules();
+    let mut grid = DynamicSizeGrid3D::new(4, 5, 6, rules.clone(), 0);
+    grid.collapse(Location3D { x: 0, y: 0, z: 0 }, Some(STATE_BLACK))
+        .expect("collapsing the first voxel shouldn't fail");
+
+    for location in grid.positions() {
+        let expected = if (location.x + location.y + location.z) % 2 == 0 {
+            STATE_BLACK
+        } else {
+            STATE_WHITE
+        };
+        assert_tile_state(&grid.get_tile(location).unwrap(), expected);
+    }
+    assert_valid_3d(&grid, &rules);
+}
+
+#[test]
+fn voxel_terrain() {
+    use crate::rules::samples::voxel_terrain::{STATE_AIR, STATE_STONE};
+    let rules = crate::rules::samples::voxel_terrain::rules();
+    const W: usize = 6;
+    const H: usize = 7;
+    const D: usize = 5;
+
+    (0..8).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid3D::new(W, H, D, rules.clone(), seed);
+        assert_eq!(
+            grid.run(W * H * D * 10, Some(BacktrackerByReset {})),
+            Err(WaveFunctionCollapseInterruption::Finished),
+            "seed {seed}"
+        );
+        assert_valid_3d(&grid, &rules);
+        for x in 0..W {
+            for z in 0..D {
+                let top = grid.get_tile(Location3D { x, y: 0, z }).unwrap();
+                assert_tile_state(&top, STATE_AIR);
+                let bottom = grid.get_tile(Location3D { x, y: H - 1, z }).unwrap();
+                assert_tile_state(&bottom, STATE_STONE);
+            }
+        }
+    });
+}
+
+#[test]
+fn propagation_strategies_and_tiles_match_3d() {
+    let rules = crate::rules::samples::voxel_terrain::rules();
+    (0..4).into_par_iter().for_each(|seed| {
+        let mut pairwise = DynamicSizeGrid3D::new(5, 5, 5, rules.clone(), seed);
+        let mut support_count = DynamicSizeGrid3D::<BitsetTile>::new_with_propagation(
+            5,
+            5,
+            5,
+            rules.clone(),
+            seed,
+            PropagationStrategy::SupportCount,
+        );
+        let pairwise_result = pairwise.run(1000, Some(BacktrackerByReset {}));
+        let support_count_result = support_count.run(1000, Some(BacktrackerByReset {}));
+
+        assert_eq!(pairwise_result, support_count_result, "seed {seed}");
+        for location in pairwise.positions() {
+            let expected: Vec<_> = pairwise
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .collect();
+            let actual: Vec<_> = support_count
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .collect();
+            assert_eq!(expected, actual, "seed {seed}, {location:?}");
+        }
+    });
+}
+
+#[test]
+fn voxel_terrain_periodic() {
+    let rules = crate::rules::samples::voxel_terrain::rules();
+    // wrapping horizontally keeps the ground and the sky at the top and bottom faces
+    let periodic = Vector3D {
+        x: true,
+        y: false,
+        z: true,
+    };
+    (0..4).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid3D::<Tile>::new_periodic(
+            6,
+            6,
+            6,
+            rules.clone(),
+            seed,
+            PropagationStrategy::Pairwise,
+            periodic,
+        );
+        assert_eq!(
+            grid.run(6 * 6 * 6 * 10, Some(BacktrackerByReset {})),
+            Err(WaveFunctionCollapseInterruption::Finished),
+            "seed {seed}"
+        );
+        assert_valid_3d(&grid, &rules);
+    });
+}
+
+#[test]
+fn hex_terrain() {
+    let rules = crate::rules::samples::hex_terrain::rules();
+    (0..8).into_par_iter().for_each(|seed| {
+        let mut pairwise = DynamicSizeHexGrid::new(9, 7, rules.clone(), seed);
+        let mut support_count = DynamicSizeHexGrid::<BitsetTile>::new_with_propagation(
+            9,
+            7,
+            rules.clone(),
+            seed,
+            PropagationStrategy::SupportCount,
+        );
+        assert_eq!(
+            pairwise.run(9 * 7 * 10, Some(BacktrackerByReset {})),
+            Err(WaveFunctionCollapseInterruption::Finished),
+            "seed {seed}"
+        );
+        assert_eq!(
+            support_count.run(9 * 7 * 10, Some(BacktrackerByReset {})),
+            Err(WaveFunctionCollapseInterruption::Finished),
+            "seed {seed}"
+        );
+
+        for location in pairwise.positions() {
+            let tile = pairwise.get_tile(location).unwrap();
+            assert!(tile.has_collapsed(), "{location:?} didn't collapse");
+            let state = tile.possible_states().next().unwrap();
+            for (direction, neighbour) in pairwise.get_neighbour_tiles(location) {
+                if let Some(neighbour) = neighbour {
+                    let neighbour_state = neighbour.possible_states().next().unwrap();
+                    assert!(
+                        rules.allowed.contains(&(state, direction, neighbour_state)),
+                        "{state} at {location:?} doesn't allow {neighbour_state} in {direction:?}"
+                    );
+                }
+            }
+
+            let other = support_count.get_tile(location).unwrap();
+            assert_eq!(
+                tile.possible_states().collect::<Vec<_>>(),
+                other.possible_states().collect::<Vec<_>>(),
+                "seed {seed}, {location:?}"
+            );
+        }
+    });
+}
+
+/// A graph shaped like a `w` x `h` lattice, numbered the same way as `DynamicSizeGrid2D`
+fn lattice_graph(w: usize, h: usize) -> GraphDescription<Direction2D> {
+    let mut edges = Vec::new();
+    for y in 0..h {
+        for x in 0..w {
+            let node = y * w + x;
+            if x + 1 < w {
+                edges.push((node, Direction2D::RIGHT, node + 1));
+            }
+            if y + 1 < h {
+                edges.push((node, Direction2D::DOWN, node + w));
+            }
+        }
+    }
+    GraphDescription {
+        directions: vec![
+            Direction2D::UP,
+            Direction2D::RIGHT,
+            Direction2D::DOWN,
+            Direction2D::LEFT,
+        ],
+        node_count: w * h,
+        edges,
+    }
+}
+
+#[test]
+fn graph_lattice() {
+    const W: usize = 8;
+    const H: usize = 7;
+    let rules = crate::rules::samples::terrain::rules();
+    let node = |location: Location2D| GraphNode(location.y * W + location.x);
+    (0..4).into_par_iter().for_each(|seed| {
+        let lattice = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+        let mut graph = GraphGrid::new(lattice_graph(W, H), rules.clone(), seed).unwrap();
+
+        // same neighbours, so the edges get initialized the same way
+        for location in lattice.positions() {
+            let expected = lattice
+                .get_neighbours(location)
+                .map(|(d, n)| (d, n.map(node)));
+            assert_eq!(graph.get_neighbours(node(location)), expected);
+            let expected: Vec<_> = lattice
+                .get_tile(location)
+                .unwrap()
+                .possible_states()
+                .collect();
+            let actual: Vec<_> = graph
+                .get_tile(node(location))
+                .unwrap()
+                .possible_states()
+                .collect();
+            assert_eq!(actual, expected);
+        }
+
+        assert_eq!(
+            graph.run(W * H * 10, Some(BacktrackerByReset {})),
+            Err(WaveFunctionCollapseInterruption::Finished),
+            "seed {seed}"
+        );
+        for location in graph.positions() {
+            let state = graph.get_tile(location).unwrap().possible_states().next();
+            for (direction, neighbour) in graph.get_neighbour_tiles(location) {
+                if let Some(neighbour) = neighbour {
+                    let neighbour_state = neighbour.possible_states().next().unwrap();
+                    assert!(
+                        rules
+                            .allowed
+                            .contains(&(state.unwrap(), direction, neighbour_state)),
+                        "seed {seed}, {location:?}"
+                    );
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn graph_ring() {
+    // an odd ring can't alternate between two colors, an even one can
+    let rules = crate::rules::samples::checkers::rules();
+    let ring = |length: usize| {
+        let mut description = lattice_graph(length, 1);
+        description.edges.push((length - 1, Direction2D::RIGHT, 0));
+        description
+    };
+
+    let mut even = GraphGrid::<4, _, BitsetTile>::new_with_propagation(
+        ring(6),
+        rules.clone(),
+        0,
+        PropagationStrategy::SupportCount,
+    )
+    .unwrap();
+    assert_eq!(
+        even.run(100, None::<BacktrackerByReset>),
+        Err(WaveFunctionCollapseInterruption::Finished)
+    );
+    for node in 0..6 {
+        for (direction, neighbour) in even.get_neighbour_tiles(GraphNode(node)) {
+            if let Some(neighbour) = neighbour {
+                let state = even
+                    .get_tile(GraphNode(node))
+                    .unwrap()
+                    .possible_states()
+                    .next();
+                let neighbour_state = neighbour.possible_states().next();
+                assert!(rules.allowed.contains(&(
+                    state.unwrap(),
+                    direction,
+                    neighbour_state.unwrap()
+                )));
+            }
+        }
+    }
+
+    let mut odd = GraphGrid::new(ring(5), rules, 0).unwrap();
+    assert!(matches!(
+        odd.collapse(GraphNode(0), None),
+        Err(WaveFunctionCollapseInterruption::Contradiction(_))
+    ));
+}
+
+#[test]
+fn flowers_undo() {
+    const W: usize = 20;
+    const H: usize = 20;
+    let rules = crate::rules::samples::flowers_singlepixel::rules();
+
+    (0..20).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+        let result = grid.run(W * H * 100, Some(BacktrackingByUndo::new()));
+        match result {
+            Err(WaveFunctionCollapseInterruption::Finished) => {}
+            Err(_) => result.unwrap(),
+            Ok(_) => panic!("Grid should've finished"),
+        };
+        assert_valid_2d(&grid, &rules);
+    });
+}
+
+#[test]
+fn undo_gives_up_on_impossible_grids() {
+    let rules = crate::rules::samples::checkers::rules();
+    let mut grid = DynamicSizeGrid2D::<Tile>::new_periodic(
+        5,
+        4,
+        rules,
+        0,
+        PropagationStrategy::default(),
+        Vector2D { x: true, y: false },
+    );
+    assert!(matches!(
+        grid.run(1000, Some(BacktrackingByUndo::new())),
+        Err(WaveFunctionCollapseInterruption::Contradiction(_))
+    ));
+}
+
+#[test]
+fn flowers_backjumping() {
+    const W: usize = 20;
+    const H: usize = 20;
+    let rules = crate::rules::samples::flowers_singlepixel::rules();
+
+    (0..20).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+        let result = grid.run(W * H * 100, Some(BacktrackerByBackjumping::new()));
+        match result {
+            Err(WaveFunctionCollapseInterruption::Finished) => {}
+            Err(_) => result.unwrap(),
+            Ok(_) => panic!("Grid should've finished"),
+        };
+        assert_valid_2d(&grid, &rules);
+    });
+}
+
+#[test]
+fn backjumping_agrees_with_undo() {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    // both search exhaustively, so jumping over decisions must never skip over a solution
+    let mut solvable = 0;
+    for seed in 0..200 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut allowed = HashSet::new();
+        for a in 0..4 {
+            for b in 0..4 {
+                for direction in [Direction2D::RIGHT, Direction2D::DOWN] {
+                    if rng.random_bool(0.4) {
+                        allowed.insert((a, direction, b));
+                    }
+                }
+            }
+        }
+        let rules = RuleSet2D::new(
+            BTreeSet::from_iter(0..4),
+            allowed,
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+
+        let mut undo = DynamicSizeGrid2D::<Tile>::new(3, 3, rules.clone(), seed);
+        let mut backjumping = DynamicSizeGrid2D::<Tile>::new(3, 3, rules.clone(), seed);
+        let undo_result = undo.run(10_000, Some(BacktrackingByUndo::new()));
+        let backjumping_result = backjumping.run(10_000, Some(BacktrackerByBackjumping::new()));
+        let finished = Err(WaveFunctionCollapseInterruption::Finished);
+        assert_eq!(
+            undo_result == finished,
+            backjumping_result == finished,
+            "seed {seed}: {undo_result:?} vs {backjumping_result:?}"
+        );
+        if backjumping_result == finished {
+            solvable += 1;
+            assert_valid_2d(&backjumping, &rules);
+        }
+    }
+    assert!(solvable > 0 && solvable < 200, "{solvable}");
+}
+
+#[test]
+fn selection_heuristics_produce_valid_output() {
+    use crate::selection::{
+        SelectionHeuristic, entropy::MinimumEntropy, priority::PriorityMap, random::UniformRandom,
+        remaining_values::MinimumRemainingValues, scanline::Scanline, spiral::Spiral,
+    };
+
+    const W: usize = 16;
+    const H: usize = 16;
+    let rules = crate::rules::samples::flowers_singlepixel::rules();
+
+    fn check<
+        S: SelectionHeuristic<4, u64, Location2D, Direction2D, Tile, DynamicSizeGrid2D<Tile>>,
+    >(
+        rules: &RuleSet2D,
+        mut selection: S,
+    ) {
+        for seed in 0..5 {
+            let mut grid = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+            let result = grid.run_with_heuristics(
+                W * H * 100,
+                Some(BacktrackingByUndo::new()),
+                &mut selection,
+                WeightedRandom::default(),
+            );
+            assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+            assert_valid_2d(&grid, rules);
+        }
+    }
+
+    check(&rules, MinimumEntropy);
+    check(&rules, MinimumRemainingValues);
+    check(&rules, Scanline::new());
+    check(&rules, Spiral::new());
+    check(&rules, UniformRandom::new(0));
+    // bottom-up, the same way the flowers grow
+    let priorities = (0..W)
+        .flat_map(|x| (0..H).map(move |y| (Location2D { x, y }, y as f64)))
+        .collect();
+    check(&rules, PriorityMap::new(priorities));
+}
+
+#[test]
+fn count_constraints_are_met() {
+    use crate::rules::samples::flowers_singlepixel::{STATE_FLOWER, STATE_SKY, rules};
+    use crate::wave_function_collapse::constraints::{CountConstraint, CountLimit};
+    const W: usize = 12;
+    const H: usize = 12;
+    let rules = rules().with_count_constraints([
+        CountConstraint::exactly(STATE_FLOWER, 2),
+        CountConstraint::at_least(STATE_SKY, CountLimit::Fraction(0.5)),
+    ]);
+    let count = |grid: &DynamicSizeGrid2D<Tile>, state| {
+        grid.positions()
+            .filter(|location| {
+                grid.get_tile(*location)
+                    .unwrap()
+                    .possible_states()
+                    .eq([state])
+            })
+            .count()
+    };
+
+    (0..10).into_par_iter().for_each(|seed| {
+        let mut undo = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+        let mut backjumping = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+        let results = [
+            undo.run(W * H * 100, Some(BacktrackingByUndo::new())),
+            backjumping.run(W * H * 100, Some(BacktrackerByBackjumping::new())),
+        ];
+        for (grid, result) in [&undo, &backjumping].into_iter().zip(results) {
+            assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+            assert_valid_2d(grid, &rules);
+            assert_eq!(count(grid, STATE_FLOWER), 2);
+            assert!(count(grid, STATE_SKY) >= W * H / 2);
+        }
+    });
+}
+
+#[test]
+fn walkable_tiles_stay_connected() {
+    use crate::wave_function_collapse::connectivity::ConnectivityConstraint;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    const FLOOR: u64 = 0;
+    const WALL: u64 = 1;
+    const W: usize = 16;
+    const H: usize = 16;
+
+    // walls are more common, so unconstrained outputs are full of isolated pockets of floor
+    let mut allowed = HashSet::new();
+    for a in [FLOOR, WALL] {
+        for b in [FLOOR, WALL] {
+            allowed.insert((a, Direction2D::RIGHT, b));
+            allowed.insert((a, Direction2D::DOWN, b));
+        }
+    }
+    let rules = RuleSet2D::new(
+        BTreeSet::from([FLOOR, WALL]),
+        allowed,
+        HashMap::from([(WALL, 2)]),
+        HashMap::new(),
+        BTreeMap::new(),
+    );
+    let start = Location2D { x: 0, y: 0 };
+    let end = Location2D { x: W - 1, y: H - 1 };
+
+    (0..10).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+        grid.set_connectivity(Some(
+            ConnectivityConstraint::new([FLOOR]).with_path(start, end),
+        ))
+        .unwrap();
+        let result = grid.run(W * H * 100, Some(BacktrackingByUndo::new()));
+        assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+
+        let floor: HashSet<_> = grid
+            .positions()
+            .filter(|location| {
+                grid.get_tile(*location)
+                    .unwrap()
+                    .possible_states()
+                    .eq([FLOOR])
+            })
+            .collect();
+        let mut reached = HashSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(location) = stack.pop() {
+            for (_, neighbour) in grid.get_neighbours(location) {
+                if let Some(neighbour) = neighbour
+                    && floor.contains(&neighbour)
+                    && reached.insert(neighbour)
+                {
+                    stack.push(neighbour);
+                }
+            }
+        }
+        assert_eq!(reached, floor, "seed {seed}");
+        assert!(floor.contains(&end));
+    });
+}
+
+#[test]
+fn masks_are_respected_by_resets() {
+    use crate::grid::masks::StateMasks;
+    use crate::rules::samples::flowers_singlepixel::{STATE_SKY, rules};
+    const W: usize = 20;
+    const H: usize = 20;
+    let rules = rules();
+    // a clearing in the middle of the flowers
+    let mut masks = StateMasks::new();
+    masks.restrict_circle(Location2D { x: 10, y: 8 }, 3, [STATE_SKY]);
+
+    (0..10).into_par_iter().for_each(|seed| {
+        for gradual in [false, true] {
+            let mut grid = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+            grid.set_masks(masks.clone()).unwrap();
+            let result = if gradual {
+                grid.run(W * H * 100, Some(BacktrackerByGradualReset::new(1)))
+            } else {
+                grid.run(W * H * 100, Some(BacktrackerByReset {}))
+            };
+            assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+            assert_valid_2d(&grid, &rules);
+            for (location, mask) in masks.iter() {
+                let tile = grid.get_tile(*location).unwrap();
+                assert!(tile.possible_states().all(|state| mask.contains(&state)));
+            }
+        }
+    });
+}
+
+#[test]
+fn symmetric_outputs_are_symmetric() {
+    use crate::tile_extraction::{
+        TileExtractor,
+        overlapping_bitmap::{OverlappingBitmapExtractor, OverlappingBitmapExtractorOptions},
+    };
+    use crate::wave_function_collapse::symmetry::Symmetry2D;
+    const W: usize = 12;
+    const H: usize = 12;
+    let image = image::load_from_memory(include_bytes!("../../samples/RedDot.png")).unwrap();
+    let extractor = OverlappingBitmapExtractor::new(
+        image,
+        OverlappingBitmapExtractorOptions {
+            n: 3,
+            symmetry: 8,
+            periodic_input: true,
+        },
+    );
+    let rules = extractor.get_rules().clone();
+    let transforms = rules.transforms.clone();
+    let state_at = |grid: &DynamicSizeGrid2D<Tile>, x, y| {
+        let tile = grid.get_tile(Location2D { x, y }).unwrap();
+        tile.possible_states().next().unwrap()
+    };
+
+    (0..5).into_par_iter().for_each(|seed| {
+        for symmetry in [Symmetry2D::MirrorX, Symmetry2D::Rotation90] {
+            let mut grid = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+            grid.set_symmetry(Some(symmetry)).unwrap();
+            let result = grid.run(W * H * 100, Some(BacktrackerByReset {}));
+            assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+            assert_valid_2d(&grid, &rules);
+            for x in 0..W {
+                for y in 0..H {
+                    let state = state_at(&grid, x, y);
+                    let (image, map) = match symmetry {
+                        Symmetry2D::MirrorX => {
+                            (state_at(&grid, W - 1 - x, y), &transforms.mirror_x)
+                        }
+                        _ => (state_at(&grid, y, W - 1 - x), &transforms.rotation),
+                    };
+                    assert_eq!(
+                        map[&state], image,
+                        "seed {seed}, {symmetry:?} at ({x}, {y})"
+                    );
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn even_patterns_are_symmetric_pixel_for_pixel() {
+    use crate::tile_extraction::{
+        TileExtractor,
+        overlapping_bitmap::{OverlappingBitmapExtractor, OverlappingBitmapExtractorOptions},
+    };
+    use crate::wave_function_collapse::symmetry::Symmetry2D;
+    const W: usize = 12;
+    let image = image::load_from_memory(include_bytes!("../../samples/RedDot.png")).unwrap();
+    let extractor = OverlappingBitmapExtractor::new(
+        image,
+        OverlappingBitmapExtractorOptions {
+            n: 2,
+            symmetry: 8,
+            periodic_input: true,
+        },
+    );
+    let rules = extractor.get_rules().clone();
+    assert_eq!(rules.transforms.offset, 1);
+    let pixel_at = |grid: &DynamicSizeGrid2D<Tile>, x, y| {
+        let tile = grid.get_tile(Location2D { x, y }).unwrap();
+        rules.state_representations[&tile.possible_states().next().unwrap()]
+    };
+
+    (0..3).into_par_iter().for_each(|seed| {
+        for symmetry in [Symmetry2D::MirrorX, Symmetry2D::Rotation90] {
+            let mut grid = DynamicSizeGrid2D::<Tile>::new(W, W, rules.clone(), seed);
+            grid.set_symmetry(Some(symmetry)).unwrap();
+            let result = grid.run(W * W * 100, Some(BacktrackerByReset {}));
+            assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+            // the pixels line up even though the patterns are drawn off their centre
+            for x in 0..W {
+                for y in 0..W {
+                    let image = match symmetry {
+                        Symmetry2D::MirrorX => pixel_at(&grid, W - 1 - x, y),
+                        _ => pixel_at(&grid, y, W - 1 - x),
+                    };
+                    assert_eq!(
+                        pixel_at(&grid, x, y),
+                        image,
+                        "seed {seed}, {symmetry:?} at ({x}, {y})"
+                    );
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn weight_fields_shape_the_output() {
+    use crate::grid::weights::WeightField;
+    use crate::utils::noise::{Noise, NoiseKind};
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    const W: usize = 30;
+    const H: usize = 10;
+    const FOREST: u64 = 0;
+    const PLAINS: u64 = 1;
+    // both states are allowed next to each other in any combination
+    let mut allowed = HashSet::new();
+    for a in [FOREST, PLAINS] {
+        for b in [FOREST, PLAINS] {
+            allowed.insert((a, Direction2D::RIGHT, b));
+            allowed.insert((a, Direction2D::DOWN, b));
+        }
+    }
+    let rules = RuleSet2D::new(
+        BTreeSet::from([FOREST, PLAINS]),
+        allowed,
+        HashMap::new(),
+        HashMap::new(),
+        BTreeMap::new(),
+    );
+    // forests thin out into plains from left to right, with some noise on top
+    let noise = Noise::new(NoiseKind::Value, 5, 4.0);
+    let field = WeightField::new()
+        .with_layer(
+            [FOREST],
+            (0..H).flat_map(|y| (0..W).map(move |x| Location2D { x, y })),
+            |l| (W - 1 - l.x) as f64 / (W - 1) as f64 * 4.0,
+        )
+        .with_noise([PLAINS], &noise, (W, H), (1.0, 2.0));
+
+    (0..10).into_par_iter().for_each(|seed| {
+        let mut grid = DynamicSizeGrid2D::<Tile>::new(W, H, rules.clone(), seed);
+        grid.set_weight_field(Some(field.clone()));
+        let result = grid.run(W * H * 10, Some(BacktrackerByReset {}));
+        assert_eq!(result, Err(WaveFunctionCollapseInterruption::Finished));
+        let forests = |xs: std::ops::Range<usize>| {
+            xs.flat_map(|x| (0..H).map(move |y| Location2D { x, y }))
+                .filter(|l| grid.get_tile(*l).unwrap().possible_states().eq([FOREST]))
+                .count()
+        };
+        let (left, right) = (forests(0..W / 3), forests(W * 2 / 3..W));
+        assert!(left > right * 2, "seed {seed}: {left} {right}");
+    });
+}
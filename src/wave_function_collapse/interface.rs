@@ -9,12 +9,15 @@ use serde::Serialize;
 use crate::{
     backtracking::Backtracker,
     grid::GridInterface,
+    selection::SelectionHeuristic,
     tile::interface::TileInterface,
     utils::space::{Direction, Location},
+    value_selection::ValueSelector,
+    wave_function_collapse::constraints::CountConstraintError,
 };
 
 /// Used when the algorithm has to return early for some reason
-#[derive(Debug, Clone, Copy, thiserror::Error, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error, Serialize)]
 pub enum WaveFunctionCollapseInterruption<TPosition> {
     /// All tiles in the grid have been successfully collapsed
     Finished,
@@ -23,6 +26,8 @@ pub enum WaveFunctionCollapseInterruption<TPosition> {
     Contradiction(TPosition),
     /// The algorithm did not complete in the allocated iterations
     MaxIterationsReached,
+    /// The count constraints of the rules can't be met on a grid of this size
+    InvalidCountConstraint(#[from] CountConstraintError),
 }
 
 /// Used for tracking which tiles will need to be visisted during propagation
@@ -63,30 +68,37 @@ pub trait WaveFunctionCollapse<
 
     fn tick(&mut self) -> TickResult<TPosition>;
 
+    /// Collapses the tile at the given position into the state picked by `values`.
+    ///
+    /// If no possible states remain, a contradiction interruption is returned.
+    fn collapse_with<
+        V: ValueSelector<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T, Self>,
+    >(
+        &mut self,
+        position: TPosition,
+        values: &mut V,
+    ) -> TickResult<TPosition>;
+
     /// Runs the algorithm until all tiles have been collapsed, a contradiction occurs or a maximum
     /// amount of iterations is reached
-    // automatically implemented for all types that implement WaveFunctionCollapse
     fn run<B: Backtracker<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T, Self>>(
         &mut self,
         max_iterations: usize,
-        mut backtracker: Option<B>,
-    ) -> TickResult<TPosition> {
-        for _ in 0..max_iterations {
-            let result = self.tick();
-            match result {
-                Ok(()) => continue,
-                Err(WaveFunctionCollapseInterruption::Contradiction(e)) => {
-                    if let Some(handler) = backtracker.as_mut() {
-                        handler.contradiction_handler_recursive(self, e, 300)?;
-                    } else {
-                        return Err(WaveFunctionCollapseInterruption::Contradiction(e));
-                    }
-                }
-                Err(e) => return Err(e),
-            }
-        }
+        backtracker: Option<B>,
+    ) -> TickResult<TPosition>;
 
-        // some tiles were left uncollapsed in the given time
-        Err(WaveFunctionCollapseInterruption::MaxIterationsReached)
-    }
+    /// Same as `run`, but the next tile to collapse is picked by `selection` and the state it
+    /// collapses into by `values`, instead of always picking the tile with the lowest entropy and
+    /// a state weighted by the rules
+    fn run_with_heuristics<
+        B: Backtracker<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T, Self>,
+        S: SelectionHeuristic<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T, Self>,
+        V: ValueSelector<NEIGHBOURS_PER_TILE, TState, TPosition, TDirection, T, Self>,
+    >(
+        &mut self,
+        max_iterations: usize,
+        backtracker: Option<B>,
+        selection: S,
+        values: V,
+    ) -> TickResult<TPosition>;
 }
@@ -1,41 +1,50 @@
 //! Main implementation of the algorithm
 
+pub mod connectivity;
+pub mod constraints;
 #[cfg(test)]
+// keeps the older tests as they were written
+#[allow(clippy::manual_range_contains)]
 mod e2e_tests;
 pub mod interface;
+pub mod propagation;
+pub mod symmetry;
 
-use std::{
-    collections::{BTreeSet, VecDeque},
-    hash::Hash,
-};
+use std::{collections::VecDeque, hash::Hash};
 
 use interface::{
     PropagateQueueEntry, TickResult, WaveFunctionCollapse, WaveFunctionCollapseInterruption,
 };
 
 use crate::{
+    backtracking::Backtracker,
     grid::GridInterface,
+    selection::{SelectionHeuristic, entropy::MinimumEntropy},
     tile::{
-        Tile, TileState,
+        TileState,
         interface::{TileCollapseInstruction, TileInterface},
     },
-    utils::space::{
-        Direction, Location,
-        s2d::{Direction2D, Location2D, NEIGHBOUR_COUNT_2D},
-    },
+    utils::space::{Direction, Location},
+    value_selection::{ValueSelector, weighted::WeightedRandom},
 };
 
-// Implements the Wave Function Collapse algorithm for any struct that implements `GridInterface`
+// Implements the Wave Function Collapse algorithm for any struct that implements `GridInterface`,
+// regardless of the dimensions or topology of the grid
 // See the trait for further documentation about the methods
-impl<T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile>>
-    WaveFunctionCollapse<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Tile> for T
+impl<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    TTile: TileInterface<TileState> + Clone,
+    T: GridInterface<N, TileState, TPosition, TDirection, TTile>,
+> WaveFunctionCollapse<N, TileState, TPosition, TDirection, TTile> for T
 {
     fn collapse(
         &mut self,
-        position: Location2D,
+        position: TPosition,
         value: Option<TileState>,
-    ) -> Result<(), WaveFunctionCollapseInterruption<Location2D>> {
-        let weights = self.get_rules().weights.clone();
+    ) -> Result<(), WaveFunctionCollapseInterruption<TPosition>> {
+        let weights = self.get_weights(position).into_owned();
         self.with_tile(position, |tile, rng| {
             let instruction = match value {
                 None => TileCollapseInstruction::Random(rng, &weights),
@@ -61,52 +70,140 @@ impl<T: GridInterface<NEIGHBOUR_COUNT_2D, TileState, Location2D, Direction2D, Ti
 
     fn propagate(
         &mut self,
-        mut queue: VecDeque<PropagateQueueEntry<Location2D>>,
-    ) -> TickResult<Location2D> {
-        while let Some(queue_entry) = queue.pop_front() {
-            let delta = queue_entry
-                .target
-                .delta(queue_entry.source)
-                .expect("converting propagate location to delta");
-            let direction =
-                Direction2D::try_from(delta).expect("converting propagate delta to direction");
-            let source = self
-                .get_tile(queue_entry.source)
-                .expect("getting propagation source")
-                .clone();
-            let rules = self.get_rules().clone();
-            let should_propagate = self
-                .with_tile(queue_entry.target, |target, _| {
-                    let old_states: BTreeSet<_> = target.possible_states().collect();
-                    let checked_states = rules.check(target, &source, direction);
-                    if checked_states.is_empty() {
-                        return Err(WaveFunctionCollapseInterruption::Contradiction(
-                            queue_entry.target,
-                        ));
+        mut queue: VecDeque<PropagateQueueEntry<TPosition>>,
+    ) -> TickResult<TPosition> {
+        let mut allowed = Vec::new();
+        loop {
+            while let Some(queue_entry) = queue.pop_front() {
+                let mut checked = self
+                    .get_tile(queue_entry.target)
+                    .expect("getting propagation target")
+                    .clone();
+                let mut was_modified = false;
+                // on small periodic grids the source can be a neighbour in more than one
+                // direction, each of them constrains the target
+                for (direction, neighbour) in self.get_neighbours(queue_entry.target) {
+                    if neighbour != Some(queue_entry.source) {
+                        continue;
                     }
-                    let was_modified = old_states != checked_states;
-                    if was_modified {
-                        target.set_possible_states(checked_states);
-                    }
-                    Ok(was_modified)
-                })
-                .expect("updating tile during propagation")?;
-            if should_propagate {
-                queue.extend(propagate_from_tile(self, queue_entry.target));
+                    let rules = self.get_compiled_rules();
+                    match self.get_support_counts() {
+                        Some(support) => {
+                            support.supported_mask(queue_entry.target, direction, &mut allowed)
+                        }
+                        None => {
+                            let source = self
+                                .get_tile(queue_entry.source)
+                                .expect("getting propagation source");
+                            rules.supported_mask(
+                                direction,
+                                source.possible_indices(rules.state_index()),
+                                &mut allowed,
+                            );
+                        }
+                    };
+                    was_modified |= checked.retain_mask(rules.state_index(), &allowed);
+                }
+                if checked.possible_states_ref().next().is_none() {
+                    return Err(WaveFunctionCollapseInterruption::Contradiction(
+                        queue_entry.target,
+                    ));
+                }
+                if was_modified {
+                    self.with_tile(queue_entry.target, |target, _| {
+                        *target = checked.clone();
+                    })
+                    .expect("updating tile during propagation");
+                    queue.extend(propagate_from_tile(self, queue_entry.target));
+                }
+            }
+            // global constraints are only checked once the local changes have settled
+            let constraint_queue = match constraints::enforce(self)? {
+                Some(constraint_queue) => Some(constraint_queue),
+                None => match connectivity::enforce(self)? {
+                    Some(constraint_queue) => Some(constraint_queue),
+                    None => symmetry::enforce(self)?,
+                },
+            };
+            match constraint_queue {
+                Some(constraint_queue) => queue = constraint_queue,
+                None => return Ok(()),
             }
         }
-        Ok(())
     }
 
-    fn tick(&mut self) -> TickResult<Location2D> {
+    fn tick(&mut self) -> TickResult<TPosition> {
         let lowest_entropy = self
             .get_lowest_entropy_position()
-            .ok_or(WaveFunctionCollapseInterruption::Finished::<Location2D>)?;
+            .ok_or(WaveFunctionCollapseInterruption::Finished::<TPosition>)?;
 
         self.collapse(lowest_entropy, None)?;
 
         Ok(())
     }
+
+    fn collapse_with<V: ValueSelector<N, TileState, TPosition, TDirection, TTile, Self>>(
+        &mut self,
+        position: TPosition,
+        values: &mut V,
+    ) -> TickResult<TPosition> {
+        let value = values
+            .select_value(self, position)
+            .ok_or(WaveFunctionCollapseInterruption::Contradiction(position))?;
+        self.collapse(position, Some(value))
+    }
+
+    fn run<B: Backtracker<N, TileState, TPosition, TDirection, TTile, Self>>(
+        &mut self,
+        max_iterations: usize,
+        backtracker: Option<B>,
+    ) -> TickResult<TPosition> {
+        self.run_with_heuristics(
+            max_iterations,
+            backtracker,
+            MinimumEntropy,
+            WeightedRandom::default(),
+        )
+    }
+
+    fn run_with_heuristics<
+        B: Backtracker<N, TileState, TPosition, TDirection, TTile, Self>,
+        S: SelectionHeuristic<N, TileState, TPosition, TDirection, TTile, Self>,
+        V: ValueSelector<N, TileState, TPosition, TDirection, TTile, Self>,
+    >(
+        &mut self,
+        max_iterations: usize,
+        mut backtracker: Option<B>,
+        mut selection: S,
+        mut values: V,
+    ) -> TickResult<TPosition> {
+        for _ in 0..max_iterations {
+            let position = selection
+                .select(self)
+                .ok_or(WaveFunctionCollapseInterruption::Finished)?;
+            let result = match values.select_value(self, position) {
+                None => Err(WaveFunctionCollapseInterruption::Contradiction(position)),
+                Some(value) => match backtracker.as_mut() {
+                    Some(handler) => handler.tick(self, position, value),
+                    None => self.collapse(position, Some(value)),
+                },
+            };
+            match result {
+                Ok(()) => continue,
+                Err(WaveFunctionCollapseInterruption::Contradiction(e)) => {
+                    if let Some(handler) = backtracker.as_mut() {
+                        handler.contradiction_handler_recursive(self, e, 300)?;
+                    } else {
+                        return Err(WaveFunctionCollapseInterruption::Contradiction(e));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        // some tiles were left uncollapsed in the given time
+        Err(WaveFunctionCollapseInterruption::MaxIterationsReached)
+    }
 }
 
 #[inline]
@@ -147,6 +244,7 @@ mod tests {
     use crate::{
         grid::constant_2d::ConstantSizeGrid2D,
         rules::{RuleSet, RuleSet2D},
+        utils::space::s2d::{Direction2D, Location2D},
     };
 
     use super::*;
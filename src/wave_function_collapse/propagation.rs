@@ -0,0 +1,390 @@
+//! Bookkeeping for the different ways changes can be propagated through the grid
+//!
+//! The pairwise strategy combines the compatible states of every state of a tile's neighbour
+//! into a bitmask (see `CompiledRuleSet::supported_mask`), which has to be rebuilt on every
+//! check. On rulesets with hundreds of states this gets slow quickly.
+//!
+//! The support-count strategy (similar to AC-4) instead keeps track of how many states of each
+//! neighbour "support" each state of a tile. A state is removed only once its support in some
+//! direction drops to zero. The supported states are kept as a bitmask, so checking a tile only
+//! copies a mask, and updating the counters costs as much as the states that changed. This pays
+//! off on rulesets with thousands of states, see the `gradient` benchmark.
+
+use std::{collections::HashMap, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    grid::GridInterface,
+    rules::compiled::{CompiledRuleSet, WORD_BITS},
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location},
+};
+
+/// Selects how the possible states of a tile are recalculated after its neighbour has changed
+///
+/// Both strategies produce identical results, they only differ in performance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum PropagationStrategy {
+    /// Check the target against every state of the source, see `CompiledRuleSet::supported_mask`
+    #[default]
+    Pairwise,
+    /// Keep per-tile, per-direction support counters, see `SupportCounts`
+    SupportCount,
+}
+
+/// Per-tile, per-direction counters of how many neighbouring states allow each state
+///
+/// The counters have to be kept up to date by calling `tile_updated` every time the possible
+/// states of a tile change. Grids do this automatically if they were created with
+/// `PropagationStrategy::SupportCount`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupportCounts<TPosition: Location, TDirection: Hash + Eq + Ord + Copy> {
+    /// Provides the compatible states of each (neighbour state, direction) pair
+    rules: CompiledRuleSet<TDirection>,
+    /// tile -> support of its states, missing tiles have zero support
+    counts: HashMap<TPosition, TileSupport>,
+    /// Scratch buffers for the state indices removed from, added to and left in an updated
+    /// tile, kept around so `tile_updated` doesn't allocate
+    #[serde(skip)]
+    removed: Vec<usize>,
+    #[serde(skip)]
+    added: Vec<usize>,
+    #[serde(skip)]
+    current: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TileSupport {
+    /// Amount of states in the neighbour towards a direction that allow a state.
+    /// Indexed with `direction_index * rules.len() + state_index`.
+    counts: Vec<usize>,
+    /// Bitmasks of the states with a non-zero count, so checking a tile doesn't have to look at
+    /// every counter. Each direction takes `rules.words()` words.
+    supported: Vec<u64>,
+}
+
+impl TileSupport {
+    fn new<TDirection: Ord + Copy>(rules: &CompiledRuleSet<TDirection>) -> Self {
+        let directions = rules.directions().len();
+        Self {
+            counts: vec![0; directions * rules.len()],
+            supported: vec![0; directions * rules.words()],
+        }
+    }
+
+    /// Adds or removes one from the counter of every state that a neighbour state (given by
+    /// index) allows. With `reset` the support from `direction` is cleared first.
+    fn update<TDirection: Ord + Copy, I: IntoIterator<Item = usize>>(
+        &mut self,
+        rules: &CompiledRuleSet<TDirection>,
+        direction: TDirection,
+        neighbours: I,
+        add: bool,
+        reset: bool,
+    ) {
+        let Some(direction_index) = rules.direction_index(direction) else {
+            // no rule mentions the direction, so nothing can be supported from there
+            return;
+        };
+        let (state_count, words) = (rules.len(), rules.words());
+        let counts = &mut self.counts[direction_index * state_count..][..state_count];
+        let supported = &mut self.supported[direction_index * words..][..words];
+        if reset {
+            counts.fill(0);
+            supported.fill(0);
+        }
+        for neighbour in neighbours {
+            for &state in rules.compatible(direction, neighbour) {
+                let bit = 1 << (state % WORD_BITS);
+                let count = &mut counts[state];
+                if add {
+                    *count += 1;
+                    supported[state / WORD_BITS] |= bit;
+                } else if *count > 0 {
+                    *count -= 1;
+                    if *count == 0 {
+                        supported[state / WORD_BITS] &= !bit;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<TPosition: Location, TDirection: Hash + Eq + Ord + Copy> SupportCounts<TPosition, TDirection> {
+    /// Creates empty counters, no state has any support yet
+    pub fn new(rules: CompiledRuleSet<TDirection>) -> Self {
+        Self {
+            rules,
+            counts: HashMap::new(),
+            removed: Vec::new(),
+            added: Vec::new(),
+            current: Vec::new(),
+        }
+    }
+
+    /// Creates counters that match the current possible states of all tiles in the grid
+    pub fn from_grid<
+        const N: usize,
+        T: TileInterface<TileState>,
+        G: GridInterface<N, TileState, TPosition, TDirection, T>,
+    >(
+        grid: &G,
+    ) -> Self
+    where
+        TDirection: Direction<N>,
+    {
+        let mut new = Self::new(grid.get_compiled_rules().clone());
+        // most tiles start out with all states, so their support is only counted once
+        let mut full = TileSupport::new(&new.rules);
+        for direction in new.rules.directions().to_vec() {
+            full.update(&new.rules, direction, 0..new.rules.len(), true, false);
+        }
+        let mut neighbour_indices = Vec::new();
+        for location in grid.positions() {
+            let mut support = full.clone();
+            for (direction, neighbour) in grid.get_neighbours(location) {
+                neighbour_indices.clear();
+                if let Some(neighbour) = neighbour.and_then(|n| grid.get_tile(n)) {
+                    neighbour_indices.extend(neighbour.possible_indices(new.rules.state_index()));
+                }
+                if neighbour_indices.len() != new.rules.len() {
+                    support.update(
+                        &new.rules,
+                        direction,
+                        neighbour_indices.iter().copied(),
+                        true,
+                        true,
+                    );
+                }
+            }
+            new.counts.insert(location, support);
+        }
+        new
+    }
+
+    /// Returns true if at least one state of the neighbour in `direction` allows `state` at
+    /// `location`
+    #[inline]
+    pub fn is_supported(
+        &self,
+        location: TPosition,
+        direction: TDirection,
+        state: TileState,
+    ) -> bool {
+        let (Some(direction_index), Some(state_index)) = (
+            self.rules.direction_index(direction),
+            self.rules.index_of(state),
+        ) else {
+            return false;
+        };
+        self.counts.get(&location).is_some_and(|support| {
+            support.counts[direction_index * self.rules.len() + state_index] > 0
+        })
+    }
+
+    /// Fills `mask` with the states at `location` that are supported by at least one state of
+    /// the neighbour in `direction`, with the same layout as `CompiledRuleSet::supported_mask`
+    pub fn supported_mask(&self, location: TPosition, direction: TDirection, mask: &mut Vec<u64>) {
+        mask.clear();
+        mask.resize(self.rules.words(), 0);
+        let (Some(direction_index), Some(support)) = (
+            self.rules.direction_index(direction),
+            self.counts.get(&location),
+        ) else {
+            return;
+        };
+        let words = self.rules.words();
+        mask.copy_from_slice(&support.supported[direction_index * words..][..words]);
+    }
+
+    fn update_support<I: IntoIterator<Item = usize>>(
+        &mut self,
+        location: TPosition,
+        direction: TDirection,
+        neighbours: I,
+        add: bool,
+        reset: bool,
+    ) {
+        self.counts
+            .entry(location)
+            .or_insert_with(|| TileSupport::new(&self.rules))
+            .update(&self.rules, direction, neighbours, add, reset);
+    }
+
+    /// Registers `neighbour_states` that were added to the neighbour of `location` in `direction`
+    pub fn add_support<I: IntoIterator<Item = TileState>>(
+        &mut self,
+        location: TPosition,
+        direction: TDirection,
+        neighbour_states: I,
+    ) {
+        let index = self.rules.state_index().clone();
+        let neighbours = neighbour_states
+            .into_iter()
+            .filter_map(|state| index.index_of(state));
+        self.update_support(location, direction, neighbours, true, false);
+    }
+
+    /// Unregisters `neighbour_states` that were removed from the neighbour of `location` in
+    /// `direction`
+    pub fn remove_support<I: IntoIterator<Item = TileState>>(
+        &mut self,
+        location: TPosition,
+        direction: TDirection,
+        neighbour_states: I,
+    ) {
+        let index = self.rules.state_index().clone();
+        let neighbours = neighbour_states
+            .into_iter()
+            .filter_map(|state| index.index_of(state));
+        self.update_support(location, direction, neighbours, false, false);
+    }
+
+    /// Updates the counters of the neighbours of a tile whose possible states changed from `old`
+    /// to `new`
+    pub fn tile_updated<const N: usize, T: TileInterface<TileState>>(
+        &mut self,
+        neighbours: [(TDirection, Option<TPosition>); N],
+        old: &T,
+        new: &T,
+    ) where
+        TDirection: Direction<N>,
+    {
+        let mut removed = std::mem::take(&mut self.removed);
+        let mut added = std::mem::take(&mut self.added);
+        let mut current = std::mem::take(&mut self.current);
+        removed.clear();
+        added.clear();
+        current.clear();
+        {
+            // both tiles list their indices in ascending order, so a single merge finds the changes
+            let index = self.rules.state_index();
+            let mut old_indices = old.possible_indices(index).peekable();
+            let mut new_indices = new.possible_indices(index).peekable();
+            loop {
+                match (old_indices.peek().copied(), new_indices.peek().copied()) {
+                    (Some(old_index), Some(new_index)) if old_index == new_index => {
+                        current.push(new_index);
+                        old_indices.next();
+                        new_indices.next();
+                    }
+                    (Some(old_index), new_index) if new_index.is_none_or(|n| old_index < n) => {
+                        removed.push(old_index);
+                        old_indices.next();
+                    }
+                    (_, Some(new_index)) => {
+                        current.push(new_index);
+                        added.push(new_index);
+                        new_indices.next();
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        // when most states changed (e.g. on a collapse), counting the remaining ones from
+        // scratch is cheaper than going through every change
+        let recount = removed.len() + added.len() > current.len();
+        for (direction, neighbour) in neighbours {
+            if let Some(neighbour) = neighbour {
+                // from the neighbour's point of view, the updated tile lies in the opposite
+                // direction
+                let direction = direction.mirror();
+                if recount {
+                    self.update_support(neighbour, direction, current.iter().copied(), true, true);
+                } else {
+                    self.update_support(
+                        neighbour,
+                        direction,
+                        removed.iter().copied(),
+                        false,
+                        false,
+                    );
+                    self.update_support(neighbour, direction, added.iter().copied(), true, false);
+                }
+            }
+        }
+        self.removed = removed;
+        self.added = added;
+        self.current = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    use crate::{
+        rules::RuleSet2D,
+        tile::Tile,
+        utils::space::s2d::{Direction2D, Location2D},
+    };
+
+    use super::*;
+
+    #[test]
+    fn support_follows_neighbour_states() {
+        const STATE_A: TileState = 0;
+        const STATE_B: TileState = 1;
+        const STATE_C: TileState = 2;
+        let rules = RuleSet2D::new(
+            BTreeSet::from([STATE_A, STATE_B, STATE_C]),
+            HashSet::from([
+                (STATE_A, Direction2D::RIGHT, STATE_B),
+                (STATE_A, Direction2D::RIGHT, STATE_C),
+            ]),
+            HashMap::new(),
+            HashMap::new(),
+            BTreeMap::new(),
+        );
+
+        let left = Location2D { x: 0, y: 0 };
+        let right = Location2D { x: 1, y: 0 };
+        let mut support = SupportCounts::new(rules.compile());
+        support.add_support(left, Direction2D::RIGHT, [STATE_A, STATE_B, STATE_C]);
+        support.add_support(right, Direction2D::LEFT, [STATE_A, STATE_B, STATE_C]);
+
+        assert!(support.is_supported(left, Direction2D::RIGHT, STATE_A));
+        assert!(!support.is_supported(left, Direction2D::RIGHT, STATE_B));
+        assert!(support.is_supported(right, Direction2D::LEFT, STATE_B));
+        assert!(support.is_supported(right, Direction2D::LEFT, STATE_C));
+        assert!(!support.is_supported(right, Direction2D::LEFT, STATE_A));
+        let mut mask = vec![];
+        support.supported_mask(right, Direction2D::LEFT, &mut mask);
+        assert_eq!(mask, vec![0b110]);
+
+        // removing one of the two supporting states should keep A supported
+        let old = Tile::new([STATE_A, STATE_B, STATE_C]);
+        let new = Tile::new([STATE_A, STATE_C]);
+        let neighbours_of_right = [
+            (Direction2D::UP, None),
+            (Direction2D::RIGHT, None),
+            (Direction2D::DOWN, None),
+            (Direction2D::LEFT, Some(left)),
+        ];
+        support.tile_updated(neighbours_of_right, &old, &new);
+        assert!(support.is_supported(left, Direction2D::RIGHT, STATE_A));
+
+        // removing the last one shouldn't
+        let newer = Tile::new([STATE_A]);
+        support.tile_updated(neighbours_of_right, &new, &newer);
+        assert!(!support.is_supported(left, Direction2D::RIGHT, STATE_A));
+
+        // and adding it back should restore the support
+        support.tile_updated(neighbours_of_right, &newer, &old);
+        assert!(support.is_supported(left, Direction2D::RIGHT, STATE_A));
+
+        // most states changing at once recounts the remaining ones
+        let only_b = Tile::new([STATE_B]);
+        support.tile_updated(neighbours_of_right, &old, &only_b);
+        assert!(support.is_supported(left, Direction2D::RIGHT, STATE_A));
+        support.tile_updated(neighbours_of_right, &only_b, &newer);
+        assert!(!support.is_supported(left, Direction2D::RIGHT, STATE_A));
+        support.tile_updated(neighbours_of_right, &newer, &old);
+        assert!(support.is_supported(left, Direction2D::RIGHT, STATE_A));
+    }
+}
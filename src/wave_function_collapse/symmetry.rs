@@ -0,0 +1,388 @@
+//! Mirror and rotational symmetry of the output
+//!
+//! A `SymmetryConstraint` ties every tile to its images under a set of transformations of the
+//! grid, for example the tile on the opposite side of the vertical centre line. The states of an
+//! image are kept to the transformed states of the tile, so collapsing one tile collapses its
+//! images as well.
+//!
+//! Which state a state turns into when mirrored or rotated is up to the rules, see
+//! `StateTransforms`.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use tsify_next::Tsify;
+
+use crate::{
+    tile::{TileState, interface::TileInterface},
+    utils::space::{Direction, Location, s2d::Location2D},
+};
+
+use super::{
+    interface::{PropagateQueueEntry, WaveFunctionCollapse, WaveFunctionCollapseInterruption},
+    propagate_from_tile,
+};
+
+/// The states tiles turn into when the output is mirrored or rotated. States missing from a map
+/// stay as they are.
+#[derive(Debug, Clone, Default, PartialEq, Tsify, Serialize, Deserialize)]
+pub struct StateTransforms {
+    /// Mirrored left to right
+    #[serde(default)]
+    pub mirror_x: HashMap<TileState, TileState>,
+    /// Mirrored top to bottom
+    #[serde(default)]
+    pub mirror_y: HashMap<TileState, TileState>,
+    /// Rotated 90° counterclockwise
+    #[serde(default)]
+    pub rotation: HashMap<TileState, TileState>,
+    /// How many tiles further the image of a tile lies than the plain mirror position. The
+    /// states of overlapping patterns of an even size are drawn with the pixel below and right of
+    /// their centre, which lands one pixel off the centre when the pattern is mirrored, so their
+    /// images are moved by 1 to line the pixels up again.
+    #[serde(default)]
+    pub offset: usize,
+}
+
+impl StateTransforms {
+    pub fn is_empty(&self) -> bool {
+        self.mirror_x.is_empty() && self.mirror_y.is_empty() && self.rotation.is_empty()
+    }
+}
+
+/// Reasons a symmetry can't be applied to a grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum SymmetryError<TPosition: std::fmt::Debug> {
+    #[error("only square grids can be rotated by 90°, the grid is {width}x{height}")]
+    NotSquare { width: usize, height: usize },
+    #[error("the current tiles break the symmetry: {0:?}")]
+    Interrupted(WaveFunctionCollapseInterruption<TPosition>),
+}
+
+/// Symmetries of a 2D output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Symmetry2D {
+    /// The right half mirrors the left half
+    MirrorX,
+    /// The bottom half mirrors the top half
+    MirrorY,
+    /// Mirrored along both axes
+    MirrorBoth,
+    /// Looks the same when rotated by 180°
+    Rotation180,
+    /// Looks the same when rotated by 90°, only possible on square grids
+    Rotation90,
+}
+
+fn transform(map: &HashMap<TileState, TileState>, state: TileState) -> TileState {
+    map.get(&state).copied().unwrap_or(state)
+}
+
+type ImageOf<'a> = Box<dyn Fn(Location2D) -> Location2D + 'a>;
+
+/// Ties tiles to their images, see the module documentation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymmetryConstraint<TPosition> {
+    /// Tile, its image and the index of the state transform between them
+    ties: Vec<(TPosition, TPosition, usize)>,
+    /// Every state of the rules -> the state of the image
+    transforms: Vec<HashMap<TileState, TileState>>,
+}
+
+impl<TPosition> SymmetryConstraint<TPosition> {
+    /// `ties` lists each tile, its image and the index of the transform in `transforms`. The
+    /// transforms should form a group, so that every image is tied back to the tile.
+    pub fn new(
+        ties: Vec<(TPosition, TPosition, usize)>,
+        transforms: Vec<HashMap<TileState, TileState>>,
+    ) -> Self {
+        Self { ties, transforms }
+    }
+}
+
+impl SymmetryConstraint<Location2D> {
+    /// Ties the tiles of a `width` by `height` grid according to `symmetry`, transforming the
+    /// `possible` states with `transforms`. Tiles whose image lies outside of the grid, because
+    /// of `StateTransforms::offset`, are left untied.
+    ///
+    /// Returns an error if `Symmetry2D::Rotation90` is used on a grid that isn't square
+    pub fn new_2d(
+        symmetry: Symmetry2D,
+        width: usize,
+        height: usize,
+        possible: &BTreeSet<TileState>,
+        transforms: &StateTransforms,
+    ) -> Result<Self, SymmetryError<Location2D>> {
+        if symmetry == Symmetry2D::Rotation90 && width != height {
+            return Err(SymmetryError::NotSquare { width, height });
+        }
+        // the far edge of the grid, moved by the offset; never below any coordinate passed in,
+        // as images only go past the edge by the offset
+        let (last_x, last_y) = (
+            width.saturating_sub(1) + transforms.offset,
+            height.saturating_sub(1) + transforms.offset,
+        );
+        let mirror_x = |l: Location2D| Location2D {
+            x: last_x - l.x,
+            y: l.y,
+        };
+        let mirror_y = |l: Location2D| Location2D {
+            x: l.x,
+            y: last_y - l.y,
+        };
+        // matches the direction of `StateTransforms::rotation`
+        let rotate = |l: Location2D| Location2D {
+            x: l.y,
+            y: last_x - l.x,
+        };
+
+        let states = |f: &dyn Fn(TileState) -> TileState| -> HashMap<TileState, TileState> {
+            possible.iter().map(|s| (*s, f(*s))).collect()
+        };
+        let (mx, my, r) = (
+            &transforms.mirror_x,
+            &transforms.mirror_y,
+            &transforms.rotation,
+        );
+        let rotate_states = |s, times| (0..times).fold(s, |s, _| transform(r, s));
+
+        let images: Vec<(ImageOf, HashMap<_, _>)> = match symmetry {
+            Symmetry2D::MirrorX => vec![(Box::new(mirror_x), states(&|s| transform(mx, s)))],
+            Symmetry2D::MirrorY => vec![(Box::new(mirror_y), states(&|s| transform(my, s)))],
+            Symmetry2D::MirrorBoth => vec![
+                (Box::new(mirror_x), states(&|s| transform(mx, s))),
+                (Box::new(mirror_y), states(&|s| transform(my, s))),
+                (
+                    Box::new(move |l| mirror_y(mirror_x(l))),
+                    states(&|s| transform(my, transform(mx, s))),
+                ),
+            ],
+            Symmetry2D::Rotation180 => vec![(
+                Box::new(move |l| mirror_y(mirror_x(l))),
+                states(&|s| rotate_states(s, 2)),
+            )],
+            Symmetry2D::Rotation90 => {
+                vec![
+                    (Box::new(rotate), states(&|s| rotate_states(s, 1))),
+                    (
+                        Box::new(move |l| rotate(rotate(l))),
+                        states(&|s| rotate_states(s, 2)),
+                    ),
+                    (
+                        Box::new(move |l| rotate(rotate(rotate(l)))),
+                        states(&|s| rotate_states(s, 3)),
+                    ),
+                ]
+            }
+        };
+
+        let mut ties = Vec::new();
+        let mut state_transforms = Vec::new();
+        for (i, (image_of, states)) in images.into_iter().enumerate() {
+            for y in 0..height {
+                for x in 0..width {
+                    let position = Location2D { x, y };
+                    let image = image_of(position);
+                    if image.x < width && image.y < height {
+                        ties.push((position, image, i));
+                    }
+                }
+            }
+            state_transforms.push(states);
+        }
+        Ok(Self::new(ties, state_transforms))
+    }
+}
+
+/// Keeps the states of every image to the transformed states of its tile.
+///
+/// Returns the propagation caused by the changes, or None if nothing had to be changed.
+pub(crate) fn enforce<
+    const N: usize,
+    TPosition: Location,
+    TDirection: Direction<N>,
+    T: TileInterface<TileState> + Clone,
+    G: WaveFunctionCollapse<N, TileState, TPosition, TDirection, T>,
+>(
+    grid: &mut G,
+) -> Result<
+    Option<VecDeque<PropagateQueueEntry<TPosition>>>,
+    WaveFunctionCollapseInterruption<TPosition>,
+> {
+    let Some(symmetry) = grid.get_symmetry() else {
+        return Ok(None);
+    };
+
+    let mut changes = Vec::new();
+    for (position, image, transform_index) in &symmetry.ties {
+        let (Some(tile), Some(image_tile)) = (grid.get_tile(*position), grid.get_tile(*image))
+        else {
+            continue;
+        };
+        let transform = &symmetry.transforms[*transform_index];
+        let transformed = |state: &TileState| transform.get(state).copied().unwrap_or(*state);
+        let allowed: BTreeSet<_> = if position == image {
+            // tiles on the axis of the symmetry are their own image
+            tile.possible_states_ref()
+                .filter(|state| transformed(state) == **state)
+                .copied()
+                .collect()
+        } else {
+            tile.possible_states_ref().map(transformed).collect()
+        };
+        if image_tile
+            .possible_states_ref()
+            .any(|state| !allowed.contains(state))
+        {
+            changes.push((*image, allowed));
+        }
+    }
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    let mut queue = VecDeque::new();
+    for (image, allowed) in changes {
+        let emptied = grid.with_tile(image, |tile, _| {
+            tile.retain(|state| allowed.contains(state));
+            tile.possible_states_ref().next().is_none()
+        });
+        if emptied == Some(true) {
+            return Err(WaveFunctionCollapseInterruption::Contradiction(image));
+        }
+        queue.extend(propagate_from_tile(grid, image));
+    }
+    Ok(Some(queue))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::{GridInterface, dynamic_2d::DynamicSizeGrid2D, tests::any_combination_rules},
+        tile::Tile,
+    };
+
+    use super::*;
+
+    const LEFT: TileState = 0;
+    const RIGHT: TileState = 1;
+    const BLANK: TileState = 2;
+
+    /// Arrows pointing left or right and blanks, allowed next to each other in any combination
+    fn gen_grid(width: usize, height: usize) -> DynamicSizeGrid2D<Tile> {
+        let mut rules = any_combination_rules([LEFT, RIGHT, BLANK]);
+        rules.transforms.mirror_x = HashMap::from([(LEFT, RIGHT), (RIGHT, LEFT)]);
+        rules.transforms.rotation = HashMap::from([(LEFT, RIGHT), (RIGHT, LEFT)]);
+        DynamicSizeGrid2D::new(width, height, rules, 0)
+    }
+
+    fn states(grid: &DynamicSizeGrid2D<Tile>, x: usize, y: usize) -> Vec<TileState> {
+        grid.get_tile(Location2D { x, y })
+            .unwrap()
+            .possible_states()
+            .collect()
+    }
+
+    #[test]
+    fn mirrored_tiles_collapse_together() {
+        let mut grid = gen_grid(4, 2);
+        grid.set_symmetry(Some(Symmetry2D::MirrorX)).unwrap();
+        grid.collapse(Location2D { x: 0, y: 1 }, Some(LEFT))
+            .unwrap();
+        assert_eq!(states(&grid, 3, 1), vec![RIGHT]);
+        assert_eq!(states(&grid, 3, 0), vec![LEFT, RIGHT, BLANK]);
+
+        // the ties survive resets
+        grid.reset();
+        grid.collapse(Location2D { x: 2, y: 0 }, Some(BLANK))
+            .unwrap();
+        assert_eq!(states(&grid, 1, 0), vec![BLANK]);
+    }
+
+    #[test]
+    fn middle_tiles_have_to_be_symmetric() {
+        let mut grid = gen_grid(3, 1);
+        grid.set_symmetry(Some(Symmetry2D::MirrorX)).unwrap();
+        assert_eq!(states(&grid, 1, 0), vec![BLANK]);
+        assert_eq!(states(&grid, 0, 0), vec![LEFT, RIGHT, BLANK]);
+    }
+
+    #[test]
+    fn rotations_follow_the_state_transforms() {
+        let mut grid = gen_grid(3, 3);
+        grid.set_symmetry(Some(Symmetry2D::Rotation90)).unwrap();
+        grid.collapse(Location2D { x: 0, y: 0 }, Some(LEFT))
+            .unwrap();
+        // rotating counterclockwise moves the top left corner to the bottom left
+        assert_eq!(states(&grid, 0, 2), vec![RIGHT]);
+        assert_eq!(states(&grid, 2, 2), vec![LEFT]);
+        assert_eq!(states(&grid, 2, 0), vec![RIGHT]);
+        assert_eq!(states(&grid, 1, 1), vec![BLANK]);
+    }
+
+    #[test]
+    fn broken_symmetry_is_a_contradiction() {
+        let mut grid = gen_grid(2, 1);
+        grid.collapse(Location2D { x: 0, y: 0 }, Some(LEFT))
+            .unwrap();
+        grid.collapse(Location2D { x: 1, y: 0 }, Some(LEFT))
+            .unwrap();
+        assert_eq!(
+            grid.set_symmetry(Some(Symmetry2D::MirrorX)),
+            Err(SymmetryError::Interrupted(
+                WaveFunctionCollapseInterruption::Contradiction(Location2D { x: 1, y: 0 })
+            ))
+        );
+    }
+
+    #[test]
+    fn unsupported_grids_are_rejected() {
+        let mut grid = gen_grid(3, 2);
+        assert_eq!(
+            grid.set_symmetry(Some(Symmetry2D::Rotation90)),
+            Err(SymmetryError::NotSquare {
+                width: 3,
+                height: 2
+            })
+        );
+        assert!(grid.get_symmetry().is_none());
+
+        let possible = BTreeSet::from([LEFT, RIGHT]);
+        for symmetry in [Symmetry2D::MirrorBoth, Symmetry2D::Rotation90] {
+            let empty =
+                SymmetryConstraint::new_2d(symmetry, 0, 0, &possible, &StateTransforms::default())
+                    .unwrap();
+            assert!(empty.ties.is_empty());
+        }
+    }
+
+    #[test]
+    fn offset_moves_the_images() {
+        let transforms = StateTransforms {
+            offset: 1,
+            ..Default::default()
+        };
+        let possible = BTreeSet::from([LEFT, RIGHT]);
+        let mirrored =
+            SymmetryConstraint::new_2d(Symmetry2D::MirrorX, 4, 1, &possible, &transforms).unwrap();
+        let ties: Vec<_> = mirrored
+            .ties
+            .iter()
+            .map(|(position, image, _)| (position.x, image.x))
+            .collect();
+        // the axis runs through the middle of the third tile, the first one has no image
+        assert_eq!(ties, vec![(1, 3), (2, 2), (3, 1)]);
+
+        let rotated =
+            SymmetryConstraint::new_2d(Symmetry2D::Rotation90, 2, 2, &possible, &transforms)
+                .unwrap();
+        let quarter_turn: Vec<_> = rotated
+            .ties
+            .iter()
+            .filter(|(_, _, transform)| *transform == 0)
+            .map(|(position, image, _)| ((position.x, position.y), (image.x, image.y)))
+            .collect();
+        assert_eq!(quarter_turn, vec![((1, 0), (0, 1)), ((1, 1), (1, 1))]);
+    }
+}